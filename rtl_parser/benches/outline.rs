@@ -0,0 +1,49 @@
+use std::time::{Duration, Instant};
+
+use rtl_parser::{parse, parse_outline, RtlResult};
+
+const FUNCTIONS: usize = 2_000;
+const STMTS: usize = 40;
+const RUNS: usize = 10;
+
+// A program whose functions have long bodies, the case an outline can skip
+fn program() -> String {
+    let mut src = String::from("struct Point { Int x, Int y }\n");
+    for i in 0..FUNCTIONS {
+        src.push_str(&format!("f F{i}(Int x, Point p) -> Int {{\n"));
+        src.push_str("    var Int mut z = x;\n");
+        for j in 0..STMTS {
+            src.push_str(&format!("    z = (z * p.x + {j}) % (p.y + 7);\n"));
+        }
+        src.push_str("    return z;\n}\n");
+    }
+    src
+}
+
+// The median time of `RUNS` calls to `run`
+fn median(mut run: impl FnMut() -> RtlResult<()>) -> RtlResult<Duration> {
+    let mut times = Vec::with_capacity(RUNS);
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        run()?;
+        times.push(start.elapsed());
+    }
+    times.sort();
+    Ok(times[RUNS / 2])
+}
+
+fn main() -> RtlResult<()> {
+    let src = program();
+    println!("outlining {} bytes, median of {} runs", src.len(), RUNS);
+
+    let full = median(|| parse(&src).map(drop))?;
+    println!("parse:         {:?}", full);
+
+    let outline = median(|| parse_outline(&src).map(drop))?;
+    println!("parse_outline: {:?}", outline);
+    println!(
+        "speedup:       {:.1}x",
+        full.as_secs_f64() / outline.as_secs_f64()
+    );
+    Ok(())
+}
@@ -0,0 +1,69 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+use rtl_parser::{parse, RtlResult};
+
+// The system allocator, counting every allocation and reallocation
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+const FUNCTIONS: usize = 20_000;
+const RUNS: usize = 10;
+
+// A large program of many small functions
+fn program() -> String {
+    let mut src = String::from("struct Point { Int x, Int y }\n");
+    for i in 0..FUNCTIONS {
+        src.push_str(&format!(
+            "f F{i}(Int x, Int y = {i}) -> Int {{ var Int z = x * y + {i}; z = z % 7; return z; }}\n"
+        ));
+    }
+    src
+}
+
+// The median time of `RUNS` calls to `run`, and the allocations of the last
+fn median(mut run: impl FnMut() -> RtlResult<()>) -> RtlResult<(Duration, usize)> {
+    let mut times = Vec::with_capacity(RUNS);
+    let mut allocations = 0;
+    for _ in 0..RUNS {
+        let before = ALLOCATIONS.load(Ordering::Relaxed);
+        let start = Instant::now();
+        run()?;
+        times.push(start.elapsed());
+        allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+    }
+    times.sort();
+    Ok((times[RUNS / 2], allocations))
+}
+
+fn main() -> RtlResult<()> {
+    let src = program();
+    println!("parsing {} bytes, median of {} runs", src.len(), RUNS);
+
+    let (time, allocations) = median(|| parse(&src).map(drop))?;
+    println!("parse: {:?}, {} allocations", time, allocations);
+    Ok(())
+}
@@ -0,0 +1,10 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+// Any input must parse to `Ok` or `Err`; a panic, overflow or hang is a bug
+fuzz_target!(|data: &[u8]| {
+    if let Ok(src) = std::str::from_utf8(data) {
+        let _ = rtl_parser::parse(src);
+    }
+});
@@ -0,0 +1,589 @@
+use std::cell::OnceCell;
+
+use proc_macro2::Span;
+use serde::{Deserialize, Serialize};
+use syn::{ext::IdentExt, parse::Parser, Ident, Lit, LitStr, Result};
+
+use crate::{
+    extension::parse_custom, none, OwnedArrayLen, OwnedAttr, OwnedDecl, OwnedElse, OwnedExpr,
+    OwnedFn, OwnedIdent, OwnedIf, OwnedIfLet, OwnedLit, OwnedMatchArm, OwnedPat, OwnedRattle,
+    OwnedStmt, OwnedType, OwnedVar, Rattle, RtlArrayLen, RtlAttr, RtlBody, RtlConstExpr, RtlDecl,
+    RtlDeclValue, RtlDef, RtlElse, RtlEnum, RtlEnumVariant, RtlExpr, RtlFn, RtlFnArg, RtlGen,
+    RtlIf, RtlIfLet, RtlImport, RtlMatchArm, RtlPat, RtlStatic, RtlStaticAssert, RtlStmt,
+    RtlStruct, RtlStructField, RtlTrait, RtlType, RtlTypeAlias, RtlVarExpr,
+};
+
+// The first bytes of every encoded program
+const MAGIC: &[u8; 4] = b"RTLB";
+
+// Bumped whenever the encoding changes, so bytes written by another version
+// are rejected rather than misread
+const FORMAT_VERSION: u32 = 2;
+
+// What follows the header: the program as plain data, and what the plain
+// data leaves out of each declaration, in the same order as its `decls`
+#[derive(Serialize, Deserialize)]
+struct Encoded {
+    program: OwnedRattle,
+    // Each declaration's doc comment lines, attributes and whether it's `pub`
+    meta: Vec<(Vec<String>, Vec<OwnedAttr>, bool)>,
+}
+
+impl Rattle {
+    // Encodes the program for caching, like a build tool keeping parsed
+    // programs on disk. Names are stored as strings with where they started,
+    // but `from_bytes` can't give a name its span back, since spans only
+    // come from tokenizing.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let encoded = Encoded {
+            program: self.to_owned_rattle(),
+            meta: self
+                .decls
+                .iter()
+                .map(|decl| {
+                    let docs = decl.docs.iter().map(LitStr::value).collect();
+                    let attrs = decl.attrs.iter().map(OwnedAttr::from).collect();
+                    (docs, attrs, decl.is_public)
+                })
+                .collect(),
+        };
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(FORMAT_VERSION.to_le_bytes());
+        bincode::serialize_into(&mut bytes, &encoded).expect("encoding to a Vec can't fail");
+        bytes
+    }
+
+    // Decodes a program `to_bytes` encoded, equal to the original, though
+    // every name and literal has the call-site span. Bytes from another
+    // format version are an error. A custom declaration is parsed again from
+    // its tokens, so its extension must be registered, through
+    // `RattleParser::from_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Rattle> {
+        let Some((magic, rest)) = bytes.split_first_chunk::<4>() else {
+            return Err(error("not an encoded Rattle program"));
+        };
+        let Some((version, rest)) = rest.split_first_chunk::<4>() else {
+            return Err(error("not an encoded Rattle program"));
+        };
+        if magic != MAGIC {
+            return Err(error("not an encoded Rattle program"));
+        }
+        let version = u32::from_le_bytes(*version);
+        if version != FORMAT_VERSION {
+            return Err(error(format!(
+                "encoded with format version {}, but this is version {}",
+                version, FORMAT_VERSION
+            )));
+        }
+        let encoded: Encoded = bincode::deserialize(rest)
+            .map_err(|err| error(format!("invalid encoded program: {}", err)))?;
+        if encoded.meta.len() != encoded.program.decls.len() {
+            return Err(error("invalid encoded program: declaration count mismatch"));
+        }
+
+        let imports = encoded
+            .program
+            .imports
+            .iter()
+            .map(|(path, alias)| {
+                Ok(RtlImport {
+                    path: idents(path)?,
+                    alias: alias.as_ref().map(ident).transpose()?,
+                })
+            })
+            .collect::<Result<_>>()?;
+        let decls = encoded
+            .program
+            .decls
+            .iter()
+            .zip(&encoded.meta)
+            .map(|(value, (docs, attrs, is_public))| {
+                Ok(RtlDecl {
+                    docs: docs
+                        .iter()
+                        .map(|line| LitStr::new(line, Span::call_site()))
+                        .collect(),
+                    attrs: attrs.iter().map(attr).collect::<Result<_>>()?,
+                    is_public: *is_public,
+                    value: decl(value)?,
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Rattle {
+            decls,
+            imports,
+            index: OnceCell::new(),
+        })
+    }
+}
+
+fn error(message: impl std::fmt::Display) -> syn::Error {
+    syn::Error::new(Span::call_site(), message)
+}
+
+// Rebuilds a name, checking it the quick way when it's plain ASCII and by
+// tokenizing it otherwise, since `Ident::new` panics on what isn't one
+fn ident((name, _): &OwnedIdent) -> Result<Ident> {
+    let (raw, word) = match name.strip_prefix("r#") {
+        Some(word) => (true, word),
+        None => (false, name.as_str()),
+    };
+    let mut chars = word.chars();
+    let plain = chars
+        .next()
+        .is_some_and(|c| c == '_' || c.is_ascii_alphabetic())
+        && chars.all(|c| c == '_' || c.is_ascii_alphanumeric());
+    match (plain, raw) {
+        (true, false) => Ok(Ident::new(word, Span::call_site())),
+        (true, true) => Ok(Ident::new_raw(word, Span::call_site())),
+        (false, _) => Ident::parse_any
+            .parse_str(name)
+            .map_err(|_| error(format!("invalid encoded name `{}`", name))),
+    }
+}
+
+fn idents(names: &[OwnedIdent]) -> Result<Vec<Ident>> {
+    names.iter().map(ident).collect()
+}
+
+fn constraints(constraints: &[(OwnedIdent, Vec<OwnedIdent>)]) -> Result<Vec<(Ident, Vec<Ident>)>> {
+    constraints
+        .iter()
+        .map(|(param, bounds)| Ok((ident(param)?, idents(bounds)?)))
+        .collect()
+}
+
+fn lit(lit: &OwnedLit) -> Result<Lit> {
+    syn::parse_str(&lit.text).map_err(|_| error(format!("invalid encoded literal `{}`", lit.text)))
+}
+
+fn attr(attr: &OwnedAttr) -> Result<RtlAttr> {
+    Ok(RtlAttr {
+        name: ident(&attr.name)?,
+        args: exprs(&attr.args)?,
+    })
+}
+
+fn decl(value: &OwnedDecl) -> Result<RtlDeclValue> {
+    Ok(match value {
+        OwnedDecl::Fn(f) => RtlDeclValue::RtlFn(func(f)?),
+        OwnedDecl::Const(c) => RtlDeclValue::RtlConst(const_expr(c)?),
+        OwnedDecl::Var(var) => RtlDeclValue::RtlVar(var_expr(var)?),
+        OwnedDecl::Static(st) => RtlDeclValue::RtlStatic(RtlStatic {
+            name: ident(&st.name)?,
+            ty: ty(&st.ty)?,
+            is_mut: st.is_mut,
+            data: expr(&st.data)?,
+        }),
+        OwnedDecl::Struct(st) => RtlDeclValue::RtlStruct(RtlStruct {
+            name: ident(&st.name)?,
+            generics: idents(&st.generics)?,
+            const_generics: idents(&st.const_generics)?,
+            constraints: constraints(&st.constraints)?,
+            fields: st
+                .fields
+                .iter()
+                .map(|(attrs, is_public, field_ty, name)| {
+                    Ok(RtlStructField {
+                        attrs: attrs.iter().map(attr).collect::<Result<_>>()?,
+                        is_public: *is_public,
+                        ty: ty(field_ty)?,
+                        name: ident(name)?,
+                    })
+                })
+                .collect::<Result<_>>()?,
+        }),
+        OwnedDecl::Enum { name, variants } => RtlDeclValue::RtlEnum(RtlEnum {
+            name: ident(name)?,
+            variants: variants
+                .iter()
+                .map(|(attrs, name, fields)| {
+                    Ok(RtlEnumVariant {
+                        attrs: attrs.iter().map(attr).collect::<Result<_>>()?,
+                        name: ident(name)?,
+                        fields: fields.iter().map(ty).collect::<Result<_>>()?,
+                    })
+                })
+                .collect::<Result<_>>()?,
+        }),
+        OwnedDecl::Def {
+            struct_name,
+            defs,
+            def_for,
+        } => RtlDeclValue::RtlDef(RtlDef {
+            struct_name: ident(struct_name)?,
+            defs: defs.iter().map(func).collect::<Result<_>>()?,
+            def_for: def_for.as_ref().map(ident).transpose()?,
+        }),
+        OwnedDecl::Trait { name, methods } => RtlDeclValue::RtlTrait(RtlTrait {
+            name: ident(name)?,
+            methods: methods.iter().map(func).collect::<Result<_>>()?,
+        }),
+        OwnedDecl::Gen {
+            params,
+            over,
+            methods,
+        } => RtlDeclValue::RtlGen(RtlGen {
+            params: idents(params)?,
+            over: over.iter().map(ty).collect::<Result<_>>()?,
+            methods: methods.iter().map(func).collect::<Result<_>>()?,
+        }),
+        OwnedDecl::StaticAssert { cond, msg } => RtlDeclValue::RtlStaticAssert(RtlStaticAssert {
+            cond: expr(cond)?,
+            msg: msg.as_ref().map(|msg| LitStr::new(msg, Span::call_site())),
+        }),
+        OwnedDecl::TypeAlias { name, target } => RtlDeclValue::RtlTypeAlias(RtlTypeAlias {
+            name: ident(name)?,
+            target: ty(target)?,
+        }),
+        // The extension's value isn't encoded, so its tokens are parsed again
+        OwnedDecl::Custom { keyword, tokens } => {
+            let src = format!("{} {}", keyword, tokens);
+            let custom = (|input: syn::parse::ParseStream| match parse_custom(input) {
+                Some(custom) => custom,
+                None => Err(input.error(format!(
+                    "no extension is registered for `{}` declarations",
+                    keyword
+                ))),
+            })
+            .parse_str(&src)?;
+            RtlDeclValue::Custom(custom)
+        }
+    })
+}
+
+fn func(f: &OwnedFn) -> Result<RtlFn> {
+    Ok(RtlFn {
+        name: ident(&f.name)?,
+        generics: idents(&f.generics)?,
+        const_generics: idents(&f.const_generics)?,
+        constraints: constraints(&f.constraints)?,
+        args: f
+            .args
+            .iter()
+            .map(|arg| {
+                Ok(RtlFnArg {
+                    ty: ty(&arg.ty)?,
+                    name: ident(&arg.name)?,
+                    default: arg.default.as_ref().map(expr).transpose()?,
+                })
+            })
+            .collect::<Result<_>>()?,
+        ret: ty(&f.ret)?,
+        body: f
+            .body
+            .as_deref()
+            .map(|stmts| body(stmts, None))
+            .transpose()?,
+    })
+}
+
+fn const_expr(c: &OwnedVar) -> Result<RtlConstExpr> {
+    Ok(RtlConstExpr {
+        name: ident(&c.name)?,
+        ty: ty(&c.ty)?,
+        data: expr(&c.data)?,
+    })
+}
+
+fn var_expr(var: &OwnedVar) -> Result<RtlVarExpr> {
+    Ok(RtlVarExpr {
+        name: ident(&var.name)?,
+        ty: ty(&var.ty)?,
+        is_mut: var.is_mut,
+        data: expr(&var.data)?,
+    })
+}
+
+fn ty(ty_: &OwnedType) -> Result<RtlType> {
+    let boxed = |inner: &OwnedType| Ok::<_, syn::Error>(Box::new(ty(inner)?));
+    Ok(match ty_ {
+        OwnedType::Named(name) => RtlType::Named(ident(name)?),
+        OwnedType::Path(segments) => RtlType::Path(idents(segments)?),
+        OwnedType::Array(inner) => RtlType::Array(boxed(inner)?),
+        OwnedType::FixedArray(inner, len) => {
+            let len = match len {
+                OwnedArrayLen::Lit(len) => RtlArrayLen::Lit(*len),
+                OwnedArrayLen::Param(name) => RtlArrayLen::Param(ident(name)?),
+            };
+            RtlType::FixedArray(boxed(inner)?, len)
+        }
+        OwnedType::Optional(inner) => RtlType::Optional(boxed(inner)?),
+        OwnedType::Ref { mutable, inner } => RtlType::Ref {
+            mutable: *mutable,
+            inner: boxed(inner)?,
+        },
+    })
+}
+
+fn body(stmts: &[OwnedStmt], value: Option<&OwnedExpr>) -> Result<RtlBody> {
+    Ok(RtlBody {
+        stmts: stmts.iter().map(stmt).collect::<Result<_>>()?,
+        value: value
+            .map(|value| Ok::<_, syn::Error>(Box::new(expr(value)?)))
+            .transpose()?,
+    })
+}
+
+fn exprs(exprs: &[OwnedExpr]) -> Result<Vec<RtlExpr>> {
+    exprs.iter().map(expr).collect()
+}
+
+fn expr(e: &OwnedExpr) -> Result<RtlExpr> {
+    let boxed = |e: &OwnedExpr| Ok::<_, syn::Error>(Box::new(expr(e)?));
+    Ok(match e {
+        OwnedExpr::Lit(l) => RtlExpr::Lit(lit(l)?),
+        OwnedExpr::Ident(name) => RtlExpr::Ident(ident(name)?),
+        OwnedExpr::Array(elems) => RtlExpr::Array(exprs(elems)?),
+        OwnedExpr::Block { stmts, value } => RtlExpr::Block(body(stmts, value.as_deref())?),
+        OwnedExpr::Repeat { value, count } => RtlExpr::Repeat {
+            value: boxed(value)?,
+            count: *count,
+        },
+        OwnedExpr::Field { base, field } => RtlExpr::Field {
+            base: boxed(base)?,
+            field: ident(field)?,
+        },
+        OwnedExpr::Index { base, index } => RtlExpr::Index {
+            base: boxed(base)?,
+            index: boxed(index)?,
+        },
+        OwnedExpr::Range { start, end } => RtlExpr::Range {
+            start: start.as_deref().map(boxed).transpose()?,
+            end: end.as_deref().map(boxed).transpose()?,
+        },
+        OwnedExpr::Slice { base, range } => RtlExpr::Slice {
+            base: boxed(base)?,
+            range: boxed(range)?,
+        },
+        OwnedExpr::Binary { op, lhs, rhs } => RtlExpr::Binary {
+            op: *op,
+            lhs: boxed(lhs)?,
+            rhs: boxed(rhs)?,
+        },
+        OwnedExpr::Conditional {
+            cond,
+            then,
+            else_branch,
+        } => RtlExpr::Conditional {
+            cond: boxed(cond)?,
+            then: boxed(then)?,
+            else_branch: boxed(else_branch)?,
+        },
+        OwnedExpr::StructLit {
+            name,
+            fields,
+            spread,
+        } => RtlExpr::StructLit {
+            name: ident(name)?,
+            fields: fields
+                .iter()
+                .map(|(field, value)| Ok((ident(field)?, expr(value)?)))
+                .collect::<Result<_>>()?,
+            spread: spread.as_deref().map(boxed).transpose()?,
+        },
+        OwnedExpr::Cast {
+            expr: inner,
+            ty: to,
+        } => RtlExpr::Cast {
+            expr: boxed(inner)?,
+            ty: ty(to)?,
+        },
+        OwnedExpr::Call { name, args } => RtlExpr::Call {
+            name: ident(name)?,
+            args: exprs(args)?,
+        },
+        OwnedExpr::Spread(inner) => RtlExpr::Spread(boxed(inner)?),
+        OwnedExpr::None(_) => RtlExpr::None(none(Span::call_site())),
+        OwnedExpr::Some(inner) => RtlExpr::Some(boxed(inner)?),
+        OwnedExpr::Match { scrutinee, arms } => RtlExpr::Match {
+            scrutinee: boxed(scrutinee)?,
+            arms: arms.iter().map(match_arm).collect::<Result<_>>()?,
+        },
+        OwnedExpr::Loop {
+            label,
+            stmts,
+            value,
+        } => RtlExpr::Loop {
+            label: label.as_ref().map(ident).transpose()?,
+            body: body(stmts, value.as_deref())?,
+        },
+    })
+}
+
+fn match_arm(arm: &OwnedMatchArm) -> Result<RtlMatchArm> {
+    Ok(RtlMatchArm {
+        pat: pat(&arm.pat)?,
+        bindings: arm
+            .bindings
+            .iter()
+            .map(|(name, value)| Ok((ident(name)?, expr(value)?)))
+            .collect::<Result<_>>()?,
+        guard: arm.guard.as_ref().map(expr).transpose()?,
+        body: expr(&arm.body)?,
+    })
+}
+
+fn pat(p: &OwnedPat) -> Result<RtlPat> {
+    Ok(match p {
+        OwnedPat::Wild => RtlPat::Wild,
+        OwnedPat::Lit(l) => RtlPat::Lit(lit(l)?),
+        OwnedPat::Binding(name) => RtlPat::Binding(ident(name)?),
+        OwnedPat::Struct { name, fields } => RtlPat::Struct {
+            name: ident(name)?,
+            fields: idents(fields)?,
+        },
+        OwnedPat::Variant { name, fields } => RtlPat::Variant {
+            name: ident(name)?,
+            fields: fields.iter().map(pat).collect::<Result<_>>()?,
+        },
+    })
+}
+
+fn stmt(s: &OwnedStmt) -> Result<RtlStmt> {
+    Ok(match s {
+        OwnedStmt::Var(var) => RtlStmt::Var(var_expr(var)?),
+        OwnedStmt::Const(c) => RtlStmt::Const(const_expr(c)?),
+        OwnedStmt::Assign { target, value } => RtlStmt::Assign {
+            target: expr(target)?,
+            value: expr(value)?,
+        },
+        OwnedStmt::Return(value) => RtlStmt::Return(value.as_ref().map(expr).transpose()?),
+        OwnedStmt::Expr(e) => RtlStmt::Expr(expr(e)?),
+        OwnedStmt::Fn(f) => RtlStmt::Fn(func(f)?),
+        OwnedStmt::IfLet(if_let) => RtlStmt::IfLet(if_let_stmt(if_let)?),
+        OwnedStmt::If(if_) => RtlStmt::If(if_stmt(if_)?),
+        OwnedStmt::While { cond, body: block } => RtlStmt::While {
+            cond: expr(cond)?,
+            body: body(block, None)?,
+        },
+        OwnedStmt::Defer(e) => RtlStmt::Defer(expr(e)?),
+        OwnedStmt::Break { label, value } => RtlStmt::Break {
+            label: label.as_ref().map(ident).transpose()?,
+            value: value.as_ref().map(expr).transpose()?,
+        },
+    })
+}
+
+fn if_let_stmt(if_let: &OwnedIfLet) -> Result<RtlIfLet> {
+    Ok(RtlIfLet {
+        pat: pat(&if_let.pat)?,
+        scrutinee: expr(&if_let.scrutinee)?,
+        then: body(&if_let.then, None)?,
+        else_branch: if_let.else_branch.as_ref().map(else_branch).transpose()?,
+    })
+}
+
+fn if_stmt(if_: &OwnedIf) -> Result<RtlIf> {
+    Ok(RtlIf {
+        cond: expr(&if_.cond)?,
+        then: body(&if_.then, None)?,
+        else_branch: if_.else_branch.as_ref().map(else_branch).transpose()?,
+    })
+}
+
+fn else_branch(branch: &OwnedElse) -> Result<RtlElse> {
+    Ok(match branch {
+        OwnedElse::IfLet(next) => RtlElse::IfLet(Box::new(if_let_stmt(next)?)),
+        OwnedElse::If(next) => RtlElse::If(Box::new(if_stmt(next)?)),
+        OwnedElse::Block(block) => RtlElse::Block(body(block, None)?),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse, DeclExtension, RattleParser};
+    use syn::parse::ParseStream;
+
+    const SRC: &str = r#"import std::io as sio;
+/// A point.
+@derive(Eq) pub struct Point<T> where T: Ord { @serde_rename("x") pub T x, T y }
+enum Shape { Circle(Float), Rect(Int, Int), Empty }
+pub const [Int; 3] XS = [1, -2, 3i64];
+var Int? mut maybe = none;
+static &mut Int r#type = some(0x1f);
+f Sum<T, N>([T; N] xs, Int start = 0) T where T: Add {
+    var Int mut total = start;
+    f Inner(Int y) Int { return y; }
+    if let Point { x, y } = p { total = total + x; } else if let Circle(r) = s {} else { defer close(f); }
+    if total > 1 { total = 1; } else if start == 0 { return 0; }
+    while total < 10 { total = total + 1; }
+    return { var Int a = xs[1..N].len; a } as T;
+}
+def Point { f Norm(This this) Float; } for Ord;
+trait Ord { f Cmp(This a, This b) Int; }
+gen<T> over [Int, Float] { f Twice(T x) T { return x * 2; } }
+static_assert(N > 0 && 'c' != '\n', "needs items");
+type Ids = [std::Id];
+const Int M = match v { some(x) where let y = x * 2, y > 0 => y, 1 => 2, _ => Point { ..base, x: 1 }.x, };"#;
+
+    #[test]
+    fn test_bytes_round_trip() {
+        let rattle = parse(SRC).unwrap();
+        let bytes = rattle.to_bytes();
+        let loaded = Rattle::from_bytes(&bytes).unwrap();
+        assert!(loaded == rattle);
+        assert_eq!(loaded.stable_hash(), rattle.stable_hash());
+        assert_eq!(loaded.to_string(), rattle.to_string());
+
+        let exported: Vec<_> = loaded.exported().collect();
+        assert_eq!(exported[0].docs().as_deref(), Some("A point."));
+        assert_eq!(exported[0].attrs()[0].name(), "derive");
+    }
+
+    #[test]
+    fn test_bytes_errors() {
+        let mut bytes = parse("const Int X = 1;").unwrap().to_bytes();
+        assert!(Rattle::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert_eq!(
+            Rattle::from_bytes(b"RTL").unwrap_err().to_string(),
+            "not an encoded Rattle program"
+        );
+
+        bytes[4..8].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        assert_eq!(
+            Rattle::from_bytes(&bytes).unwrap_err().to_string(),
+            format!(
+                "encoded with format version {}, but this is version {}",
+                FORMAT_VERSION + 1,
+                FORMAT_VERSION
+            )
+        );
+    }
+
+    // `unit Name;`, parsed into its name
+    struct UnitExtension;
+
+    impl DeclExtension for UnitExtension {
+        fn keyword(&self) -> &str {
+            "unit"
+        }
+
+        fn parse(&self, input: ParseStream) -> Result<crate::CustomDecl> {
+            let name: Ident = input.parse()?;
+            input.parse::<syn::Token![;]>()?;
+            Ok(crate::CustomDecl::new(name.to_string()))
+        }
+    }
+
+    #[test]
+    fn test_bytes_custom_decl() {
+        let mut parser = RattleParser::new();
+        parser.register(UnitExtension);
+        let rattle = parser.parse("unit Meter; const Int X = 1;").unwrap();
+        let bytes = rattle.to_bytes();
+
+        let err = Rattle::from_bytes(&bytes).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "no extension is registered for `unit` declarations"
+        );
+        let loaded = parser.from_bytes(&bytes).unwrap();
+        assert!(loaded == rattle);
+        let RtlDeclValue::Custom(unit) = loaded.decls()[0].value() else {
+            panic!("expected a custom declaration");
+        };
+        assert_eq!(unit.downcast_ref::<String>().unwrap(), "Meter");
+    }
+}
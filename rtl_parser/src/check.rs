@@ -0,0 +1,869 @@
+use proc_macro2::Span;
+use syn::{Error, Ident, Result};
+
+use crate::{
+    symbols, Rattle, RtlBody, RtlDeclValue, RtlElse, RtlEnum, RtlEnumVariant, RtlExpr, RtlFn,
+    RtlIf, RtlIfLet, RtlMatchArm, RtlPat, RtlStmt, RtlSymbolKind, RtlTrait, RtlType, SymbolTable,
+};
+
+// Checks that every `def X { ... } for Y` naming a declared trait `Y` defines
+// each of the trait's methods with a matching signature. A `for` naming no
+// declared trait isn't checked.
+pub fn check_defs(rattle: &Rattle) -> Result<()> {
+    let traits: Vec<&RtlTrait> = rattle
+        .decls()
+        .iter()
+        .filter_map(|decl| match decl.value() {
+            RtlDeclValue::RtlTrait(tr) => Some(tr),
+            _ => None,
+        })
+        .collect();
+
+    let mut errors: Option<Error> = None;
+    let mut report = |err: Error| match &mut errors {
+        Some(errors) => errors.combine(err),
+        None => errors = Some(err),
+    };
+    for decl in rattle.decls() {
+        let RtlDeclValue::RtlDef(def) = decl.value() else {
+            continue;
+        };
+        let Some(tr) = def
+            .def_for()
+            .and_then(|name| traits.iter().find(|tr| tr.name() == name))
+        else {
+            continue;
+        };
+        for method in tr.methods() {
+            let mut same_name = def.defs().iter().filter(|d| d.name() == method.name());
+            let Some(first) = same_name.clone().next() else {
+                report(Error::new(
+                    def.struct_name().span(),
+                    format!(
+                        "`{}` is missing method `{}` of trait `{}`",
+                        def.struct_name(),
+                        method.name(),
+                        tr.name()
+                    ),
+                ));
+                continue;
+            };
+            if !same_name.any(|d| same_signature(d, method)) {
+                report(Error::new(
+                    first.name().span(),
+                    format!(
+                        "method `{}` of `{}` doesn't match its signature in trait `{}`",
+                        method.name(),
+                        def.struct_name(),
+                        tr.name()
+                    ),
+                ));
+            }
+        }
+    }
+    errors.map_or(Ok(()), Err)
+}
+
+// Checks the names a `def X { ... } for Y` uses: `X` must be a declared
+// struct or trait, whose methods the block defines, and `Y` any declared
+// type: a struct, enum, trait or type alias. An imported name is accepted for
+// either, since what it names isn't known here. A method taking `this` must take it first, as a
+// `This`.
+pub fn check_def_names(rattle: &Rattle) -> Result<()> {
+    let table = SymbolTable::new(rattle);
+    let is_declared = |name: &Ident, kinds: &[RtlSymbolKind]| {
+        let imported = rattle
+            .imports()
+            .iter()
+            .any(|import| import.alias().or(import.path().last()) == Some(name));
+        imported
+            || table
+                .symbols()
+                .iter()
+                .any(|sym| kinds.contains(&sym.kind()) && name == sym.name())
+    };
+
+    let mut errors: Option<Error> = None;
+    let mut report = |err: Error| match &mut errors {
+        Some(errors) => errors.combine(err),
+        None => errors = Some(err),
+    };
+    for decl in rattle.decls() {
+        let RtlDeclValue::RtlDef(def) = decl.value() else {
+            continue;
+        };
+        let name = def.struct_name();
+        if !is_declared(name, &[RtlSymbolKind::Struct, RtlSymbolKind::Trait]) {
+            report(Error::new(
+                name.span(),
+                format!("`def {}` names no declared struct or trait", name),
+            ));
+        }
+        if let Some(tr) = def.def_for() {
+            let types = [
+                RtlSymbolKind::Struct,
+                RtlSymbolKind::Enum,
+                RtlSymbolKind::Trait,
+                RtlSymbolKind::Type,
+            ];
+            if !is_declared(tr, &types) {
+                report(Error::new(
+                    tr.span(),
+                    format!("`def {} for {}` names no declared type", name, tr),
+                ));
+            }
+        }
+        for method in def.defs() {
+            let this = method.args().iter().position(|arg| arg.name() == "this");
+            let Some(i) = this else {
+                continue;
+            };
+            let arg = &method.args()[i];
+            let is_this = matches!(arg.ty(), RtlType::Named(ty) if ty == "This");
+            if i != 0 || !is_this {
+                report(Error::new(
+                    arg.name().span(),
+                    format!(
+                        "`this` must be the first parameter of `{}`, as a `This`",
+                        method.name()
+                    ),
+                ));
+            }
+        }
+    }
+    errors.map_or(Ok(()), Err)
+}
+
+// Checks that every function with a body, including methods and nested
+// functions, returns on every path. A body returns when one of its statements
+// does: a `return`, an `if` or `if let` whose block and every `else` branch
+// return, or a `loop` that nothing breaks out of, which never finishes. An
+// `if` without a final `else` may skip its block, and a `while` its body, so
+// neither returns. The
+// `?:` and `match` expressions branch too, but can't hold a `return`, so
+// these are the only paths there are.
+pub fn check_returns(rattle: &Rattle) -> Result<()> {
+    let mut errors: Option<Error> = None;
+    let mut report = |err: Error| match &mut errors {
+        Some(errors) => errors.combine(err),
+        None => errors = Some(err),
+    };
+    for (_, _, item) in symbols::items(rattle) {
+        match item {
+            symbols::RtlItem::Decl(RtlDeclValue::RtlFn(func)) | symbols::RtlItem::GenFn(func) => {
+                check_fn_returns(func, &mut report)
+            }
+            symbols::RtlItem::Decl(RtlDeclValue::RtlDef(def)) => {
+                for func in def.defs() {
+                    check_fn_returns(func, &mut report);
+                }
+            }
+            _ => {}
+        }
+    }
+    errors.map_or(Ok(()), Err)
+}
+
+fn check_fn_returns(func: &RtlFn, report: &mut impl FnMut(Error)) {
+    let Some(body) = func.body() else {
+        return;
+    };
+    // A function returning `Unit` may end without a `return`
+    if !func.ret().is_unit() && !returns(body) {
+        // Point at where the path that falls through ends
+        let span = fallthrough_span(body).unwrap_or_else(|| func.name().span());
+        report(Error::new(
+            span,
+            format!("`{}` doesn't return a value on every path", func.name()),
+        ));
+    }
+    for stmt in body.stmts() {
+        if let RtlStmt::Fn(inner) = stmt {
+            check_fn_returns(inner, report);
+        }
+    }
+}
+
+// Whether every path through a body ends in a `return`, or never ends
+fn returns(body: &RtlBody) -> bool {
+    body.stmts().iter().any(|stmt| match stmt {
+        RtlStmt::Return(_) => true,
+        RtlStmt::IfLet(if_let) => if_let_returns(if_let),
+        RtlStmt::If(if_) => if_returns(if_),
+        RtlStmt::Expr(RtlExpr::Loop { label, body }) => !breaks_out(body, label.as_ref(), false),
+        _ => false,
+    })
+}
+
+// Whether a `break` in a loop's body leaves the loop: one naming its label,
+// or an unlabelled one outside any loop `nested` in it. A nested function
+// can't break out of the loop around it.
+fn breaks_out(body: &RtlBody, label: Option<&Ident>, nested: bool) -> bool {
+    let stmt_breaks = |stmt: &RtlStmt| match stmt {
+        RtlStmt::Break {
+            label: target,
+            value,
+        } => {
+            let leaves = match target {
+                Some(target) => Some(target) == label,
+                None => !nested,
+            };
+            leaves || value.iter().any(|e| expr_breaks_out(e, label, nested))
+        }
+        RtlStmt::Var(var) => expr_breaks_out(var.data(), label, nested),
+        RtlStmt::Const(c) => expr_breaks_out(c.data(), label, nested),
+        RtlStmt::Assign { target, value } => {
+            expr_breaks_out(target, label, nested) || expr_breaks_out(value, label, nested)
+        }
+        RtlStmt::Return(value) => value.iter().any(|e| expr_breaks_out(e, label, nested)),
+        RtlStmt::Expr(expr) | RtlStmt::Defer(expr) => expr_breaks_out(expr, label, nested),
+        RtlStmt::Fn(_) => false,
+        RtlStmt::IfLet(if_let) => if_let_breaks_out(if_let, label, nested),
+        RtlStmt::If(if_) => if_breaks_out(if_, label, nested),
+        // An unlabelled `break` in the body is the `while`'s own
+        RtlStmt::While { cond, body } => {
+            expr_breaks_out(cond, label, nested) || breaks_out(body, label, true)
+        }
+    };
+    body.stmts().iter().any(stmt_breaks)
+        || body
+            .value()
+            .is_some_and(|value| expr_breaks_out(value, label, nested))
+}
+
+fn if_let_breaks_out(if_let: &RtlIfLet, label: Option<&Ident>, nested: bool) -> bool {
+    expr_breaks_out(if_let.scrutinee(), label, nested)
+        || breaks_out(if_let.then(), label, nested)
+        || else_breaks_out(if_let.else_branch(), label, nested)
+}
+
+fn if_breaks_out(if_: &RtlIf, label: Option<&Ident>, nested: bool) -> bool {
+    expr_breaks_out(if_.cond(), label, nested)
+        || breaks_out(if_.then(), label, nested)
+        || else_breaks_out(if_.else_branch(), label, nested)
+}
+
+fn else_breaks_out(branch: Option<&RtlElse>, label: Option<&Ident>, nested: bool) -> bool {
+    match branch {
+        Some(RtlElse::IfLet(next)) => if_let_breaks_out(next, label, nested),
+        Some(RtlElse::If(next)) => if_breaks_out(next, label, nested),
+        Some(RtlElse::Block(block)) => breaks_out(block, label, nested),
+        None => false,
+    }
+}
+
+fn expr_breaks_out(expr: &RtlExpr, label: Option<&Ident>, nested: bool) -> bool {
+    let breaks = |expr: &RtlExpr| expr_breaks_out(expr, label, nested);
+    match expr {
+        RtlExpr::Lit(_) | RtlExpr::None(_) | RtlExpr::Ident(_) => false,
+        RtlExpr::Array(elems) | RtlExpr::Call { args: elems, .. } => elems.iter().any(breaks),
+        RtlExpr::Block(block) => breaks_out(block, label, nested),
+        // A nested loop of the same label hides it from the breaks inside
+        RtlExpr::Loop { label: inner, body } => {
+            let label = label.filter(|label| inner.as_ref() != Some(label));
+            breaks_out(body, label, true)
+        }
+        RtlExpr::Repeat { value, .. }
+        | RtlExpr::Field { base: value, .. }
+        | RtlExpr::Cast { expr: value, .. }
+        | RtlExpr::Some(value)
+        | RtlExpr::Spread(value) => breaks(value),
+        RtlExpr::Index { base, index: other }
+        | RtlExpr::Slice { base, range: other }
+        | RtlExpr::Binary {
+            lhs: base,
+            rhs: other,
+            ..
+        } => breaks(base) || breaks(other),
+        RtlExpr::Range { start, end } => start.iter().chain(end).any(|bound| breaks(bound)),
+        RtlExpr::Conditional {
+            cond,
+            then,
+            else_branch,
+        } => breaks(cond) || breaks(then) || breaks(else_branch),
+        RtlExpr::StructLit { fields, spread, .. } => {
+            fields.iter().any(|(_, value)| breaks(value)) || spread.iter().any(|base| breaks(base))
+        }
+        RtlExpr::Match { scrutinee, arms } => {
+            breaks(scrutinee)
+                || arms.iter().any(|arm| {
+                    arm.bindings().iter().any(|(_, value)| breaks(value))
+                        || arm.guard().iter().any(|guard| breaks(guard))
+                        || breaks(arm.body())
+                })
+        }
+    }
+}
+
+fn if_let_returns(if_let: &RtlIfLet) -> bool {
+    returns(if_let.then()) && else_returns(if_let.else_branch())
+}
+
+fn if_returns(if_: &RtlIf) -> bool {
+    returns(if_.then()) && else_returns(if_.else_branch())
+}
+
+fn else_returns(branch: Option<&RtlElse>) -> bool {
+    match branch {
+        Some(RtlElse::IfLet(next)) => if_let_returns(next),
+        Some(RtlElse::If(next)) => if_returns(next),
+        Some(RtlElse::Block(block)) => returns(block),
+        None => false,
+    }
+}
+
+// The last statement on a path through a body that doesn't return, following
+// `if` and `if let` branches, or `None` for an empty body
+fn fallthrough_span(body: &RtlBody) -> Option<Span> {
+    match body.stmts().last()? {
+        RtlStmt::IfLet(if_let) => Some(if_let_fallthrough_span(if_let)),
+        RtlStmt::If(if_) => Some(if_fallthrough_span(if_)),
+        RtlStmt::While { cond, .. } => Some(cond.span()),
+        RtlStmt::Var(var) => Some(var.name().span()),
+        RtlStmt::Const(c) => Some(c.name().span()),
+        RtlStmt::Assign { target, .. } => Some(target.span()),
+        RtlStmt::Return(Some(expr)) | RtlStmt::Expr(expr) | RtlStmt::Defer(expr) => {
+            Some(expr.span())
+        }
+        RtlStmt::Return(None) => None,
+        RtlStmt::Fn(func) => Some(func.name().span()),
+        RtlStmt::Break { label, value } => label
+            .as_ref()
+            .map(Ident::span)
+            .or_else(|| value.as_ref().map(RtlExpr::span)),
+    }
+}
+
+fn if_let_fallthrough_span(if_let: &RtlIfLet) -> Span {
+    let span = if_let.scrutinee().span();
+    if !returns(if_let.then()) {
+        return fallthrough_span(if_let.then()).unwrap_or(span);
+    }
+    // Without an `else`, the path where the pattern doesn't match
+    else_fallthrough_span(if_let.else_branch()).unwrap_or(span)
+}
+
+fn if_fallthrough_span(if_: &RtlIf) -> Span {
+    let span = if_.cond().span();
+    if !returns(if_.then()) {
+        return fallthrough_span(if_.then()).unwrap_or(span);
+    }
+    // Without an `else`, the path where the condition doesn't hold
+    else_fallthrough_span(if_.else_branch()).unwrap_or(span)
+}
+
+fn else_fallthrough_span(branch: Option<&RtlElse>) -> Option<Span> {
+    match branch? {
+        RtlElse::IfLet(next) => Some(if_let_fallthrough_span(next)),
+        RtlElse::If(next) => Some(if_fallthrough_span(next)),
+        RtlElse::Block(block) => fallthrough_span(block),
+    }
+}
+
+impl Rattle {
+    // Checks that no function, method or nested function takes more than
+    // `max` parameters, for backends that limit how many a call can pass.
+    // Bodiless declarations count too, since they may be extern.
+    pub fn check_arity(&self, max: usize) -> Result<()> {
+        let mut errors: Option<Error> = None;
+        let mut report = |err: Error| match &mut errors {
+            Some(errors) => errors.combine(err),
+            None => errors = Some(err),
+        };
+        for (_, _, item) in symbols::items(self) {
+            let funcs = match item {
+                symbols::RtlItem::Decl(RtlDeclValue::RtlFn(func))
+                | symbols::RtlItem::GenFn(func) => std::slice::from_ref(func),
+                symbols::RtlItem::Decl(RtlDeclValue::RtlDef(def)) => def.defs(),
+                symbols::RtlItem::Decl(RtlDeclValue::RtlTrait(tr)) => tr.methods(),
+                _ => continue,
+            };
+            for func in funcs {
+                check_fn_arity(func, max, &mut report);
+            }
+        }
+        errors.map_or(Ok(()), Err)
+    }
+}
+
+fn check_fn_arity(func: &RtlFn, max: usize, report: &mut impl FnMut(Error)) {
+    if func.args().len() > max {
+        report(Error::new(
+            func.name().span(),
+            format!(
+                "`{}` takes {} parameters, more than the limit of {}",
+                func.name(),
+                func.args().len(),
+                max
+            ),
+        ));
+    }
+    for stmt in func.body().map_or(&[][..], RtlBody::stmts) {
+        if let RtlStmt::Fn(inner) = stmt {
+            check_fn_arity(inner, max, report);
+        }
+    }
+}
+
+// Checks every `match` whose arms name an enum's variants: each variant
+// pattern must give a pattern for each of the variant's payload fields, and
+// the arms must cover every variant of the enum, unless an arm without a
+// guard matches anything. A name alone, like `Empty`, is a variant pattern
+// when an enum declares a variant of that name, and a binding otherwise.
+pub fn check_matches(rattle: &Rattle) -> Result<()> {
+    let mut errors: Option<Error> = None;
+    let mut report = |err: Error| match &mut errors {
+        Some(errors) => errors.combine(err),
+        None => errors = Some(err),
+    };
+    let mut found = Vec::new();
+    for (_, _, item) in symbols::items(rattle) {
+        let funcs = match item {
+            symbols::RtlItem::Decl(RtlDeclValue::RtlFn(func)) | symbols::RtlItem::GenFn(func) => {
+                std::slice::from_ref(func)
+            }
+            symbols::RtlItem::Decl(RtlDeclValue::RtlDef(def)) => def.defs(),
+            symbols::RtlItem::Decl(RtlDeclValue::RtlTrait(tr)) => tr.methods(),
+            symbols::RtlItem::Decl(RtlDeclValue::RtlConst(c)) => {
+                expr_matches(c.data(), &mut found);
+                continue;
+            }
+            symbols::RtlItem::Decl(RtlDeclValue::RtlVar(var)) => {
+                expr_matches(var.data(), &mut found);
+                continue;
+            }
+            symbols::RtlItem::Decl(RtlDeclValue::RtlStatic(st)) => {
+                expr_matches(&st.data, &mut found);
+                continue;
+            }
+            symbols::RtlItem::Decl(RtlDeclValue::RtlStaticAssert(assert)) => {
+                expr_matches(assert.cond(), &mut found);
+                continue;
+            }
+            _ => continue,
+        };
+        funcs.iter().for_each(|func| fn_matches(func, &mut found));
+    }
+
+    for (scrutinee, arms) in found {
+        let mut matched = None;
+        let mut covered = Vec::new();
+        let mut catch_all = false;
+        for arm in arms {
+            let Some((name, (en, variant), given)) = variant_pat(rattle, arm.pat()) else {
+                catch_all |=
+                    arm.guard().is_none() && matches!(arm.pat(), RtlPat::Wild | RtlPat::Binding(_));
+                continue;
+            };
+            if given != variant.fields().len() {
+                report(Error::new(
+                    name.span(),
+                    format!(
+                        "variant `{}` has {} payload fields, but the pattern matches {}",
+                        name,
+                        variant.fields().len(),
+                        given
+                    ),
+                ));
+            }
+            matched.get_or_insert(en);
+            let irrefutable = match arm.pat() {
+                RtlPat::Variant { fields, .. } => fields.iter().all(|pat| {
+                    variant_pat(rattle, pat).is_none()
+                        && matches!(pat, RtlPat::Wild | RtlPat::Binding(_))
+                }),
+                _ => true,
+            };
+            if arm.guard().is_none() && irrefutable {
+                covered.push(variant.name());
+            }
+        }
+        let Some(en) = matched.filter(|_| !catch_all) else {
+            continue;
+        };
+        let missing: Vec<String> = en
+            .variants()
+            .iter()
+            .filter(|variant| !covered.contains(&variant.name()))
+            .map(|variant| format!("`{}`", variant.name()))
+            .collect();
+        if !missing.is_empty() {
+            report(Error::new(
+                scrutinee.span(),
+                format!(
+                    "`match` on enum `{}` doesn't cover {}",
+                    en.name(),
+                    missing.join(", ")
+                ),
+            ));
+        }
+    }
+    errors.map_or(Ok(()), Err)
+}
+
+// The variant a pattern names, with the enum declaring it and how many
+// payload fields the pattern matches, or `None` for other patterns
+fn variant_pat<'a, 'p>(
+    rattle: &'a Rattle,
+    pat: &'p RtlPat,
+) -> Option<(&'p Ident, (&'a RtlEnum, &'a RtlEnumVariant), usize)> {
+    let (name, given) = match pat {
+        RtlPat::Variant { name, fields } => (name, fields.len()),
+        RtlPat::Binding(name) => (name, 0),
+        RtlPat::Wild | RtlPat::Lit(_) | RtlPat::Struct { .. } => return None,
+    };
+    let variant = rattle.find_variant(&name.to_string())?;
+    Some((name, variant, given))
+}
+
+type MatchExpr<'a> = (&'a RtlExpr, &'a [RtlMatchArm]);
+
+fn fn_matches<'a>(func: &'a RtlFn, found: &mut Vec<MatchExpr<'a>>) {
+    for arg in func.args() {
+        if let Some(default) = arg.default() {
+            expr_matches(default, found);
+        }
+    }
+    if let Some(body) = func.body() {
+        body_matches(body, found);
+    }
+}
+
+fn body_matches<'a>(body: &'a RtlBody, found: &mut Vec<MatchExpr<'a>>) {
+    for stmt in body.stmts() {
+        match stmt {
+            RtlStmt::Var(var) => expr_matches(var.data(), found),
+            RtlStmt::Const(c) => expr_matches(c.data(), found),
+            RtlStmt::Assign { target, value } => {
+                expr_matches(target, found);
+                expr_matches(value, found);
+            }
+            RtlStmt::Return(value) | RtlStmt::Break { value, .. } => {
+                value.iter().for_each(|e| expr_matches(e, found))
+            }
+            RtlStmt::Expr(expr) | RtlStmt::Defer(expr) => expr_matches(expr, found),
+            RtlStmt::Fn(func) => fn_matches(func, found),
+            RtlStmt::IfLet(if_let) => if_let_matches(if_let, found),
+            RtlStmt::If(if_) => if_matches(if_, found),
+            RtlStmt::While { cond, body } => {
+                expr_matches(cond, found);
+                body_matches(body, found);
+            }
+        }
+    }
+    if let Some(value) = body.value() {
+        expr_matches(value, found);
+    }
+}
+
+fn if_let_matches<'a>(if_let: &'a RtlIfLet, found: &mut Vec<MatchExpr<'a>>) {
+    expr_matches(if_let.scrutinee(), found);
+    body_matches(if_let.then(), found);
+    else_matches(if_let.else_branch(), found);
+}
+
+fn if_matches<'a>(if_: &'a RtlIf, found: &mut Vec<MatchExpr<'a>>) {
+    expr_matches(if_.cond(), found);
+    body_matches(if_.then(), found);
+    else_matches(if_.else_branch(), found);
+}
+
+fn else_matches<'a>(branch: Option<&'a RtlElse>, found: &mut Vec<MatchExpr<'a>>) {
+    match branch {
+        Some(RtlElse::IfLet(next)) => if_let_matches(next, found),
+        Some(RtlElse::If(next)) => if_matches(next, found),
+        Some(RtlElse::Block(block)) => body_matches(block, found),
+        None => {}
+    }
+}
+
+fn expr_matches<'a>(expr: &'a RtlExpr, found: &mut Vec<MatchExpr<'a>>) {
+    match expr {
+        RtlExpr::Lit(_) | RtlExpr::None(_) | RtlExpr::Ident(_) => {}
+        RtlExpr::Array(elems) | RtlExpr::Call { args: elems, .. } => {
+            elems.iter().for_each(|e| expr_matches(e, found))
+        }
+        RtlExpr::Block(block) | RtlExpr::Loop { body: block, .. } => body_matches(block, found),
+        RtlExpr::Repeat { value, .. }
+        | RtlExpr::Field { base: value, .. }
+        | RtlExpr::Cast { expr: value, .. }
+        | RtlExpr::Some(value)
+        | RtlExpr::Spread(value) => expr_matches(value, found),
+        RtlExpr::Index { base, index: other }
+        | RtlExpr::Slice { base, range: other }
+        | RtlExpr::Binary {
+            lhs: base,
+            rhs: other,
+            ..
+        } => {
+            expr_matches(base, found);
+            expr_matches(other, found);
+        }
+        RtlExpr::Range { start, end } => start
+            .iter()
+            .chain(end)
+            .for_each(|bound| expr_matches(bound, found)),
+        RtlExpr::Conditional {
+            cond,
+            then,
+            else_branch,
+        } => {
+            expr_matches(cond, found);
+            expr_matches(then, found);
+            expr_matches(else_branch, found);
+        }
+        RtlExpr::StructLit { fields, spread, .. } => {
+            fields
+                .iter()
+                .for_each(|(_, value)| expr_matches(value, found));
+            spread.iter().for_each(|base| expr_matches(base, found));
+        }
+        RtlExpr::Match { scrutinee, arms } => {
+            expr_matches(scrutinee, found);
+            found.push((scrutinee, arms));
+            for arm in arms {
+                arm.bindings()
+                    .iter()
+                    .for_each(|(_, value)| expr_matches(value, found));
+                arm.guard()
+                    .iter()
+                    .for_each(|guard| expr_matches(guard, found));
+                expr_matches(arm.body(), found);
+            }
+        }
+    }
+}
+
+// Whether two functions take the same argument types and return the same type
+fn same_signature(a: &RtlFn, b: &RtlFn) -> bool {
+    a.ret() == b.ret()
+        && a.args().len() == b.args().len()
+        && a.args().iter().zip(b.args()).all(|(x, y)| x.ty() == y.ty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+    use proc_macro2::LineColumn;
+
+    const GREETER: &str = "trait Greeter { f Greet(This this) String; f Age(This this) Int; }";
+
+    fn check(def: &str) -> Result<()> {
+        check_defs(&parse(&format!("{} {}", GREETER, def)).unwrap())
+    }
+
+    #[test]
+    fn test_def_implements_trait() {
+        let def = "def Person { f Greet(This this) String; f Age(This self_) Int; } for Greeter;";
+        assert!(check(def).is_ok());
+        // Traits that were never declared aren't checked
+        assert!(check("def Person { f Greet(This this) Int; } for SuperHuman;").is_ok());
+    }
+
+    #[test]
+    fn test_check_def_names() {
+        let check = |src: &str| check_def_names(&parse(src).unwrap());
+        let person = "struct Person { String name, Int age }
+            def Person {
+                f From(String raw) This;
+                f Greet(This this) String;
+            }";
+        assert!(check(&format!("{} {}", person, "trait SuperHuman { }")).is_ok());
+        assert!(check(&format!(
+            "{} for SuperHuman; trait SuperHuman {{ }}",
+            person
+        ))
+        .is_ok());
+        assert!(check(&format!(
+            "import ::powers::SuperHuman; {} for SuperHuman;",
+            person
+        ))
+        .is_ok());
+
+        // `Y` may be any kind of type, and `X` a trait as well as a struct
+        for ty in [
+            "struct SuperHuman { }",
+            "enum SuperHuman { A }",
+            "type SuperHuman = Int;",
+        ] {
+            assert!(check(&format!("{} for SuperHuman; {}", person, ty)).is_ok());
+        }
+        assert!(check("trait Greeter { } struct P { } def Greeter { } for P;").is_ok());
+
+        let err = check(&format!("{} for SuperHuman;", person)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "`def Person for SuperHuman` names no declared type"
+        );
+        // Neither an enum nor a function has methods of its own
+        let err = check("enum Person { A } f Greeter() Int { 1 } def Person { } for Greeter;")
+            .unwrap_err();
+        let errs: Vec<_> = err.into_iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            errs,
+            [
+                "`def Person` names no declared struct or trait",
+                "`def Person for Greeter` names no declared type"
+            ]
+        );
+
+        let err = check("struct P { } def P { f A(Int x, This this) Int; f B(Int this) Int; }")
+            .unwrap_err();
+        let errs: Vec<_> = err.into_iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            errs,
+            [
+                "`this` must be the first parameter of `A`, as a `This`",
+                "`this` must be the first parameter of `B`, as a `This`"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_def_missing_method() {
+        let err = check("def Person { f Greet(This this) String; } for Greeter;").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "`Person` is missing method `Age` of trait `Greeter`"
+        );
+    }
+
+    #[test]
+    fn test_check_returns() {
+        let check = |src: &str| check_returns(&parse(src).unwrap());
+        let ok = "f Both(Int? x) Int { if let some(v) = x { return v; } else { return 0; } }
+                  f Chain(Int? x) Int {
+                      if let some(1) = x { return 1; } else if let some(v) = x { return v; } else { return 0; }
+                  }
+                  f Late(Int? x) Int { if let some(v) = x { var Int y = v; } return 0; }
+                  f Pick(Bool b) Int { if b { return 1; } else { return 0; } }
+                  f Sign(Int x) Int {
+                      if x > 0 { return 1; } else if let 0 = x { return 0; } else { return -1; }
+                  }
+                  f Tail(Int x) Int { x + 1 }
+                  f Decl(Int x) Int;
+                  f Log(String s) { print(s); }
+                  f Forever() Int { loop { Tick(); }; }
+                  f Spin() Int { 'outer: loop { loop { break; } }; }
+                  f Wait(Bool b) Int { loop { while b { break; } }; }";
+        assert!(check(ok).is_ok());
+
+        // A loop that may be broken out of can fall through. A trailing loop
+        // is the body's value instead, like any other trailing expression.
+        let err = check("f F() Int { loop { break; }; }").unwrap_err();
+        assert_eq!(err.to_string(), "`F` doesn't return a value on every path");
+        for src in [
+            "f F() Int { 'a: loop { loop { break 'a; } }; }",
+            "f F() Int { loop { var Int x = { break; }; }; }",
+            "f F(Int? v) Int { loop { if let some(x) = v { break; } }; }",
+            "f F(Bool b) Int { loop { if b { } else { break; } }; }",
+            "f F(Bool b) Int { while b { return 1; } }",
+        ] {
+            assert!(check(src).is_err(), "{}", src);
+        }
+
+        // The error points at the `if let` whose pattern may not match
+        let err = check("f F(Int? x) Int {\n    if let some(v) = x { return v; }\n}").unwrap_err();
+        assert_eq!(err.to_string(), "`F` doesn't return a value on every path");
+        assert_eq!(err.span().start().line, 2);
+        assert_eq!(err.span().start().column, 21);
+
+        // Or the `if` whose condition may not hold
+        let err = check("f F(Bool b) Int {\n    if b { return 1; }\n}").unwrap_err();
+        assert_eq!(err.to_string(), "`F` doesn't return a value on every path");
+        assert_eq!(err.span().start(), LineColumn { line: 2, column: 7 });
+        let err = check("f F(Bool b) Int { if b { return 1; } else if b == false { return 0; } }");
+        assert!(err.is_err());
+
+        // Or at the last statement of the branch that falls through
+        let src = "def P { f G(Int? x) Int {
+                       if let some(v) = x { return v; } else { var Int y = 0; }
+                   } }";
+        let err = check(src).unwrap_err();
+        assert!(err.to_string().starts_with("`G`"));
+        let column = src.lines().nth(1).unwrap().find("y = 0").unwrap();
+        assert_eq!(err.span().start(), LineColumn { line: 2, column });
+
+        let errs: Vec<_> = check("f A() Int { } f B() Int { f C() Int { } return 1; }")
+            .unwrap_err()
+            .into_iter()
+            .collect();
+        assert_eq!(errs.len(), 2);
+        assert!(errs[1].to_string().starts_with("`C`"));
+    }
+
+    #[test]
+    fn test_check_arity() {
+        let args: Vec<String> = (0..10).map(|i| format!("Int a{}", i)).collect();
+        let src = format!(
+            "f Wide({args}) Int;
+             f Narrow(Int a) Int {{ f Inner({args}) Int {{ return a0; }} return a; }}
+             trait T {{ f M(This this, {args}) Int; }}",
+            args = args.join(", ")
+        );
+        let rattle = parse(&src).unwrap();
+        assert!(rattle.check_arity(11).is_ok());
+
+        let err = rattle.check_arity(8).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "`Wide` takes 10 parameters, more than the limit of 8"
+        );
+        assert_eq!(err.span().start(), LineColumn { line: 1, column: 2 });
+        let errs: Vec<_> = err.into_iter().map(|e| e.to_string()).collect();
+        assert_eq!(errs.len(), 3);
+        assert!(errs[1].starts_with("`Inner` takes 10"));
+        assert!(errs[2].starts_with("`M` takes 11"));
+    }
+
+    #[test]
+    fn test_check_matches() {
+        let check = |body: &str| {
+            let src = format!(
+                "enum Shape {{ Circle(Float), Rect(Float, Float), Empty }}
+                 f Area(Shape s) Float {{ return {}; }}",
+                body
+            );
+            check_matches(&parse(&src).unwrap())
+        };
+        assert!(check("match s { Circle(r) => r * r, Rect(w, h) => w * h, Empty => 0.0 }").is_ok());
+        assert!(check("match s { Circle(r) => r * r, _ => 0.0 }").is_ok());
+        assert!(check("match s { Circle(r) => r, other => 0.0 }").is_ok());
+
+        let err = check("match s { Circle(r) => r * r, Empty => 0.0 }").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "`match` on enum `Shape` doesn't cover `Rect`"
+        );
+        // A guarded arm may not match, so its variant isn't covered
+        let err = check("match s { Circle(r) where r > 1.0 => r, Rect(w, h) => w, Empty => 0.0 }")
+            .unwrap_err();
+        assert!(err.to_string().ends_with("doesn't cover `Circle`"));
+        let err = check("match s { Circle(1.0) => 1.0, Rect(_, _) => 0.0 }").unwrap_err();
+        assert!(err.to_string().ends_with("doesn't cover `Circle`, `Empty`"));
+
+        let errs: Vec<_> = check("match s { Circle(r, x) => r, Rect(w) => w, Empty(e) => 0.0 }")
+            .unwrap_err()
+            .into_iter()
+            .map(|e| e.to_string())
+            .collect();
+        assert_eq!(
+            errs,
+            [
+                "variant `Circle` has 1 payload fields, but the pattern matches 2",
+                "variant `Rect` has 2 payload fields, but the pattern matches 1",
+                "variant `Empty` has 0 payload fields, but the pattern matches 1",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_def_signature_mismatch() {
+        let def =
+            "def Person { f Greet(This this, Int n) String; f Age(This this) Float; } for Greeter;";
+        let errs: Vec<_> = check(def).unwrap_err().into_iter().collect();
+        assert_eq!(errs.len(), 2);
+        assert!(errs[1].to_string().contains("method `Age`"));
+    }
+}
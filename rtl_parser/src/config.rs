@@ -0,0 +1,242 @@
+use std::{cell::RefCell, collections::HashMap, fmt, mem};
+
+use proc_macro2::Span;
+use syn::{
+    ext::IdentExt,
+    parse::{ParseStream, Parser},
+    Error, Ident, Result,
+};
+
+// The keywords an embedder may spell differently, like `fn` for `f`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RtlKeyword {
+    Fn,
+    Var,
+    Def,
+    Gen,
+    Import,
+}
+
+impl RtlKeyword {
+    pub const ALL: [RtlKeyword; 5] = [
+        RtlKeyword::Fn,
+        RtlKeyword::Var,
+        RtlKeyword::Def,
+        RtlKeyword::Gen,
+        RtlKeyword::Import,
+    ];
+
+    // The keyword's default spelling
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RtlKeyword::Fn => "f",
+            RtlKeyword::Var => "var",
+            RtlKeyword::Def => "def",
+            RtlKeyword::Gen => "gen",
+            RtlKeyword::Import => "import",
+        }
+    }
+}
+
+impl fmt::Display for RtlKeyword {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+// Words that begin a declaration or statement whatever the config, so no
+// keyword can be spelled as one
+const RESERVED: &[&str] = &[
+    "const",
+    "static",
+    "struct",
+    "enum",
+    "trait",
+    "type",
+    "pub",
+    "static_assert",
+    "if",
+    "while",
+    "return",
+    "defer",
+    "break",
+    "loop",
+    "match",
+    "some",
+    "none",
+    "true",
+    "false",
+];
+
+// How a `RattleParser` spells the keywords of `RtlKeyword`. A keyword spelled
+// differently loses its default spelling, which becomes a plain name.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ParseConfig {
+    spellings: HashMap<RtlKeyword, String>,
+}
+
+impl ParseConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Spells `keyword` as `spelling`, which must be an identifier no other
+    // keyword is spelled as
+    pub fn rename(&mut self, keyword: RtlKeyword, spelling: &str) -> Result<&mut Self> {
+        let ident = Ident::parse_any.parse_str(spelling).map_err(|_| {
+            Error::new(
+                Span::call_site(),
+                format!("`{}` isn't an identifier", spelling),
+            )
+        })?;
+        let spelling = ident.to_string();
+        if RESERVED.contains(&spelling.as_str()) {
+            return Err(Error::new(
+                Span::call_site(),
+                format!("`{}` is already a keyword", spelling),
+            ));
+        }
+        if let Some(other) = RtlKeyword::ALL
+            .into_iter()
+            .find(|&other| other != keyword && self.spelling(other) == spelling)
+        {
+            return Err(Error::new(
+                Span::call_site(),
+                format!("`{}` already spells the `{}` keyword", spelling, other),
+            ));
+        }
+        self.spellings.insert(keyword, spelling);
+        Ok(self)
+    }
+
+    pub fn spelling(&self, keyword: RtlKeyword) -> &str {
+        self.spellings
+            .get(&keyword)
+            .map_or(keyword.as_str(), String::as_str)
+    }
+}
+
+thread_local! {
+    // The config of the `RattleParser` currently parsing on this thread
+    static CONFIG: RefCell<ParseConfig> = RefCell::new(ParseConfig::default());
+}
+
+// Puts the thread's outer config back when dropped, so a panic in `f` can't
+// leave another parser's config behind for the next parse
+struct RestoreConfig(ParseConfig);
+
+impl Drop for RestoreConfig {
+    fn drop(&mut self) {
+        CONFIG.with(|c| c.replace(mem::take(&mut self.0)));
+    }
+}
+
+// Runs `f` with `config` as the thread's
+pub(crate) fn with_config<T>(config: &ParseConfig, f: impl FnOnce() -> T) -> T {
+    let _restore = RestoreConfig(CONFIG.with(|c| c.replace(config.clone())));
+    f()
+}
+
+// The keyword's spelling on this thread
+pub(crate) fn spelling(keyword: RtlKeyword) -> String {
+    CONFIG.with(|c| c.borrow().spelling(keyword).to_string())
+}
+
+// Whether the keyword is next, as spelled on this thread
+pub(crate) fn peek_keyword(input: ParseStream, keyword: RtlKeyword) -> bool {
+    input
+        .cursor()
+        .ident()
+        .is_some_and(|(next, _)| CONFIG.with(|c| next == c.borrow().spelling(keyword)))
+}
+
+pub(crate) fn parse_keyword(input: ParseStream, keyword: RtlKeyword) -> Result<Ident> {
+    if !peek_keyword(input, keyword) {
+        return Err(input.error(format!("expected `{}`", spelling(keyword))));
+    }
+    Ident::parse_any(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse, RattleParser, RtlDeclValue, RtlStmt};
+
+    fn renamed() -> RattleParser {
+        let mut config = ParseConfig::new();
+        config
+            .rename(RtlKeyword::Fn, "fn")
+            .unwrap()
+            .rename(RtlKeyword::Var, "let")
+            .unwrap();
+        let mut parser = RattleParser::new();
+        parser.set_config(config);
+        parser
+    }
+
+    #[test]
+    fn test_renamed_keywords() {
+        let parser = renamed();
+        let rattle = parser
+            .parse(
+                "fn Add(Int x, Int f) Int {
+                    let Int y = x + f;
+                    if let some(z) = Find(y) { return z; }
+                    return y;
+                }",
+            )
+            .unwrap();
+        let RtlDeclValue::RtlFn(func) = rattle.decls()[0].value() else {
+            panic!("expected a function");
+        };
+        assert_eq!(func.name().to_string(), "Add");
+        // `f` is a plain name now, and `if let` keeps its `let`
+        assert_eq!(func.args()[1].name().to_string(), "f");
+        assert!(matches!(
+            func.body().unwrap().stmts(),
+            [RtlStmt::Var(_), RtlStmt::IfLet(_), RtlStmt::Return(_)]
+        ));
+        // The program is the same as one in the default spelling
+        let default = parse(
+            "f Add(Int x, Int f) Int {
+                var Int y = x + f;
+                if let some(z) = Find(y) { return z; }
+                return y;
+            }",
+        )
+        .unwrap();
+        assert!(rattle == default);
+
+        let err = parser.parse("f Add(Int x, Int y) Int;").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "expected one of: `fn`, `let`, `def`, `gen`, `const`, `static`, `struct`, \
+             `enum`, `trait`, `static_assert`, `type`"
+        );
+        // Parsing on its own still uses the default spellings
+        assert!(parse("fn Add(Int x, Int y) Int;").is_err());
+        assert!(parse("f Add(Int x, Int y) Int;").is_ok());
+
+        // Even after a panic while the renamed config was the thread's
+        let mut config = ParseConfig::new();
+        config.rename(RtlKeyword::Fn, "fn").unwrap();
+        let panicked = std::panic::catch_unwind(|| with_config(&config, || panic!("in a parse")));
+        assert!(panicked.is_err());
+        assert_eq!(spelling(RtlKeyword::Fn), "f");
+    }
+
+    #[test]
+    fn test_rename_errors() {
+        let mut config = ParseConfig::new();
+        let err = config.rename(RtlKeyword::Def, "struct").unwrap_err();
+        assert_eq!(err.to_string(), "`struct` is already a keyword");
+        let err = config.rename(RtlKeyword::Gen, "var").unwrap_err();
+        assert_eq!(err.to_string(), "`var` already spells the `var` keyword");
+        let err = config.rename(RtlKeyword::Gen, "1x").unwrap_err();
+        assert_eq!(err.to_string(), "`1x` isn't an identifier");
+        // Renaming back to the default is fine
+        config.rename(RtlKeyword::Fn, "fn").unwrap();
+        config.rename(RtlKeyword::Fn, "f").unwrap();
+        assert_eq!(config.spelling(RtlKeyword::Fn), "f");
+    }
+}
@@ -0,0 +1,114 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::symbols::{items, RtlItem};
+use crate::{Rattle, RtlSymbolKind};
+
+// A change to one top-level declaration between two versions of a program
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RtlChange {
+    Added { kind: RtlSymbolKind, name: String },
+    Removed { kind: RtlSymbolKind, name: String },
+    // Anything about the declaration differs, its signature or its body
+    Modified { kind: RtlSymbolKind, name: String },
+}
+
+impl RtlChange {
+    pub fn kind(&self) -> RtlSymbolKind {
+        match self {
+            RtlChange::Added { kind, .. }
+            | RtlChange::Removed { kind, .. }
+            | RtlChange::Modified { kind, .. } => *kind,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            RtlChange::Added { name, .. }
+            | RtlChange::Removed { name, .. }
+            | RtlChange::Modified { name, .. } => name,
+        }
+    }
+}
+
+// The top-level declarations added, removed or modified going from `old` to
+// `new`, keyed by kind and name so a struct and its `def` block are tracked
+// apart. Additions and modifications come in `new`'s order, then removals in
+// `old`'s. Only moving a declaration isn't a change.
+pub fn diff(old: &Rattle, new: &Rattle) -> Vec<RtlChange> {
+    let old_items = items(old);
+    let mut by_key: HashMap<(RtlSymbolKind, String), RtlItem> = HashMap::new();
+    for (kind, name, item) in &old_items {
+        by_key.entry((*kind, name.to_string())).or_insert(*item);
+    }
+
+    let mut changes = Vec::new();
+    let mut seen = HashSet::new();
+    for (kind, name, item) in items(new) {
+        let key = (kind, name.to_string());
+        if !seen.insert(key.clone()) {
+            continue;
+        }
+        match by_key.get(&key) {
+            None => changes.push(RtlChange::Added {
+                kind,
+                name: key.1.clone(),
+            }),
+            Some(old_item) if *old_item != item => changes.push(RtlChange::Modified {
+                kind,
+                name: key.1.clone(),
+            }),
+            Some(_) => {}
+        }
+    }
+    for (kind, name, _) in old_items {
+        let key = (kind, name.to_string());
+        if seen.insert(key.clone()) {
+            changes.push(RtlChange::Removed { kind, name: key.1 });
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    #[test]
+    fn test_diff() {
+        let old = parse(
+            "const Int Zero = 0;
+f Add(Int a, Int b) Int { return a + b; }
+f Neg(Int a) Int { return 0 - a; }",
+        )
+        .unwrap();
+        // `Sub` is new and `Zero` only moved down a line
+        let new = parse(
+            "f Sub(Int a, Int b) Int { return a - b; }
+const Int Zero = 0;
+f Add(Int a, Int b) Int { return a + b; }
+f Neg(Int a) Int { return 0 - a; }",
+        )
+        .unwrap();
+        assert_eq!(
+            diff(&old, &new),
+            vec![RtlChange::Added {
+                kind: RtlSymbolKind::Fn,
+                name: "Sub".to_string(),
+            }]
+        );
+        assert!(diff(&new, &new).is_empty());
+
+        // A new signature modifies `Add`, and dropping `Neg` removes it
+        let changed = parse(
+            "const Int Zero = 0;
+f Add(Int a, Int b, Int c) Int { return a + b; }",
+        )
+        .unwrap();
+        let changes = diff(&old, &changed);
+        assert_eq!(changes.len(), 2);
+        assert!(matches!(&changes[0], RtlChange::Modified { name, .. } if name == "Add"));
+        assert!(matches!(&changes[1], RtlChange::Removed { name, .. } if name == "Neg"));
+        assert_eq!(changes[1].kind(), RtlSymbolKind::Fn);
+    }
+}
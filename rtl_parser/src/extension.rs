@@ -0,0 +1,271 @@
+use std::{
+    any::Any,
+    cell::RefCell,
+    fmt,
+    hash::{Hash, Hasher},
+    mem,
+    rc::Rc,
+    str::FromStr,
+};
+
+use proc_macro2::TokenStream;
+use syn::{ext::IdentExt, parse::ParseStream, parse2, Ident, Result};
+
+use crate::{config, ParseConfig, Rattle};
+
+// A declaration form an embedder adds to the grammar, like
+// `macro Name { ... }`, without forking the parser
+pub trait DeclExtension {
+    // The keyword starting the declaration. It may be any identifier that
+    // isn't already a declaration keyword, Rust keywords like `macro` included.
+    fn keyword(&self) -> &str;
+
+    // Parses the rest of the declaration, after its keyword. Like the
+    // built-in declarations, it should take the `;` it ends with, if any.
+    fn parse(&self, input: ParseStream) -> Result<CustomDecl>;
+}
+
+// A declaration parsed by a `DeclExtension`, holding whatever value the
+// extension parsed it into. Equality and hashing compare the keyword and the
+// declaration's tokens, since the value itself can't be compared.
+pub struct CustomDecl {
+    keyword: String,
+    tokens: String,
+    value: Box<dyn Any>,
+}
+
+impl CustomDecl {
+    pub fn new(value: impl Any) -> Self {
+        CustomDecl {
+            keyword: String::new(),
+            tokens: String::new(),
+            value: Box::new(value),
+        }
+    }
+
+    pub fn keyword(&self) -> &str {
+        &self.keyword
+    }
+
+    // The declaration's tokens after its keyword, without spans or formatting
+    pub fn tokens(&self) -> &str {
+        &self.tokens
+    }
+
+    pub fn value(&self) -> &dyn Any {
+        &*self.value
+    }
+
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        self.value.downcast_ref()
+    }
+}
+
+impl fmt::Debug for CustomDecl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CustomDecl")
+            .field("keyword", &self.keyword)
+            .field("tokens", &self.tokens)
+            .finish_non_exhaustive()
+    }
+}
+
+impl PartialEq for CustomDecl {
+    fn eq(&self, other: &Self) -> bool {
+        self.keyword == other.keyword && self.tokens == other.tokens
+    }
+}
+
+impl Hash for CustomDecl {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.keyword.hash(state);
+        self.tokens.hash(state);
+    }
+}
+
+thread_local! {
+    // The extensions of the `RattleParser` currently parsing on this thread
+    static EXTENSIONS: RefCell<Vec<Rc<dyn DeclExtension>>> = const { RefCell::new(Vec::new()) };
+}
+
+// Parses programs like `parse`, also accepting the declarations of the
+// registered extensions, with the keywords spelled as its config says
+#[derive(Default)]
+pub struct RattleParser {
+    extensions: Vec<Rc<dyn DeclExtension>>,
+    config: ParseConfig,
+}
+
+impl RattleParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Registers an extension. Built-in declarations win over an extension
+    // with the same keyword, and earlier extensions over later ones.
+    pub fn register(&mut self, extension: impl DeclExtension + 'static) -> &mut Self {
+        self.extensions.push(Rc::new(extension));
+        self
+    }
+
+    pub fn set_config(&mut self, config: ParseConfig) -> &mut Self {
+        self.config = config;
+        self
+    }
+
+    pub fn config(&self) -> &ParseConfig {
+        &self.config
+    }
+
+    pub fn parse(&self, src: &str) -> Result<Rattle> {
+        let ts = TokenStream::from_str(src)?;
+        crate::check_group_depth(&ts)?;
+        self.with_extensions(|| parse2(ts))
+    }
+
+    // Like `Rattle::from_bytes`, parsing custom declarations again with the
+    // registered extensions
+    #[cfg(feature = "bincode")]
+    pub fn from_bytes(&self, bytes: &[u8]) -> Result<Rattle> {
+        self.with_extensions(|| Rattle::from_bytes(bytes))
+    }
+
+    // Runs `f` with this parser's extensions and config as the thread's
+    fn with_extensions<T>(&self, f: impl FnOnce() -> T) -> T {
+        let outer = EXTENSIONS.with(|exts| exts.replace(self.extensions.clone()));
+        let _restore = RestoreExtensions(outer);
+        config::with_config(&self.config, f)
+    }
+}
+
+// Puts the thread's outer extensions back when dropped, even by a panic
+struct RestoreExtensions(Vec<Rc<dyn DeclExtension>>);
+
+impl Drop for RestoreExtensions {
+    fn drop(&mut self) {
+        EXTENSIONS.with(|exts| exts.replace(mem::take(&mut self.0)));
+    }
+}
+
+// Parses a declaration starting with a registered extension's keyword, or
+// returns `None` when no extension's keyword is next
+pub(crate) fn parse_custom(input: ParseStream) -> Option<Result<CustomDecl>> {
+    let (next, _) = input.cursor().ident()?;
+    let keyword = next.to_string();
+    let extension = EXTENSIONS.with(|exts| {
+        exts.borrow()
+            .iter()
+            .find(|ext| ext.keyword() == keyword)
+            .cloned()
+    })?;
+    Some(parse_with(input, &*extension, keyword))
+}
+
+fn parse_with(
+    input: ParseStream,
+    extension: &dyn DeclExtension,
+    keyword: String,
+) -> Result<CustomDecl> {
+    Ident::parse_any(input)?;
+    let begin = input.cursor();
+    let mut decl = extension.parse(input)?;
+    let end = input.cursor();
+
+    let mut tokens = TokenStream::new();
+    let mut cursor = begin;
+    while cursor != end {
+        let Some((tt, next)) = cursor.token_tree() else {
+            break;
+        };
+        tokens.extend([tt]);
+        cursor = next;
+    }
+    decl.keyword = keyword;
+    decl.tokens = tokens.to_string();
+    Ok(decl)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse, RtlDeclValue, RtlExpr};
+    use std::panic::AssertUnwindSafe;
+    use syn::{braced, parse::Parse, Token};
+
+    // `macro Name { expr, ... }`, parsed into its name and expressions
+    struct MacroDecl {
+        name: String,
+        exprs: Vec<RtlExpr>,
+    }
+
+    struct MacroExtension;
+
+    impl DeclExtension for MacroExtension {
+        fn keyword(&self) -> &str {
+            "macro"
+        }
+
+        fn parse(&self, input: ParseStream) -> Result<CustomDecl> {
+            let name: Ident = input.parse()?;
+            let content;
+            braced!(content in input);
+            let exprs = content.parse_terminated(RtlExpr::parse, Token![,])?;
+            Ok(CustomDecl::new(MacroDecl {
+                name: name.to_string(),
+                exprs: exprs.into_iter().collect(),
+            }))
+        }
+    }
+
+    #[test]
+    fn test_decl_extension() {
+        let src = "macro Pair { 1, 2 + 3 }\nf Main() Int { return 1; }";
+        let mut parser = RattleParser::new();
+        parser.register(MacroExtension);
+        let rattle = parser.parse(src).unwrap();
+
+        let RtlDeclValue::Custom(custom) = rattle.decls()[0].value() else {
+            panic!("expected a custom declaration");
+        };
+        assert_eq!(custom.keyword(), "macro");
+        let mac = custom.downcast_ref::<MacroDecl>().unwrap();
+        assert_eq!(mac.name, "Pair");
+        assert_eq!(mac.exprs.len(), 2);
+        assert!(matches!(rattle.decls()[1].value(), RtlDeclValue::RtlFn(_)));
+
+        // Only the same tokens are equal, wherever they are
+        let moved = parser.parse(&format!("\n\n{}", src)).unwrap();
+        assert!(rattle == moved);
+        let changed = parser.parse("macro Pair { 1, 2 + 4 }").unwrap();
+        assert!(rattle.decls()[0] != changed.decls()[0]);
+
+        // Without the extension, or outside the parser, `macro` is unknown
+        assert!(parse(src).is_err());
+        assert!(RattleParser::new().parse(src).is_err());
+        let err = parser.parse("macro Pair { 1, }, ").unwrap_err();
+        assert!(err.to_string().contains("expected"), "{}", err);
+    }
+
+    struct PanickingExtension;
+
+    impl DeclExtension for PanickingExtension {
+        fn keyword(&self) -> &str {
+            "boom"
+        }
+
+        fn parse(&self, _: ParseStream) -> Result<CustomDecl> {
+            panic!("extension bug")
+        }
+    }
+
+    #[test]
+    fn test_extension_panic() {
+        let mut parser = RattleParser::new();
+        parser.register(PanickingExtension);
+        parser.register(MacroExtension);
+        let panicked = std::panic::catch_unwind(AssertUnwindSafe(|| parser.parse("boom")));
+        assert!(panicked.is_err());
+        // The parser's extensions aren't left behind for plain parsing
+        assert!(parse("macro Pair { 1 }").is_err());
+    }
+}
@@ -0,0 +1,681 @@
+use std::fmt;
+
+use crate::owned::lit_text;
+use crate::{
+    parse, Rattle, RtlAttr, RtlBody, RtlDeclValue, RtlElse, RtlExpr, RtlFn, RtlIf, RtlIfLet,
+    RtlMatchArm, RtlPat, RtlResult, RtlStmt,
+};
+
+// Reformats a program into canonical source: one item per line, bodies
+// indented by four spaces, a blank line between declarations and single
+// spaces around operators, `=` and after `:` and `,`. Parentheses are kept
+// only where leaving them out would parse differently, so formatting
+// formatted source changes nothing. Comments are lost, since the tokenizer
+// drops them before parsing, except for a declaration's doc comment.
+pub fn format(src: &str) -> RtlResult<String> {
+    Ok(parse(src)?.to_string())
+}
+
+// Prints a program as `format` does
+impl fmt::Display for Rattle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut printer = Printer::default();
+        printer.program(self);
+        f.write_str(&printer.out)
+    }
+}
+
+// How tightly each kind of expression binds, for deciding where parentheses
+// are needed. Binary operators sit between `BINARY` and `CAST`, at their
+// own precedence above `BINARY`.
+const RANGE: u8 = 0;
+const CONDITIONAL: u8 = 1;
+const BINARY: u8 = 10;
+const CAST: u8 = 30;
+const POSTFIX: u8 = 40;
+
+fn binding_power(expr: &RtlExpr) -> u8 {
+    match expr {
+        RtlExpr::Range { .. } => RANGE,
+        RtlExpr::Conditional { .. } => CONDITIONAL,
+        RtlExpr::Binary { op, .. } => BINARY + op.precedence(),
+        RtlExpr::Cast { .. } => CAST,
+        _ => POSTFIX,
+    }
+}
+
+// Whether the expression's last token would swallow what follows it: an
+// open range takes anything but `;`, `,` or a closing bracket as its end, a
+// cast's type takes a `?`, and a capitalized name takes an empty `{}` as a
+// struct literal.
+fn open_ended(expr: &RtlExpr, next: &str) -> bool {
+    match expr {
+        RtlExpr::Range { end: None, .. } => true,
+        RtlExpr::Range { end: Some(end), .. } => open_ended(end, next),
+        RtlExpr::Binary { rhs, .. } => open_ended(rhs, next),
+        RtlExpr::Conditional { else_branch, .. } => open_ended(else_branch, next),
+        RtlExpr::Cast { .. } => next == "?",
+        RtlExpr::Ident(name) => next == "{}" && name.to_string().starts_with(char::is_uppercase),
+        _ => false,
+    }
+}
+
+#[derive(Default)]
+struct Printer {
+    out: String,
+    indent: usize,
+}
+
+impl Printer {
+    fn push(&mut self, text: &str) {
+        self.out.push_str(text);
+    }
+
+    fn newline(&mut self) {
+        self.out.push('\n');
+        for _ in 0..self.indent {
+            self.out.push_str("    ");
+        }
+    }
+
+    // Prints `items` separated by `, `
+    fn list<T>(&mut self, items: &[T], mut item: impl FnMut(&mut Self, &T)) {
+        for (i, it) in items.iter().enumerate() {
+            if i > 0 {
+                self.push(", ");
+            }
+            item(self, it);
+        }
+    }
+
+    // Prints `items` one per line inside braces, or `{}` without any
+    fn block<T>(&mut self, items: &[T], gap: bool, mut item: impl FnMut(&mut Self, &T)) {
+        if items.is_empty() {
+            self.push("{}");
+            return;
+        }
+        self.push("{");
+        self.indent += 1;
+        for (i, it) in items.iter().enumerate() {
+            if gap && i > 0 {
+                self.out.push('\n');
+            }
+            self.newline();
+            item(self, it);
+        }
+        self.indent -= 1;
+        self.newline();
+        self.push("}");
+    }
+
+    fn program(&mut self, rattle: &Rattle) {
+        for import in rattle.imports() {
+            let path: Vec<String> = import.path.iter().map(ToString::to_string).collect();
+            self.push(&format!("import {}", path.join("::")));
+            if let Some(alias) = &import.alias {
+                self.push(&format!(" as {}", alias));
+            }
+            self.push(";\n");
+        }
+        for (i, decl) in rattle.decls().iter().enumerate() {
+            if i > 0 || !rattle.imports().is_empty() {
+                self.out.push('\n');
+            }
+            for line in &decl.docs {
+                self.push(&format!("///{}", line.value()));
+                self.newline();
+            }
+            self.attrs(&decl.attrs);
+            if decl.is_public {
+                self.push("pub ");
+            }
+            self.decl(decl.value());
+            self.out.push('\n');
+        }
+    }
+
+    fn decl(&mut self, decl: &RtlDeclValue) {
+        match decl {
+            RtlDeclValue::RtlFn(func) => self.func(func),
+            RtlDeclValue::RtlConst(c) => {
+                self.push(&format!("const {} {} = ", c.ty, c.name));
+                self.expr(&c.data);
+                self.push(";");
+            }
+            RtlDeclValue::RtlVar(var) => {
+                let is_mut = if var.is_mut { "mut " } else { "" };
+                self.push(&format!("var {} {}{} = ", var.ty, is_mut, var.name));
+                self.expr(&var.data);
+                self.push(";");
+            }
+            RtlDeclValue::RtlStatic(st) => {
+                let is_mut = if st.is_mut { "mut " } else { "" };
+                self.push(&format!("static {} {}{} = ", st.ty, is_mut, st.name));
+                self.expr(&st.data);
+                self.push(";");
+            }
+            RtlDeclValue::RtlStruct(st) => {
+                self.push(&format!("struct {}", st.name));
+                self.params(&st.generics, &st.const_generics);
+                self.constraints(&st.constraints);
+                self.push(" ");
+                self.block(&st.fields, false, |p, field| {
+                    p.attrs(&field.attrs);
+                    if field.is_public {
+                        p.push("pub ");
+                    }
+                    p.push(&format!("{} {},", field.ty, field.name));
+                });
+            }
+            RtlDeclValue::RtlEnum(en) => {
+                self.push(&format!("enum {} ", en.name));
+                self.block(&en.variants, false, |p, variant| {
+                    p.attrs(&variant.attrs);
+                    p.push(&variant.name.to_string());
+                    if !variant.fields.is_empty() {
+                        p.push("(");
+                        p.list(&variant.fields, |p, ty| p.push(&ty.to_string()));
+                        p.push(")");
+                    }
+                    p.push(",");
+                });
+            }
+            RtlDeclValue::RtlDef(def) => {
+                self.push(&format!("def {} ", def.struct_name));
+                self.block(&def.defs, true, Printer::func);
+                if let Some(tr) = &def.def_for {
+                    self.push(&format!(" for {};", tr));
+                }
+            }
+            RtlDeclValue::RtlTrait(tr) => {
+                self.push(&format!("trait {} ", tr.name));
+                self.block(&tr.methods, false, Printer::func);
+            }
+            RtlDeclValue::RtlGen(gen) if !gen.params.is_empty() => {
+                self.push("gen");
+                self.params(&gen.params, &[]);
+                let over: Vec<String> = gen.over.iter().map(ToString::to_string).collect();
+                self.push(&format!(" over [{}] ", over.join(", ")));
+                self.block(&gen.methods, true, Printer::func);
+            }
+            // The methods of a plain `gen` run to the end of the program
+            RtlDeclValue::RtlGen(gen) => {
+                self.push("gen");
+                self.indent += 1;
+                for (i, method) in gen.methods.iter().enumerate() {
+                    if i > 0 {
+                        self.out.push('\n');
+                    }
+                    self.newline();
+                    self.func(method);
+                }
+                self.indent -= 1;
+            }
+            RtlDeclValue::RtlStaticAssert(assert) => {
+                self.push("static_assert(");
+                self.expr(&assert.cond);
+                if let Some(msg) = &assert.msg {
+                    self.push(&format!(", {}", msg.token()));
+                }
+                self.push(");");
+            }
+            RtlDeclValue::RtlTypeAlias(alias) => {
+                self.push(&format!("type {} = {};", alias.name, alias.target));
+            }
+            RtlDeclValue::Custom(custom) => {
+                self.push(custom.keyword());
+                if !custom.tokens().is_empty() {
+                    self.push(" ");
+                    self.push(custom.tokens());
+                }
+            }
+        }
+    }
+
+    // Prints type parameters, then const parameters marked `const`
+    fn params(&mut self, generics: &[syn::Ident], const_generics: &[syn::Ident]) {
+        if generics.is_empty() && const_generics.is_empty() {
+            return;
+        }
+        let params: Vec<String> = generics
+            .iter()
+            .map(ToString::to_string)
+            .chain(
+                const_generics
+                    .iter()
+                    .map(|param| format!("const {}", param)),
+            )
+            .collect();
+        self.push(&format!("<{}>", params.join(", ")));
+    }
+
+    fn constraints(&mut self, constraints: &[(syn::Ident, Vec<syn::Ident>)]) {
+        if !constraints.is_empty() {
+            self.push(" where ");
+            self.list(constraints, |p, (param, bounds)| {
+                let bounds: Vec<String> = bounds.iter().map(ToString::to_string).collect();
+                p.push(&format!("{}: {}", param, bounds.join(" + ")));
+            });
+        }
+    }
+
+    fn attrs(&mut self, attrs: &[RtlAttr]) {
+        for attr in attrs {
+            self.push(&format!("@{}", attr.name));
+            if !attr.args.is_empty() {
+                self.push("(");
+                self.list(&attr.args, Printer::expr);
+                self.push(")");
+            }
+            self.push(" ");
+        }
+    }
+
+    fn func(&mut self, func: &RtlFn) {
+        self.push(&format!("f {}", func.name));
+        self.params(&func.generics, &func.const_generics);
+        self.push("(");
+        self.list(&func.args, |p, arg| {
+            p.push(&format!("{} {}", arg.ty, arg.name));
+            if let Some(default) = &arg.default {
+                p.push(" = ");
+                p.expr(default);
+            }
+        });
+        self.push(")");
+        if !func.ret.is_unit() {
+            self.push(&format!(" -> {}", func.ret));
+        }
+        self.constraints(&func.constraints);
+        match &func.body {
+            Some(body) => {
+                self.push(" ");
+                self.body(body);
+            }
+            None => self.push(";"),
+        }
+    }
+
+    fn body(&mut self, body: &RtlBody) {
+        let Some(value) = &body.value else {
+            self.block(&body.stmts, false, Printer::stmt);
+            return;
+        };
+        self.push("{");
+        self.indent += 1;
+        for stmt in &body.stmts {
+            self.newline();
+            self.stmt(stmt);
+        }
+        self.newline();
+        self.expr(value);
+        self.indent -= 1;
+        self.newline();
+        self.push("}");
+    }
+
+    fn stmt(&mut self, stmt: &RtlStmt) {
+        match stmt {
+            RtlStmt::Var(var) => {
+                let is_mut = if var.is_mut { "mut " } else { "" };
+                self.push(&format!("var {} {}{} = ", var.ty, is_mut, var.name));
+                self.expr(&var.data);
+                self.push(";");
+            }
+            RtlStmt::Const(c) => {
+                self.push(&format!("const {} {} = ", c.ty, c.name));
+                self.expr(&c.data);
+                self.push(";");
+            }
+            RtlStmt::Assign { target, value } => {
+                self.expr(target);
+                self.push(" = ");
+                self.expr(value);
+                self.push(";");
+            }
+            RtlStmt::Return(None) => self.push("return;"),
+            RtlStmt::Return(Some(value)) => {
+                self.push("return ");
+                self.expr(value);
+                self.push(";");
+            }
+            RtlStmt::Expr(expr) => {
+                self.expr(expr);
+                self.push(";");
+            }
+            RtlStmt::Break { label, value } => {
+                self.push("break");
+                if let Some(label) = label {
+                    self.push(&format!(" '{}", label));
+                }
+                if let Some(value) = value {
+                    self.push(" ");
+                    self.expr(value);
+                }
+                self.push(";");
+            }
+            RtlStmt::Fn(func) => self.func(func),
+            RtlStmt::IfLet(if_let) => self.if_let(if_let),
+            RtlStmt::If(if_) => self.if_(if_),
+            RtlStmt::While { cond, body } => {
+                self.push("while ");
+                let empty = body.stmts.is_empty() && body.value.is_none();
+                self.expr_before(cond, if empty { "{}" } else { "{" });
+                self.push(" ");
+                self.body(body);
+            }
+            RtlStmt::Defer(expr) => {
+                self.push("defer ");
+                self.expr(expr);
+                self.push(";");
+            }
+        }
+    }
+
+    fn if_let(&mut self, if_let: &RtlIfLet) {
+        self.push("if let ");
+        self.pat(&if_let.pat);
+        self.push(" = ");
+        let empty = if_let.then.stmts.is_empty() && if_let.then.value.is_none();
+        self.expr_before(&if_let.scrutinee, if empty { "{}" } else { "{" });
+        self.push(" ");
+        self.body(&if_let.then);
+        self.else_branch(if_let.else_branch.as_ref());
+    }
+
+    fn if_(&mut self, if_: &RtlIf) {
+        self.push("if ");
+        let empty = if_.then.stmts.is_empty() && if_.then.value.is_none();
+        self.expr_before(&if_.cond, if empty { "{}" } else { "{" });
+        self.push(" ");
+        self.body(&if_.then);
+        self.else_branch(if_.else_branch.as_ref());
+    }
+
+    fn else_branch(&mut self, branch: Option<&RtlElse>) {
+        let Some(branch) = branch else {
+            return;
+        };
+        self.push(" else ");
+        match branch {
+            RtlElse::IfLet(next) => self.if_let(next),
+            RtlElse::If(next) => self.if_(next),
+            RtlElse::Block(body) => self.body(body),
+        }
+    }
+
+    fn pat(&mut self, pat: &RtlPat) {
+        match pat {
+            RtlPat::Wild => self.push("_"),
+            RtlPat::Lit(lit) => self.push(&lit_text(lit)),
+            RtlPat::Binding(name) => self.push(&name.to_string()),
+            RtlPat::Struct { name, fields } if fields.is_empty() => {
+                self.push(&format!("{} {{}}", name))
+            }
+            RtlPat::Struct { name, fields } => {
+                self.push(&format!("{} {{ ", name));
+                self.list(fields, |p, field| p.push(&field.to_string()));
+                self.push(" }");
+            }
+            RtlPat::Variant { name, fields } => {
+                self.push(&format!("{}(", name));
+                self.list(fields, Printer::pat);
+                self.push(")");
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &RtlExpr) {
+        self.expr_at(expr, RANGE);
+    }
+
+    // Prints an expression followed by `next`, in parentheses if it would
+    // otherwise run on into it
+    fn expr_before(&mut self, expr: &RtlExpr, next: &str) {
+        if open_ended(expr, next) {
+            self.push("(");
+            self.expr(expr);
+            self.push(")");
+        } else {
+            self.expr(expr);
+        }
+    }
+
+    // Prints an expression where only ones binding at least as tightly as
+    // `min` can go without parentheses
+    fn expr_at(&mut self, expr: &RtlExpr, min: u8) {
+        if binding_power(expr) < min {
+            self.push("(");
+            self.expr(expr);
+            self.push(")");
+            return;
+        }
+        match expr {
+            RtlExpr::Lit(lit) => self.push(&lit_text(lit)),
+            RtlExpr::Ident(name) => self.push(&name.to_string()),
+            RtlExpr::Array(elems) => {
+                self.push("[");
+                self.list(elems, Printer::expr);
+                self.push("]");
+            }
+            RtlExpr::Block(body) => self.body(body),
+            RtlExpr::Repeat { value, count } => {
+                self.push("[");
+                self.expr(value);
+                self.push(&format!("; {}]", count));
+            }
+            RtlExpr::Field { base, field } => {
+                self.expr_at(base, POSTFIX);
+                self.push(&format!(".{}", field));
+            }
+            RtlExpr::Index { base, index: inner } | RtlExpr::Slice { base, range: inner } => {
+                self.expr_at(base, POSTFIX);
+                self.push("[");
+                self.expr(inner);
+                self.push("]");
+            }
+            RtlExpr::Range { start, end } => {
+                if let Some(start) = start {
+                    self.expr_at(start, CONDITIONAL);
+                }
+                self.push("..");
+                if let Some(end) = end {
+                    self.expr_at(end, CONDITIONAL);
+                }
+            }
+            RtlExpr::Binary { op, lhs, rhs } => {
+                // Operators of one precedence nest to the left
+                let power = BINARY + op.precedence();
+                self.expr_at(lhs, power);
+                self.push(&format!(" {} ", op));
+                self.expr_at(rhs, power + 1);
+            }
+            RtlExpr::StructLit {
+                name,
+                fields,
+                spread: None,
+            } if fields.is_empty() => self.push(&format!("{} {{}}", name)),
+            RtlExpr::StructLit {
+                name,
+                fields,
+                spread,
+            } => {
+                self.push(&format!("{} {{ ", name));
+                if let Some(base) = spread {
+                    self.push("..");
+                    self.expr_before(base, ",");
+                    if !fields.is_empty() {
+                        self.push(", ");
+                    }
+                }
+                self.list(fields, |p, (field, value)| {
+                    p.push(&format!("{}: ", field));
+                    p.expr(value);
+                });
+                self.push(" }");
+            }
+            RtlExpr::Conditional {
+                cond,
+                then,
+                else_branch,
+            } => {
+                if open_ended(cond, "?") {
+                    self.push("(");
+                    self.expr(cond);
+                    self.push(")");
+                } else {
+                    self.expr_at(cond, BINARY);
+                }
+                self.push(" ? ");
+                self.expr_at(then, CONDITIONAL);
+                self.push(" : ");
+                self.expr_at(else_branch, CONDITIONAL);
+            }
+            RtlExpr::Cast { expr, ty } => {
+                self.expr_at(expr, CAST);
+                self.push(&format!(" as {}", ty));
+            }
+            RtlExpr::None(_) => self.push("none"),
+            RtlExpr::Some(value) => {
+                self.push("some(");
+                self.expr(value);
+                self.push(")");
+            }
+            RtlExpr::Call { name, args } => {
+                self.push(&format!("{}(", name));
+                self.list(args, Printer::expr);
+                self.push(")");
+            }
+            RtlExpr::Spread(value) => {
+                self.expr(value);
+                self.push("...");
+            }
+            RtlExpr::Match { scrutinee, arms } => {
+                self.push("match ");
+                self.expr_before(scrutinee, if arms.is_empty() { "{}" } else { "{" });
+                self.push(" ");
+                self.block(arms, false, Printer::arm);
+            }
+            RtlExpr::Loop { label, body } => {
+                if let Some(label) = label {
+                    self.push(&format!("'{}: ", label));
+                }
+                self.push("loop ");
+                self.body(body);
+            }
+        }
+    }
+
+    fn arm(&mut self, arm: &RtlMatchArm) {
+        self.pat(&arm.pat);
+        if !arm.bindings.is_empty() || arm.guard.is_some() {
+            self.push(" where ");
+            self.list(&arm.bindings, |p, (name, value)| {
+                p.push(&format!("let {} = ", name));
+                p.expr_before(value, ",");
+            });
+            if let Some(guard) = &arm.guard {
+                if !arm.bindings.is_empty() {
+                    self.push(", ");
+                }
+                self.expr_before(guard, "=>");
+            }
+        }
+        self.push(" => ");
+        self.expr(&arm.body);
+        self.push(",");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format() {
+        let src = r#"
+            import std::io as io ;
+            const Int   Max=10 ;
+            struct Point<T>where T:Show{ @serde_rename( "x" )pub T x ,T y }
+            f Add( Int a,Int b=2 )Int{ var Int mut c=a+b*2 ; c += ( a-b )-( b-a );
+            if let Some( v )=Find( c ){ return v ; } else if c>1{ c=1 ; } else { 0 }
+            while c<10{ c += 1 ; }
+            match c { 0=>1 , n where let m = n*2, m>3=>( m..n ).len , _=>{ var Int d = 1; d } } ;
+            (c as Float) ? 1 : 2
+            }
+            def Point{ f Show( This this )String ; f Len( This this )Int{ 2 } } for Shape ;
+        "#;
+        let expected = r#"import std::io as io;
+
+const Int Max = 10;
+
+struct Point<T> where T: Show {
+    @serde_rename("x") pub T x,
+    T y,
+}
+
+f Add(Int a, Int b = 2) -> Int {
+    var Int mut c = a + b * 2;
+    c = c + (a - b - (b - a));
+    if let Some(v) = Find(c) {
+        return v;
+    } else if c > 1 {
+        c = 1;
+    } else {
+        return 0;
+    }
+    while c < 10 {
+        c = c + 1;
+    }
+    match c {
+        0 => 1,
+        n where let m = n * 2, m > 3 => (m..n).len,
+        _ => {
+            var Int d = 1;
+            d
+        },
+    };
+    return (c as Float) ? 1 : 2;
+}
+
+def Point {
+    f Show(This this) -> String;
+
+    f Len(This this) -> Int {
+        return 2;
+    }
+} for Shape;
+"#;
+        let formatted = format(src).unwrap();
+        assert_eq!(formatted, expected);
+        assert_eq!(format(&formatted).unwrap(), formatted);
+        assert!(parse(src).unwrap() == parse(&formatted).unwrap());
+    }
+
+    #[test]
+    fn test_format_keeps_meaning() {
+        // Every parenthesis here changes the parse, so formatting keeps it
+        let srcs = [
+            "const Int X = (1 + 2) * 3;",
+            "const Int X = 1 - (2 - 3);",
+            "const Bool X = (a ? b : c) ? d : e;",
+            "const Int X = (a as Int) ? 1 : 2;",
+            "const Int X = (-1 as Int).y;",
+            "const Int X = (a | b) & (c << 1) + 1;",
+            "const P X = P { ..(a..), y: 1 };",
+            "f F() -> Int {\n    if let A = (B) {}\n    match (x..) {};\n    return 0;\n}",
+            "f F() -> Int {\n    if (B) {} else if let A = x {}\n    while (B) {}\n    return 0;\n}",
+            "static_assert(Size > 0 && Size < 8, \"size must be small\");",
+            "type Ids = [Id?];",
+            "f Log(String s) {\n    print(s);\n}",
+            "f F() -> Int {\n    var Int x = 'l: loop {\n        break 'l a + 1;\n    };\n    loop {};\n    return x;\n}",
+            "/// Adds \"one\".\n///\n@inline @since(2) pub f Inc(Int x) -> Int;",
+            "gen<T, U> over [Int, [Float]] {\n    f Id(T x) -> T;\n\n    f Pair(T a, U b) -> Int;\n}",
+        ];
+        for src in srcs {
+            let formatted = format(src).unwrap();
+            assert_eq!(formatted.trim_end(), src);
+            assert!(parse(src).unwrap() == parse(&formatted).unwrap(), "{}", src);
+        }
+    }
+}
@@ -0,0 +1,236 @@
+use std::collections::HashSet;
+
+use syn::Ident;
+
+use crate::{RtlBody, RtlElse, RtlExpr, RtlFn, RtlIf, RtlIfLet, RtlPat, RtlStmt};
+
+impl RtlExpr {
+    // The names the expression refers to without binding them itself: the
+    // variables and constants it reads and the functions it calls. A `var`,
+    // `const` or local function in a block binds its name for the rest of
+    // the block, a function's parameters for its body, and a pattern's or
+    // `where let`'s bindings for their arm or `if let` block. Types, like a
+    // struct literal's or a cast's, and field names aren't variables, so
+    // they're left out.
+    pub fn free_vars(&self) -> HashSet<String> {
+        let mut free_vars = FreeVars::default();
+        free_vars.expr(self);
+        free_vars.free
+    }
+}
+
+#[derive(Default)]
+struct FreeVars {
+    // The names bound where we are, innermost last
+    bound: Vec<String>,
+    free: HashSet<String>,
+}
+
+impl FreeVars {
+    fn name(&mut self, name: &Ident) {
+        let name = name.to_string();
+        if !self.bound.contains(&name) {
+            self.free.insert(name);
+        }
+    }
+
+    fn bind(&mut self, name: &Ident) {
+        self.bound.push(name.to_string());
+    }
+
+    fn func(&mut self, func: &RtlFn) {
+        let outer = self.bound.len();
+        for arg in &func.args {
+            if let Some(default) = &arg.default {
+                self.expr(default);
+            }
+        }
+        func.args.iter().for_each(|arg| self.bind(&arg.name));
+        if let Some(body) = &func.body {
+            self.body(body);
+        }
+        self.bound.truncate(outer);
+    }
+
+    fn body(&mut self, body: &RtlBody) {
+        let outer = self.bound.len();
+        body.stmts.iter().for_each(|stmt| self.stmt(stmt));
+        if let Some(value) = &body.value {
+            self.expr(value);
+        }
+        self.bound.truncate(outer);
+    }
+
+    // Binds what a statement declares for the rest of its body
+    fn stmt(&mut self, stmt: &RtlStmt) {
+        match stmt {
+            RtlStmt::Var(var) => {
+                self.expr(&var.data);
+                self.bind(&var.name);
+            }
+            RtlStmt::Const(c) => {
+                self.expr(&c.data);
+                self.bind(&c.name);
+            }
+            RtlStmt::Assign { target, value } => {
+                self.expr(target);
+                self.expr(value);
+            }
+            RtlStmt::Return(value) | RtlStmt::Break { value, .. } => {
+                if let Some(value) = value {
+                    self.expr(value);
+                }
+            }
+            RtlStmt::Expr(expr) | RtlStmt::Defer(expr) => self.expr(expr),
+            // A local function is visible in its own body, for recursion
+            RtlStmt::Fn(func) => {
+                self.bind(&func.name);
+                self.func(func);
+            }
+            RtlStmt::IfLet(if_let) => self.if_let(if_let),
+            RtlStmt::If(if_) => self.if_(if_),
+            RtlStmt::While { cond, body } => {
+                self.expr(cond);
+                self.body(body);
+            }
+        }
+    }
+
+    fn if_let(&mut self, if_let: &RtlIfLet) {
+        self.expr(&if_let.scrutinee);
+        let outer = self.bound.len();
+        self.pat(&if_let.pat);
+        self.body(&if_let.then);
+        self.bound.truncate(outer);
+        self.else_branch(if_let.else_branch.as_ref());
+    }
+
+    fn if_(&mut self, if_: &RtlIf) {
+        self.expr(&if_.cond);
+        self.body(&if_.then);
+        self.else_branch(if_.else_branch.as_ref());
+    }
+
+    fn else_branch(&mut self, branch: Option<&RtlElse>) {
+        match branch {
+            Some(RtlElse::IfLet(next)) => self.if_let(next),
+            Some(RtlElse::If(next)) => self.if_(next),
+            Some(RtlElse::Block(block)) => self.body(block),
+            None => {}
+        }
+    }
+
+    // Binds the names a pattern binds
+    fn pat(&mut self, pat: &RtlPat) {
+        match pat {
+            RtlPat::Binding(name) => self.bind(name),
+            RtlPat::Struct { fields, .. } => fields.iter().for_each(|field| self.bind(field)),
+            RtlPat::Variant { fields, .. } => fields.iter().for_each(|pat| self.pat(pat)),
+            RtlPat::Wild | RtlPat::Lit(_) => {}
+        }
+    }
+
+    fn expr(&mut self, expr: &RtlExpr) {
+        match expr {
+            RtlExpr::Lit(_) | RtlExpr::None(_) => {}
+            RtlExpr::Ident(name) => self.name(name),
+            RtlExpr::Array(elems) => elems.iter().for_each(|elem| self.expr(elem)),
+            RtlExpr::Block(block) | RtlExpr::Loop { body: block, .. } => self.body(block),
+            RtlExpr::Repeat { value, .. } => self.expr(value),
+            RtlExpr::Field { base, .. } => self.expr(base),
+            RtlExpr::Index { base, index } => {
+                self.expr(base);
+                self.expr(index);
+            }
+            RtlExpr::Range { start, end } => {
+                start.iter().chain(end).for_each(|bound| self.expr(bound));
+            }
+            RtlExpr::Slice { base, range } => {
+                self.expr(base);
+                self.expr(range);
+            }
+            RtlExpr::Binary { lhs, rhs, .. } => {
+                self.expr(lhs);
+                self.expr(rhs);
+            }
+            RtlExpr::Conditional {
+                cond,
+                then,
+                else_branch,
+            } => {
+                self.expr(cond);
+                self.expr(then);
+                self.expr(else_branch);
+            }
+            RtlExpr::StructLit { fields, spread, .. } => {
+                fields.iter().for_each(|(_, value)| self.expr(value));
+                spread.iter().for_each(|base| self.expr(base));
+            }
+            RtlExpr::Cast { expr, .. } => self.expr(expr),
+            RtlExpr::Some(value) | RtlExpr::Spread(value) => self.expr(value),
+            RtlExpr::Call { name, args } => {
+                self.name(name);
+                args.iter().for_each(|arg| self.expr(arg));
+            }
+            RtlExpr::Match { scrutinee, arms } => {
+                self.expr(scrutinee);
+                for arm in arms {
+                    let outer = self.bound.len();
+                    self.pat(&arm.pat);
+                    for (name, value) in &arm.bindings {
+                        self.expr(value);
+                        self.bind(name);
+                    }
+                    if let Some(guard) = &arm.guard {
+                        self.expr(guard);
+                    }
+                    self.expr(&arm.body);
+                    self.bound.truncate(outer);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::RtlExpr;
+
+    fn free_vars(src: &str) -> HashSet<String> {
+        syn::parse_str::<RtlExpr>(src).unwrap().free_vars()
+    }
+
+    fn set(names: &[&str]) -> HashSet<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_free_vars() {
+        assert_eq!(free_vars("a + f(b, c)"), set(&["a", "b", "c", "f"]));
+        assert_eq!(free_vars("p.x as Float"), set(&["p"]));
+        assert_eq!(free_vars("Point { x: a, ..base }"), set(&["a", "base"]));
+
+        // A local is bound only after its declaration, and only in its block
+        assert_eq!(
+            free_vars("{ var Int y = x + y; var Int z = y * 2; z + w }"),
+            set(&["x", "y", "w"])
+        );
+        assert_eq!(free_vars("{ var Int y = 1; y } + y"), set(&["y"]));
+        assert_eq!(
+            free_vars("{ f Sq(Int n) Int { return n * Sq(k); } Sq(3) }"),
+            set(&["k"])
+        );
+
+        // Patterns and `where let` bind in their own arm
+        assert_eq!(
+            free_vars("match v { some(x) where let y = x * s, y > 0 => y, n => n + x }"),
+            set(&["v", "s", "x"])
+        );
+        assert_eq!(
+            free_vars("{ if let Point { x, y } = p { return x + y + z; } x }"),
+            set(&["p", "z", "x"])
+        );
+    }
+}
@@ -0,0 +1,397 @@
+use std::collections::HashSet;
+
+use proc_macro2::Span;
+use syn::Ident;
+
+use crate::{
+    Rattle, RtlArrayLen, RtlBody, RtlDeclValue, RtlElse, RtlExpr, RtlFn, RtlIf, RtlIfLet, RtlPat,
+    RtlStmt, RtlType,
+};
+
+// Enum for the kinds of lints Rattle reports
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LintKind {
+    // An assignment of a place to itself, like `x = x;`
+    SelfAssign,
+    // A top-level `var` that's never referred to
+    UnusedVar,
+    // A top-level `const` that's never referred to
+    UnusedConst,
+    // An import whose name is never referred to
+    UnusedImport,
+}
+
+// The struct for a single lint finding
+#[derive(Debug)]
+pub struct RtlLint {
+    pub kind: LintKind,
+    pub span: Span,
+    pub message: String,
+}
+
+// Flags assignments whose target and value are the same place, which are no-ops
+pub fn lint_self_assign(body: &RtlBody) -> Vec<RtlLint> {
+    body.stmts()
+        .iter()
+        .filter_map(|stmt| match stmt {
+            RtlStmt::Assign { target, value } if target == value => Some(RtlLint {
+                kind: LintKind::SelfAssign,
+                span: target.span(),
+                message: "assignment of a place to itself has no effect".to_string(),
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+impl Rattle {
+    // Flags top-level variables, constants and imports that nothing in the
+    // program refers to. An import is used when its alias, or its last segment
+    // without one, names a value, function or type, or starts a type path.
+    pub fn lint(&self) -> Vec<RtlLint> {
+        let mut refs = Refs::default();
+        for decl in &self.decls {
+            decl.attrs
+                .iter()
+                .flat_map(|attr| &attr.args)
+                .for_each(|e| refs.expr(e));
+            refs.decl(&decl.value);
+        }
+
+        let mut lints = Vec::new();
+        for import in &self.imports {
+            let Some(name) = import.alias.as_ref().or(import.path.last()) else {
+                continue;
+            };
+            if !refs.names.contains(&name.to_string()) {
+                lints.push(RtlLint {
+                    kind: LintKind::UnusedImport,
+                    span: name.span(),
+                    message: format!("import `{}` is never used", name),
+                });
+            }
+        }
+        // Another program importing an exported one may use it
+        for decl in self.decls.iter().filter(|decl| !decl.is_public) {
+            let (kind, name, what) = match &decl.value {
+                RtlDeclValue::RtlVar(var) => (LintKind::UnusedVar, &var.name, "variable"),
+                RtlDeclValue::RtlConst(c) => (LintKind::UnusedConst, &c.name, "constant"),
+                _ => continue,
+            };
+            if !refs.names.contains(&name.to_string()) {
+                lints.push(RtlLint {
+                    kind,
+                    span: name.span(),
+                    message: format!("{} `{}` is never used", what, name),
+                });
+            }
+        }
+        lints
+    }
+}
+
+// The names a program refers to: values, called functions, struct literals,
+// patterns and types. Locals aren't told apart from top-level declarations,
+// so using a local that shadows a declaration counts as using the declaration.
+#[derive(Default)]
+struct Refs {
+    names: HashSet<String>,
+}
+
+impl Refs {
+    fn name(&mut self, name: &Ident) {
+        self.names.insert(name.to_string());
+    }
+
+    fn decl(&mut self, value: &RtlDeclValue) {
+        match value {
+            RtlDeclValue::RtlFn(func) => self.func(func),
+            RtlDeclValue::RtlConst(c) => {
+                self.ty(&c.ty);
+                self.expr(&c.data);
+            }
+            RtlDeclValue::RtlVar(var) => {
+                self.ty(&var.ty);
+                self.expr(&var.data);
+            }
+            RtlDeclValue::RtlStatic(st) => {
+                self.ty(&st.ty);
+                self.expr(&st.data);
+            }
+            RtlDeclValue::RtlStaticAssert(assert) => self.expr(&assert.cond),
+            RtlDeclValue::RtlTypeAlias(alias) => self.ty(&alias.target),
+            RtlDeclValue::RtlStruct(st) => {
+                st.constraints
+                    .iter()
+                    .flat_map(|(_, bounds)| bounds)
+                    .for_each(|b| self.name(b));
+                for field in &st.fields {
+                    field
+                        .attrs
+                        .iter()
+                        .flat_map(|attr| &attr.args)
+                        .for_each(|e| self.expr(e));
+                    self.ty(&field.ty);
+                }
+            }
+            RtlDeclValue::RtlEnum(en) => {
+                for variant in &en.variants {
+                    variant
+                        .attrs
+                        .iter()
+                        .flat_map(|attr| &attr.args)
+                        .for_each(|e| self.expr(e));
+                    variant.fields.iter().for_each(|ty| self.ty(ty));
+                }
+            }
+            RtlDeclValue::RtlDef(def) => {
+                self.name(&def.struct_name);
+                if let Some(tr) = &def.def_for {
+                    self.name(tr);
+                }
+                def.defs.iter().for_each(|func| self.func(func));
+            }
+            RtlDeclValue::RtlTrait(tr) => tr.methods.iter().for_each(|func| self.func(func)),
+            RtlDeclValue::RtlGen(gen) => {
+                gen.over.iter().for_each(|ty| self.ty(ty));
+                gen.methods.iter().for_each(|func| self.func(func));
+            }
+            // Its structure is the extension's own, so any identifier in it
+            // may be a use
+            RtlDeclValue::Custom(custom) => {
+                let words = custom
+                    .tokens()
+                    .split(|c: char| !(c.is_alphanumeric() || c == '_'));
+                self.names.extend(words.map(str::to_string));
+            }
+        }
+    }
+
+    fn func(&mut self, func: &RtlFn) {
+        func.constraints
+            .iter()
+            .flat_map(|(_, bounds)| bounds)
+            .for_each(|b| self.name(b));
+        for arg in &func.args {
+            self.ty(&arg.ty);
+            if let Some(default) = &arg.default {
+                self.expr(default);
+            }
+        }
+        self.ty(&func.ret);
+        if let Some(body) = &func.body {
+            self.body(body);
+        }
+    }
+
+    fn ty(&mut self, ty: &RtlType) {
+        match ty {
+            RtlType::Named(name) => self.name(name),
+            // The first segment is the module an import may have brought in
+            RtlType::Path(segments) => self.name(&segments[0]),
+            RtlType::Array(inner) | RtlType::Optional(inner) | RtlType::Ref { inner, .. } => {
+                self.ty(inner)
+            }
+            RtlType::FixedArray(inner, len) => {
+                self.ty(inner);
+                // A length may name a constant
+                if let RtlArrayLen::Param(name) = len {
+                    self.name(name);
+                }
+            }
+        }
+    }
+
+    fn body(&mut self, body: &RtlBody) {
+        body.stmts.iter().for_each(|stmt| self.stmt(stmt));
+        if let Some(value) = &body.value {
+            self.expr(value);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &RtlStmt) {
+        match stmt {
+            RtlStmt::Var(var) => {
+                self.ty(&var.ty);
+                self.expr(&var.data);
+            }
+            RtlStmt::Const(c) => {
+                self.ty(&c.ty);
+                self.expr(&c.data);
+            }
+            RtlStmt::Assign { target, value } => {
+                self.expr(target);
+                self.expr(value);
+            }
+            RtlStmt::Return(value) | RtlStmt::Break { value, .. } => {
+                if let Some(value) = value {
+                    self.expr(value);
+                }
+            }
+            RtlStmt::Expr(expr) | RtlStmt::Defer(expr) => self.expr(expr),
+            RtlStmt::Fn(func) => self.func(func),
+            RtlStmt::IfLet(if_let) => self.if_let(if_let),
+            RtlStmt::If(if_) => self.if_(if_),
+            RtlStmt::While { cond, body } => {
+                self.expr(cond);
+                self.body(body);
+            }
+        }
+    }
+
+    fn if_let(&mut self, if_let: &RtlIfLet) {
+        self.pat(&if_let.pat);
+        self.expr(&if_let.scrutinee);
+        self.body(&if_let.then);
+        self.else_branch(if_let.else_branch.as_ref());
+    }
+
+    fn if_(&mut self, if_: &RtlIf) {
+        self.expr(&if_.cond);
+        self.body(&if_.then);
+        self.else_branch(if_.else_branch.as_ref());
+    }
+
+    fn else_branch(&mut self, branch: Option<&RtlElse>) {
+        match branch {
+            Some(RtlElse::IfLet(next)) => self.if_let(next),
+            Some(RtlElse::If(next)) => self.if_(next),
+            Some(RtlElse::Block(block)) => self.body(block),
+            None => {}
+        }
+    }
+
+    fn pat(&mut self, pat: &RtlPat) {
+        match pat {
+            RtlPat::Struct { name, .. } => self.name(name),
+            RtlPat::Variant { name, fields } => {
+                self.name(name);
+                fields.iter().for_each(|pat| self.pat(pat));
+            }
+            RtlPat::Wild | RtlPat::Lit(_) | RtlPat::Binding(_) => {}
+        }
+    }
+
+    fn expr(&mut self, expr: &RtlExpr) {
+        match expr {
+            RtlExpr::Lit(_) | RtlExpr::None(_) => {}
+            RtlExpr::Ident(name) => self.name(name),
+            RtlExpr::Array(elems) => elems.iter().for_each(|elem| self.expr(elem)),
+            RtlExpr::Block(block) | RtlExpr::Loop { body: block, .. } => self.body(block),
+            RtlExpr::Repeat { value, .. } => self.expr(value),
+            RtlExpr::Field { base, .. } => self.expr(base),
+            RtlExpr::Index { base, index } => {
+                self.expr(base);
+                self.expr(index);
+            }
+            RtlExpr::Range { start, end } => {
+                start.iter().chain(end).for_each(|bound| self.expr(bound));
+            }
+            RtlExpr::Slice { base, range } => {
+                self.expr(base);
+                self.expr(range);
+            }
+            RtlExpr::Binary { lhs, rhs, .. } => {
+                self.expr(lhs);
+                self.expr(rhs);
+            }
+            RtlExpr::Conditional {
+                cond,
+                then,
+                else_branch,
+            } => {
+                self.expr(cond);
+                self.expr(then);
+                self.expr(else_branch);
+            }
+            RtlExpr::StructLit {
+                name,
+                fields,
+                spread,
+            } => {
+                self.name(name);
+                fields.iter().for_each(|(_, value)| self.expr(value));
+                spread.iter().for_each(|base| self.expr(base));
+            }
+            RtlExpr::Cast { expr, ty } => {
+                self.expr(expr);
+                self.ty(ty);
+            }
+            RtlExpr::Some(value) | RtlExpr::Spread(value) => self.expr(value),
+            RtlExpr::Call { name, args } => {
+                self.name(name);
+                args.iter().for_each(|arg| self.expr(arg));
+            }
+            RtlExpr::Match { scrutinee, arms } => {
+                self.expr(scrutinee);
+                for arm in arms {
+                    self.pat(&arm.pat);
+                    arm.bindings.iter().for_each(|(_, value)| self.expr(value));
+                    if let Some(guard) = &arm.guard {
+                        self.expr(guard);
+                    }
+                    self.expr(&arm.body);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse, RtlDeclValue};
+
+    fn lint_fn(src: &str) -> Vec<RtlLint> {
+        let rattle = parse(src).unwrap();
+        let RtlDeclValue::RtlFn(func) = rattle.decls()[0].value() else {
+            panic!("expected a function");
+        };
+        lint_self_assign(func.body().unwrap())
+    }
+
+    #[test]
+    fn test_self_assign_ident() {
+        let lints = lint_fn("f F(Int x) Int { x = x; }");
+        assert_eq!(lints.len(), 1);
+        assert_eq!(lints[0].kind, LintKind::SelfAssign);
+    }
+
+    #[test]
+    fn test_self_assign_field_and_index() {
+        let lints = lint_fn("f F(Person p) Int { p.age = p.age; xs[0] = xs[0]; xs[0] = xs[1]; }");
+        assert_eq!(lints.len(), 2);
+    }
+
+    #[test]
+    fn test_distinct_assign() {
+        assert!(lint_fn("f F(Int x, Int y) Int { x = y; p.age = q.age; }").is_empty());
+    }
+
+    #[test]
+    fn test_lint_unused() {
+        let rattle = parse(
+            "import std::io;
+import std::collections as coll;
+import std::math::Max;
+const Int Limit = 10;
+const Int Unused = 1;
+pub const Int Exported = 2;
+var Int mut total = 0;
+f Clamp(coll::Map m, Int x) Int { total = Max(x, Limit); return total; }",
+        )
+        .unwrap();
+        let lints = rattle.lint();
+        let found: Vec<_> = lints
+            .iter()
+            .map(|lint| (lint.kind, lint.span.start().line, lint.message.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (LintKind::UnusedImport, 1, "import `io` is never used"),
+                (LintKind::UnusedConst, 5, "constant `Unused` is never used"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,284 @@
+use std::str::FromStr;
+
+use proc_macro2::{Delimiter, TokenStream, TokenTree};
+use syn::Result;
+
+use crate::{RtlSymbolKind, SpanInfo};
+
+// The kind, name and signature of a top-level declaration. The signature is
+// the source text between the name and the body or initializer, like
+// `(Int x, Int y) Int` for a function or `Int` for a constant, with runs of
+// whitespace collapsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeclOutline {
+    kind: RtlSymbolKind,
+    name: String,
+    signature: String,
+    span: SpanInfo,
+}
+
+impl DeclOutline {
+    pub fn kind(&self) -> RtlSymbolKind {
+        self.kind
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn signature(&self) -> &str {
+        &self.signature
+    }
+
+    pub fn span(&self) -> SpanInfo {
+        self.span
+    }
+}
+
+// Lists a program's declarations for an outline view, like `SymbolTable` but
+// without parsing. Bodies and initializers are skipped whole, since the
+// tokenizer already balances their braces into a single group, so nothing in
+// them is checked either: a program `parse` rejects may still outline fine.
+pub fn parse_outline(src: &str) -> Result<Vec<DeclOutline>> {
+    let mut outline = Vec::new();
+    outline_tokens(src, TokenStream::from_str(src)?, &mut outline)?;
+    Ok(outline)
+}
+
+fn outline_tokens(src: &str, tokens: TokenStream, outline: &mut Vec<DeclOutline>) -> Result<()> {
+    let tokens: Vec<TokenTree> = tokens.into_iter().collect();
+    let mut i = 0;
+    while i < tokens.len() {
+        let keyword = match &tokens[i] {
+            TokenTree::Punct(p) if p.as_char() == ';' => {
+                i += 1;
+                continue;
+            }
+            // A doc comment line, which the tokenizer makes `#[doc = "..."]`
+            TokenTree::Punct(p) if p.as_char() == '#' => {
+                i += 2;
+                continue;
+            }
+            // An attribute, with its arguments if it has any
+            TokenTree::Punct(p) if p.as_char() == '@' => {
+                i += 2;
+                if matches!(tokens.get(i), Some(TokenTree::Group(g)) if g.delimiter() == Delimiter::Parenthesis)
+                {
+                    i += 1;
+                }
+                continue;
+            }
+            TokenTree::Ident(ident) => ident.to_string(),
+            other => return Err(syn::Error::new(other.span(), "expected a declaration")),
+        };
+        i += 1;
+        let kind = match keyword.as_str() {
+            "pub" => continue,
+            // Neither names anything to list
+            "import" | "static_assert" => {
+                i = skip_past_semi(&tokens, i);
+                continue;
+            }
+            // A `gen` block's methods are listed as functions, those of a
+            // template from inside its braces
+            "gen" => {
+                if matches!(tokens.get(i), Some(TokenTree::Punct(p)) if p.as_char() == '<') {
+                    let body = (i..tokens.len()).find_map(|j| match &tokens[j] {
+                        TokenTree::Group(g) if g.delimiter() == Delimiter::Brace => Some((j, g)),
+                        _ => None,
+                    });
+                    let Some((j, body)) = body else {
+                        return Err(syn::Error::new(tokens[i - 1].span(), "expected `{`"));
+                    };
+                    outline_tokens(src, body.stream(), outline)?;
+                    i = j + 1;
+                }
+                continue;
+            }
+            "f" => RtlSymbolKind::Fn,
+            "const" => RtlSymbolKind::Const,
+            "var" => RtlSymbolKind::Var,
+            "static" => RtlSymbolKind::Static,
+            "struct" => RtlSymbolKind::Struct,
+            "enum" => RtlSymbolKind::Enum,
+            "def" => RtlSymbolKind::Def,
+            "trait" => RtlSymbolKind::Trait,
+            "type" => RtlSymbolKind::Type,
+            _ => {
+                return Err(syn::Error::new(
+                    tokens[i - 1].span(),
+                    "expected a declaration",
+                ))
+            }
+        };
+
+        let decl = match kind {
+            RtlSymbolKind::Const | RtlSymbolKind::Var | RtlSymbolKind::Static => {
+                // The name is the last identifier before `=`, after the type
+                // and any `mut`
+                let eq = (i..tokens.len())
+                    .find(|&j| matches!(&tokens[j], TokenTree::Punct(p) if p.as_char() == '='))
+                    .ok_or_else(|| syn::Error::new(tokens[i - 1].span(), "expected `=`"))?;
+                let name = eq.checked_sub(1).filter(|&j| j >= i).map(|j| &tokens[j]);
+                let Some(TokenTree::Ident(name)) = name else {
+                    return Err(syn::Error::new(tokens[i - 1].span(), "expected a name"));
+                };
+                let ty_end = match &tokens[eq - 2] {
+                    TokenTree::Ident(m) if m == "mut" => eq - 2,
+                    _ => eq - 1,
+                };
+                let decl = outline_of(src, kind, name, &tokens[i..ty_end]);
+                i = skip_past_semi(&tokens, eq);
+                decl
+            }
+            // An alias's target is its initializer, so it has no signature
+            RtlSymbolKind::Type => {
+                let Some(TokenTree::Ident(name)) = tokens.get(i) else {
+                    return Err(syn::Error::new(tokens[i - 1].span(), "expected a name"));
+                };
+                let decl = outline_of(src, kind, name, &[]);
+                i = skip_past_semi(&tokens, i);
+                decl
+            }
+            _ => {
+                let Some(TokenTree::Ident(name)) = tokens.get(i) else {
+                    return Err(syn::Error::new(tokens[i - 1].span(), "expected a name"));
+                };
+                let start = i + 1;
+                let mut end = start;
+                while end < tokens.len() && !ends_header(&tokens[end]) {
+                    end += 1;
+                }
+                let decl = outline_of(src, kind, name, &tokens[start..end]);
+                i = end;
+                if let Some(TokenTree::Group(body)) = tokens.get(i) {
+                    if body.delimiter() == Delimiter::Brace {
+                        i += 1;
+                    }
+                }
+                // The trait a `def` block implements follows its body
+                if kind == RtlSymbolKind::Def
+                    && matches!(tokens.get(i), Some(TokenTree::Ident(ident)) if ident == "for")
+                {
+                    i += 2;
+                }
+                decl
+            }
+        };
+        outline.push(decl);
+    }
+    Ok(())
+}
+
+// Whether a token ends a declaration's header: its body, a `;`, or the start
+// of the next declaration after a bodiless function
+fn ends_header(token: &TokenTree) -> bool {
+    match token {
+        TokenTree::Group(group) => group.delimiter() == Delimiter::Brace,
+        TokenTree::Punct(p) => matches!(p.as_char(), ';' | '#' | '@'),
+        TokenTree::Ident(ident) => matches!(
+            ident.to_string().as_str(),
+            "f" | "const"
+                | "var"
+                | "static"
+                | "struct"
+                | "enum"
+                | "def"
+                | "trait"
+                | "gen"
+                | "static_assert"
+                | "type"
+                | "pub"
+        ),
+        TokenTree::Literal(_) => false,
+    }
+}
+
+// The position after the next top-level `;`, or the end of the tokens
+fn skip_past_semi(tokens: &[TokenTree], from: usize) -> usize {
+    (from..tokens.len())
+        .find(|&j| matches!(&tokens[j], TokenTree::Punct(p) if p.as_char() == ';'))
+        .map_or(tokens.len(), |j| j + 1)
+}
+
+fn outline_of(
+    src: &str,
+    kind: RtlSymbolKind,
+    name: &proc_macro2::Ident,
+    header: &[TokenTree],
+) -> DeclOutline {
+    let signature = match (header.first(), header.last()) {
+        (Some(first), Some(last)) => {
+            let text = &src[first.span().byte_range().start..last.span().byte_range().end];
+            text.split_whitespace().collect::<Vec<_>>().join(" ")
+        }
+        _ => String::new(),
+    };
+    DeclOutline {
+        kind,
+        name: name.to_string(),
+        signature,
+        span: SpanInfo::of(name.span()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse, SymbolTable};
+
+    const SRC: &str = "import ::std as hi;
+f Add(Int x,
+      Int y) Int;
+struct Pair<T> where T: Show { T a, T b }
+/// The first few.
+@serde_rename(\"xs\") pub const [Int] XS = [1; 3];
+type Id = Int;
+var Int mut count = { var Int a = 2; a + 1 };
+def Pair { f Swap(This this) This { return this; } } for Swap;
+f Max<T>(T a, T b) T where T: Ord { return a; }
+gen<T> over [Int, Float] { f Twice(T x) T; }
+gen f Id(Int x) Int { return x; }";
+
+    #[test]
+    fn test_parse_outline() {
+        let outline = parse_outline(SRC).unwrap();
+        let summary: Vec<_> = outline
+            .iter()
+            .map(|decl| (decl.kind().as_str(), decl.name(), decl.signature()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("fn", "Add", "(Int x, Int y) Int"),
+                ("struct", "Pair", "<T> where T: Show"),
+                ("const", "XS", "[Int]"),
+                ("type", "Id", ""),
+                ("var", "count", "Int"),
+                ("def", "Pair", ""),
+                ("fn", "Max", "<T>(T a, T b) T where T: Ord"),
+                ("fn", "Twice", "(T x) T"),
+                ("fn", "Id", "(Int x) Int"),
+            ]
+        );
+
+        // The same declarations, in the same places, as a full parse finds
+        let table = SymbolTable::new(&parse(SRC).unwrap());
+        assert_eq!(table.symbols().len(), outline.len());
+        for (sym, decl) in table.symbols().iter().zip(&outline) {
+            assert_eq!(
+                (sym.kind(), sym.name(), sym.span()),
+                (decl.kind(), decl.name(), decl.span())
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_outline_errors() {
+        let err = parse_outline("42").unwrap_err();
+        assert_eq!(err.to_string(), "expected a declaration");
+        let err = parse_outline("const Int X;").unwrap_err();
+        assert_eq!(err.to_string(), "expected `=`");
+        assert!(parse_outline("f (").is_err());
+    }
+}
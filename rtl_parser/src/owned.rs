@@ -0,0 +1,748 @@
+use proc_macro2::Span;
+use syn::{Ident, Lit};
+
+use crate::{
+    Rattle, RtlArrayLen, RtlAttr, RtlBinOp, RtlBody, RtlDeclValue, RtlElse, RtlExpr, RtlFn, RtlIf,
+    RtlIfLet, RtlMatchArm, RtlPat, RtlStmt, RtlType,
+};
+
+// A copy of a parsed program that holds no proc-macro2 tokens, so it's `Send`
+// and `Sync` and can be shared with worker threads. It's plain data: names and
+// literals are strings paired with where they started in the source.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "bincode", derive(serde::Serialize, serde::Deserialize))]
+pub struct OwnedRattle {
+    pub decls: Vec<OwnedDecl>,
+    // Each import's path, then its alias
+    pub imports: Vec<(Vec<OwnedIdent>, Option<OwnedIdent>)>,
+}
+
+// Where a token started; lines count from 1 and columns from 0
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "bincode", derive(serde::Serialize, serde::Deserialize))]
+pub struct SpanInfo {
+    pub line: usize,
+    pub column: usize,
+}
+
+pub type OwnedIdent = (String, SpanInfo);
+
+// A literal as written in the source, like `"hi"` or `5i32`
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "bincode", derive(serde::Serialize, serde::Deserialize))]
+pub struct OwnedLit {
+    pub text: String,
+    pub span: SpanInfo,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "bincode", derive(serde::Serialize, serde::Deserialize))]
+pub enum OwnedDecl {
+    Fn(OwnedFn),
+    Const(OwnedVar),
+    Var(OwnedVar),
+    Static(OwnedVar),
+    Struct(OwnedStruct),
+    Enum {
+        name: OwnedIdent,
+        // Each variant's attributes, name and payload types
+        variants: Vec<(Vec<OwnedAttr>, OwnedIdent, Vec<OwnedType>)>,
+    },
+    Def {
+        struct_name: OwnedIdent,
+        defs: Vec<OwnedFn>,
+        def_for: Option<OwnedIdent>,
+    },
+    Trait {
+        name: OwnedIdent,
+        methods: Vec<OwnedFn>,
+    },
+    Gen {
+        // A template's type parameters, and the types they expand over
+        params: Vec<OwnedIdent>,
+        over: Vec<OwnedType>,
+        methods: Vec<OwnedFn>,
+    },
+    StaticAssert {
+        cond: OwnedExpr,
+        msg: Option<String>,
+    },
+    TypeAlias {
+        name: OwnedIdent,
+        target: OwnedType,
+    },
+    // A declaration parsed by an extension, as its keyword and tokens
+    Custom {
+        keyword: String,
+        tokens: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "bincode", derive(serde::Serialize, serde::Deserialize))]
+pub struct OwnedFn {
+    pub name: OwnedIdent,
+    pub generics: Vec<OwnedIdent>,
+    pub const_generics: Vec<OwnedIdent>,
+    pub constraints: Vec<(OwnedIdent, Vec<OwnedIdent>)>,
+    pub args: Vec<OwnedFnArg>,
+    pub ret: OwnedType,
+    pub body: Option<Vec<OwnedStmt>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "bincode", derive(serde::Serialize, serde::Deserialize))]
+pub struct OwnedFnArg {
+    pub ty: OwnedType,
+    pub name: OwnedIdent,
+    pub default: Option<OwnedExpr>,
+}
+
+// A constant, variable or static; constants are never `is_mut`
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "bincode", derive(serde::Serialize, serde::Deserialize))]
+pub struct OwnedVar {
+    pub name: OwnedIdent,
+    pub ty: OwnedType,
+    pub is_mut: bool,
+    pub data: OwnedExpr,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "bincode", derive(serde::Serialize, serde::Deserialize))]
+pub struct OwnedStruct {
+    pub name: OwnedIdent,
+    pub generics: Vec<OwnedIdent>,
+    pub const_generics: Vec<OwnedIdent>,
+    pub constraints: Vec<(OwnedIdent, Vec<OwnedIdent>)>,
+    // Each field's attributes, whether it's `pub`, type and name
+    pub fields: Vec<(Vec<OwnedAttr>, bool, OwnedType, OwnedIdent)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "bincode", derive(serde::Serialize, serde::Deserialize))]
+pub struct OwnedAttr {
+    pub name: OwnedIdent,
+    pub args: Vec<OwnedExpr>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "bincode", derive(serde::Serialize, serde::Deserialize))]
+pub enum OwnedType {
+    Named(OwnedIdent),
+    Array(Box<OwnedType>),
+    FixedArray(Box<OwnedType>, OwnedArrayLen),
+    Path(Vec<OwnedIdent>),
+    Optional(Box<OwnedType>),
+    Ref {
+        mutable: bool,
+        inner: Box<OwnedType>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "bincode", derive(serde::Serialize, serde::Deserialize))]
+pub enum OwnedArrayLen {
+    Lit(usize),
+    Param(OwnedIdent),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "bincode", derive(serde::Serialize, serde::Deserialize))]
+pub enum OwnedExpr {
+    Lit(OwnedLit),
+    Ident(OwnedIdent),
+    Array(Vec<OwnedExpr>),
+    Block {
+        stmts: Vec<OwnedStmt>,
+        value: Option<Box<OwnedExpr>>,
+    },
+    Repeat {
+        value: Box<OwnedExpr>,
+        count: usize,
+    },
+    Field {
+        base: Box<OwnedExpr>,
+        field: OwnedIdent,
+    },
+    Index {
+        base: Box<OwnedExpr>,
+        index: Box<OwnedExpr>,
+    },
+    Range {
+        start: Option<Box<OwnedExpr>>,
+        end: Option<Box<OwnedExpr>>,
+    },
+    Slice {
+        base: Box<OwnedExpr>,
+        range: Box<OwnedExpr>,
+    },
+    Binary {
+        op: RtlBinOp,
+        lhs: Box<OwnedExpr>,
+        rhs: Box<OwnedExpr>,
+    },
+    Conditional {
+        cond: Box<OwnedExpr>,
+        then: Box<OwnedExpr>,
+        else_branch: Box<OwnedExpr>,
+    },
+    StructLit {
+        name: OwnedIdent,
+        fields: Vec<(OwnedIdent, OwnedExpr)>,
+        spread: Option<Box<OwnedExpr>>,
+    },
+    Cast {
+        expr: Box<OwnedExpr>,
+        ty: OwnedType,
+    },
+    Call {
+        name: OwnedIdent,
+        args: Vec<OwnedExpr>,
+    },
+    Spread(Box<OwnedExpr>),
+    None(SpanInfo),
+    Some(Box<OwnedExpr>),
+    Match {
+        scrutinee: Box<OwnedExpr>,
+        arms: Vec<OwnedMatchArm>,
+    },
+    Loop {
+        label: Option<OwnedIdent>,
+        stmts: Vec<OwnedStmt>,
+        value: Option<Box<OwnedExpr>>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "bincode", derive(serde::Serialize, serde::Deserialize))]
+pub struct OwnedMatchArm {
+    pub pat: OwnedPat,
+    pub bindings: Vec<(OwnedIdent, OwnedExpr)>,
+    pub guard: Option<OwnedExpr>,
+    pub body: OwnedExpr,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "bincode", derive(serde::Serialize, serde::Deserialize))]
+pub enum OwnedPat {
+    Wild,
+    Lit(OwnedLit),
+    Binding(OwnedIdent),
+    Struct {
+        name: OwnedIdent,
+        fields: Vec<OwnedIdent>,
+    },
+    Variant {
+        name: OwnedIdent,
+        fields: Vec<OwnedPat>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "bincode", derive(serde::Serialize, serde::Deserialize))]
+pub enum OwnedStmt {
+    Var(OwnedVar),
+    Const(OwnedVar),
+    Assign {
+        target: OwnedExpr,
+        value: OwnedExpr,
+    },
+    Return(Option<OwnedExpr>),
+    Expr(OwnedExpr),
+    Fn(OwnedFn),
+    IfLet(OwnedIfLet),
+    If(OwnedIf),
+    While {
+        cond: OwnedExpr,
+        body: Vec<OwnedStmt>,
+    },
+    Defer(OwnedExpr),
+    Break {
+        label: Option<OwnedIdent>,
+        value: Option<OwnedExpr>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "bincode", derive(serde::Serialize, serde::Deserialize))]
+pub struct OwnedIfLet {
+    pub pat: OwnedPat,
+    pub scrutinee: OwnedExpr,
+    pub then: Vec<OwnedStmt>,
+    pub else_branch: Option<OwnedElse>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "bincode", derive(serde::Serialize, serde::Deserialize))]
+pub struct OwnedIf {
+    pub cond: OwnedExpr,
+    pub then: Vec<OwnedStmt>,
+    pub else_branch: Option<OwnedElse>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "bincode", derive(serde::Serialize, serde::Deserialize))]
+pub enum OwnedElse {
+    IfLet(Box<OwnedIfLet>),
+    If(Box<OwnedIf>),
+    Block(Vec<OwnedStmt>),
+}
+
+impl Rattle {
+    // Copies the program into a form that can cross threads
+    pub fn into_owned(self) -> OwnedRattle {
+        self.to_owned_rattle()
+    }
+
+    pub(crate) fn to_owned_rattle(&self) -> OwnedRattle {
+        OwnedRattle {
+            decls: self.decls.iter().map(|decl| (&decl.value).into()).collect(),
+            imports: self
+                .imports
+                .iter()
+                .map(|import| {
+                    let path = import.path.iter().map(ident).collect();
+                    (path, import.alias.as_ref().map(ident))
+                })
+                .collect(),
+        }
+    }
+}
+
+impl SpanInfo {
+    pub(crate) fn of(span: Span) -> Self {
+        let start = span.start();
+        SpanInfo {
+            line: start.line,
+            column: start.column,
+        }
+    }
+}
+
+fn ident(ident: &Ident) -> OwnedIdent {
+    (ident.to_string(), SpanInfo::of(ident.span()))
+}
+
+fn idents(idents: &[Ident]) -> Vec<OwnedIdent> {
+    idents.iter().map(ident).collect()
+}
+
+fn exprs(exprs: &[RtlExpr]) -> Vec<OwnedExpr> {
+    exprs.iter().map(OwnedExpr::from).collect()
+}
+
+fn body(body: &RtlBody) -> Vec<OwnedStmt> {
+    body.stmts.iter().map(OwnedStmt::from).collect()
+}
+
+fn constraints(constraints: &[(Ident, Vec<Ident>)]) -> Vec<(OwnedIdent, Vec<OwnedIdent>)> {
+    constraints
+        .iter()
+        .map(|(param, bounds)| (ident(param), idents(bounds)))
+        .collect()
+}
+
+// A literal's source text, suffix included
+pub(crate) fn lit_text(lit: &Lit) -> String {
+    match lit {
+        Lit::Str(s) => s.token().to_string(),
+        Lit::ByteStr(s) => s.token().to_string(),
+        Lit::CStr(s) => s.token().to_string(),
+        Lit::Byte(b) => b.token().to_string(),
+        Lit::Char(c) => c.token().to_string(),
+        Lit::Int(i) => i.token().to_string(),
+        Lit::Float(f) => f.token().to_string(),
+        Lit::Bool(b) => b.value.to_string(),
+        Lit::Verbatim(v) => v.to_string(),
+        other => format!("{:?}", other),
+    }
+}
+
+impl From<&Lit> for OwnedLit {
+    fn from(lit: &Lit) -> Self {
+        OwnedLit {
+            text: lit_text(lit),
+            span: SpanInfo::of(lit.span()),
+        }
+    }
+}
+
+impl From<&RtlDeclValue> for OwnedDecl {
+    fn from(value: &RtlDeclValue) -> Self {
+        match value {
+            RtlDeclValue::RtlFn(func) => OwnedDecl::Fn(func.into()),
+            RtlDeclValue::RtlConst(c) => OwnedDecl::Const(OwnedVar {
+                name: ident(&c.name),
+                ty: (&c.ty).into(),
+                is_mut: false,
+                data: (&c.data).into(),
+            }),
+            RtlDeclValue::RtlVar(var) => OwnedDecl::Var(OwnedVar {
+                name: ident(&var.name),
+                ty: (&var.ty).into(),
+                is_mut: var.is_mut,
+                data: (&var.data).into(),
+            }),
+            RtlDeclValue::RtlStatic(st) => OwnedDecl::Static(OwnedVar {
+                name: ident(&st.name),
+                ty: (&st.ty).into(),
+                is_mut: st.is_mut,
+                data: (&st.data).into(),
+            }),
+            RtlDeclValue::RtlStruct(st) => OwnedDecl::Struct(OwnedStruct {
+                name: ident(&st.name),
+                generics: idents(&st.generics),
+                const_generics: idents(&st.const_generics),
+                constraints: constraints(&st.constraints),
+                fields: st
+                    .fields
+                    .iter()
+                    .map(|field| {
+                        let attrs = field.attrs.iter().map(OwnedAttr::from).collect();
+                        let ty = (&field.ty).into();
+                        (attrs, field.is_public, ty, ident(&field.name))
+                    })
+                    .collect(),
+            }),
+            RtlDeclValue::RtlEnum(en) => OwnedDecl::Enum {
+                name: ident(&en.name),
+                variants: en
+                    .variants
+                    .iter()
+                    .map(|variant| {
+                        let attrs = variant.attrs.iter().map(OwnedAttr::from).collect();
+                        let fields = variant.fields.iter().map(OwnedType::from).collect();
+                        (attrs, ident(&variant.name), fields)
+                    })
+                    .collect(),
+            },
+            RtlDeclValue::RtlDef(def) => OwnedDecl::Def {
+                struct_name: ident(&def.struct_name),
+                defs: def.defs.iter().map(OwnedFn::from).collect(),
+                def_for: def.def_for.as_ref().map(ident),
+            },
+            RtlDeclValue::RtlTrait(tr) => OwnedDecl::Trait {
+                name: ident(&tr.name),
+                methods: tr.methods.iter().map(OwnedFn::from).collect(),
+            },
+            RtlDeclValue::RtlGen(gen) => OwnedDecl::Gen {
+                params: idents(&gen.params),
+                over: gen.over.iter().map(OwnedType::from).collect(),
+                methods: gen.methods.iter().map(OwnedFn::from).collect(),
+            },
+            RtlDeclValue::RtlStaticAssert(assert) => OwnedDecl::StaticAssert {
+                cond: (&assert.cond).into(),
+                msg: assert.msg(),
+            },
+            RtlDeclValue::RtlTypeAlias(alias) => OwnedDecl::TypeAlias {
+                name: ident(&alias.name),
+                target: (&alias.target).into(),
+            },
+            RtlDeclValue::Custom(custom) => OwnedDecl::Custom {
+                keyword: custom.keyword().to_string(),
+                tokens: custom.tokens().to_string(),
+            },
+        }
+    }
+}
+
+impl From<&RtlFn> for OwnedFn {
+    fn from(func: &RtlFn) -> Self {
+        OwnedFn {
+            name: ident(&func.name),
+            generics: idents(&func.generics),
+            const_generics: idents(&func.const_generics),
+            constraints: constraints(&func.constraints),
+            args: func
+                .args
+                .iter()
+                .map(|arg| OwnedFnArg {
+                    ty: (&arg.ty).into(),
+                    name: ident(&arg.name),
+                    default: arg.default.as_ref().map(OwnedExpr::from),
+                })
+                .collect(),
+            ret: (&func.ret).into(),
+            body: func.body.as_ref().map(body),
+        }
+    }
+}
+
+impl From<&RtlAttr> for OwnedAttr {
+    fn from(attr: &RtlAttr) -> Self {
+        OwnedAttr {
+            name: ident(&attr.name),
+            args: exprs(&attr.args),
+        }
+    }
+}
+
+impl From<&RtlType> for OwnedType {
+    fn from(ty: &RtlType) -> Self {
+        match ty {
+            RtlType::Named(name) => OwnedType::Named(ident(name)),
+            RtlType::Path(segments) => OwnedType::Path(segments.iter().map(ident).collect()),
+            RtlType::Array(inner) => OwnedType::Array(Box::new((&**inner).into())),
+            RtlType::FixedArray(inner, len) => {
+                let len = match len {
+                    RtlArrayLen::Lit(len) => OwnedArrayLen::Lit(*len),
+                    RtlArrayLen::Param(name) => OwnedArrayLen::Param(ident(name)),
+                };
+                OwnedType::FixedArray(Box::new((&**inner).into()), len)
+            }
+            RtlType::Optional(inner) => OwnedType::Optional(Box::new((&**inner).into())),
+            RtlType::Ref { mutable, inner } => OwnedType::Ref {
+                mutable: *mutable,
+                inner: Box::new((&**inner).into()),
+            },
+        }
+    }
+}
+
+impl From<&RtlExpr> for OwnedExpr {
+    fn from(expr: &RtlExpr) -> Self {
+        let boxed = |expr: &RtlExpr| Box::new(OwnedExpr::from(expr));
+        match expr {
+            RtlExpr::Lit(lit) => OwnedExpr::Lit(lit.into()),
+            RtlExpr::Ident(name) => OwnedExpr::Ident(ident(name)),
+            RtlExpr::Array(elems) => OwnedExpr::Array(exprs(elems)),
+            RtlExpr::Block(block) => OwnedExpr::Block {
+                stmts: body(block),
+                value: block.value.as_deref().map(|value| Box::new(value.into())),
+            },
+            RtlExpr::Repeat { value, count } => OwnedExpr::Repeat {
+                value: boxed(value),
+                count: *count,
+            },
+            RtlExpr::Field { base, field } => OwnedExpr::Field {
+                base: boxed(base),
+                field: ident(field),
+            },
+            RtlExpr::Index { base, index } => OwnedExpr::Index {
+                base: boxed(base),
+                index: boxed(index),
+            },
+            RtlExpr::Range { start, end } => OwnedExpr::Range {
+                start: start.as_deref().map(boxed),
+                end: end.as_deref().map(boxed),
+            },
+            RtlExpr::Slice { base, range } => OwnedExpr::Slice {
+                base: boxed(base),
+                range: boxed(range),
+            },
+            RtlExpr::Binary { op, lhs, rhs } => OwnedExpr::Binary {
+                op: *op,
+                lhs: boxed(lhs),
+                rhs: boxed(rhs),
+            },
+            RtlExpr::Conditional {
+                cond,
+                then,
+                else_branch,
+            } => OwnedExpr::Conditional {
+                cond: boxed(cond),
+                then: boxed(then),
+                else_branch: boxed(else_branch),
+            },
+            RtlExpr::StructLit {
+                name,
+                fields,
+                spread,
+            } => OwnedExpr::StructLit {
+                name: ident(name),
+                fields: fields
+                    .iter()
+                    .map(|(field, value)| (ident(field), value.into()))
+                    .collect(),
+                spread: spread.as_deref().map(boxed),
+            },
+            RtlExpr::Cast { expr, ty } => OwnedExpr::Cast {
+                expr: boxed(expr),
+                ty: ty.into(),
+            },
+            RtlExpr::Call { name, args } => OwnedExpr::Call {
+                name: ident(name),
+                args: exprs(args),
+            },
+            RtlExpr::Spread(expr) => OwnedExpr::Spread(boxed(expr)),
+            RtlExpr::None(kw) => OwnedExpr::None(SpanInfo::of(kw.span)),
+            RtlExpr::Some(expr) => OwnedExpr::Some(boxed(expr)),
+            RtlExpr::Match { scrutinee, arms } => OwnedExpr::Match {
+                scrutinee: boxed(scrutinee),
+                arms: arms.iter().map(OwnedMatchArm::from).collect(),
+            },
+            RtlExpr::Loop { label, body: block } => OwnedExpr::Loop {
+                label: label.as_ref().map(ident),
+                stmts: body(block),
+                value: block.value.as_deref().map(|value| Box::new(value.into())),
+            },
+        }
+    }
+}
+
+impl From<&RtlMatchArm> for OwnedMatchArm {
+    fn from(arm: &RtlMatchArm) -> Self {
+        OwnedMatchArm {
+            pat: (&arm.pat).into(),
+            bindings: arm
+                .bindings
+                .iter()
+                .map(|(name, value)| (ident(name), value.into()))
+                .collect(),
+            guard: arm.guard.as_ref().map(OwnedExpr::from),
+            body: (&arm.body).into(),
+        }
+    }
+}
+
+impl From<&RtlPat> for OwnedPat {
+    fn from(pat: &RtlPat) -> Self {
+        match pat {
+            RtlPat::Wild => OwnedPat::Wild,
+            RtlPat::Lit(lit) => OwnedPat::Lit(lit.into()),
+            RtlPat::Binding(name) => OwnedPat::Binding(ident(name)),
+            RtlPat::Struct { name, fields } => OwnedPat::Struct {
+                name: ident(name),
+                fields: idents(fields),
+            },
+            RtlPat::Variant { name, fields } => OwnedPat::Variant {
+                name: ident(name),
+                fields: fields.iter().map(OwnedPat::from).collect(),
+            },
+        }
+    }
+}
+
+impl From<&RtlStmt> for OwnedStmt {
+    fn from(stmt: &RtlStmt) -> Self {
+        match stmt {
+            RtlStmt::Var(var) => OwnedStmt::Var(OwnedVar {
+                name: ident(&var.name),
+                ty: (&var.ty).into(),
+                is_mut: var.is_mut,
+                data: (&var.data).into(),
+            }),
+            RtlStmt::Const(c) => OwnedStmt::Const(OwnedVar {
+                name: ident(&c.name),
+                ty: (&c.ty).into(),
+                is_mut: false,
+                data: (&c.data).into(),
+            }),
+            RtlStmt::Assign { target, value } => OwnedStmt::Assign {
+                target: target.into(),
+                value: value.into(),
+            },
+            RtlStmt::Return(value) => OwnedStmt::Return(value.as_ref().map(OwnedExpr::from)),
+            RtlStmt::Expr(expr) => OwnedStmt::Expr(expr.into()),
+            RtlStmt::Fn(func) => OwnedStmt::Fn(func.into()),
+            RtlStmt::IfLet(if_let) => OwnedStmt::IfLet(if_let.into()),
+            RtlStmt::If(if_) => OwnedStmt::If(if_.into()),
+            RtlStmt::While { cond, body: block } => OwnedStmt::While {
+                cond: cond.into(),
+                body: body(block),
+            },
+            RtlStmt::Defer(expr) => OwnedStmt::Defer(expr.into()),
+            RtlStmt::Break { label, value } => OwnedStmt::Break {
+                label: label.as_ref().map(ident),
+                value: value.as_ref().map(OwnedExpr::from),
+            },
+        }
+    }
+}
+
+impl From<&RtlIfLet> for OwnedIfLet {
+    fn from(if_let: &RtlIfLet) -> Self {
+        OwnedIfLet {
+            pat: (&if_let.pat).into(),
+            scrutinee: (&if_let.scrutinee).into(),
+            then: body(&if_let.then),
+            else_branch: if_let.else_branch.as_ref().map(OwnedElse::from),
+        }
+    }
+}
+
+impl From<&RtlIf> for OwnedIf {
+    fn from(if_: &RtlIf) -> Self {
+        OwnedIf {
+            cond: (&if_.cond).into(),
+            then: body(&if_.then),
+            else_branch: if_.else_branch.as_ref().map(OwnedElse::from),
+        }
+    }
+}
+
+impl From<&RtlElse> for OwnedElse {
+    fn from(branch: &RtlElse) -> Self {
+        match branch {
+            RtlElse::IfLet(next) => OwnedElse::IfLet(Box::new((&**next).into())),
+            RtlElse::If(next) => OwnedElse::If(Box::new((&**next).into())),
+            RtlElse::Block(block) => OwnedElse::Block(body(block)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+    use std::thread;
+
+    #[test]
+    fn test_owned_across_threads() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<OwnedRattle>();
+
+        let owned = parse(
+            "struct Point { Int x, Int y }
+             f Sum(Point p) Int {
+                 return p.x + p.y;
+             }",
+        )
+        .unwrap()
+        .into_owned();
+
+        let names = thread::spawn(move || {
+            owned
+                .decls
+                .iter()
+                .map(|decl| match decl {
+                    OwnedDecl::Struct(st) => st.name.clone(),
+                    OwnedDecl::Fn(func) => func.name.clone(),
+                    other => panic!("unexpected declaration {:?}", other),
+                })
+                .collect::<Vec<_>>()
+        })
+        .join()
+        .unwrap();
+
+        assert_eq!(names[0].0, "Point");
+        assert_eq!(names[1].0, "Sum");
+        assert_eq!(
+            names[1].1,
+            SpanInfo {
+                line: 2,
+                column: 15
+            }
+        );
+    }
+
+    #[test]
+    fn test_owned_lits() {
+        let owned = parse(r#"const String S = "hi"; const Int N = 5i32;"#)
+            .unwrap()
+            .into_owned();
+        let texts: Vec<_> = owned
+            .decls
+            .iter()
+            .map(|decl| match decl {
+                OwnedDecl::Const(OwnedVar {
+                    data: OwnedExpr::Lit(lit),
+                    ..
+                }) => lit.text.as_str(),
+                other => panic!("expected a literal constant, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(texts, [r#""hi""#, "5i32"]);
+    }
+}
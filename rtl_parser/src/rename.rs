@@ -0,0 +1,450 @@
+use std::cell::OnceCell;
+
+use syn::Ident;
+
+use crate::{
+    Rattle, RtlArrayLen, RtlBody, RtlDeclValue, RtlElse, RtlExpr, RtlFn, RtlIf, RtlIfLet, RtlPat,
+    RtlStmt, RtlType,
+};
+
+impl Rattle {
+    // Renames the top-level name `from` to `to`: the declarations of that
+    // name, and every reference to them in types, expressions, patterns,
+    // `def` blocks and `where` clauses. An import bringing in `from` is
+    // renamed too, by its alias, or by giving it one. References to a local,
+    // parameter or type parameter named `from` are left alone, as are field
+    // and method names. Returns how many names changed.
+    //
+    // Panics if `to` isn't an identifier.
+    pub fn rename(&mut self, from: &str, to: &str) -> usize {
+        let mut renamer = Renamer {
+            from,
+            to,
+            shadowed: false,
+            count: 0,
+        };
+        for import in &mut self.imports {
+            match &mut import.alias {
+                Some(alias) => renamer.name(alias),
+                None => {
+                    if let Some(last) = import.path.last().filter(|last| *last == from) {
+                        import.alias = Some(Ident::new(to, last.span()));
+                        renamer.count += 1;
+                    }
+                }
+            }
+        }
+        for decl in &mut self.decls {
+            decl.attrs
+                .iter_mut()
+                .flat_map(|attr| &mut attr.args)
+                .for_each(|e| renamer.expr(e));
+            renamer.decl(&mut decl.value);
+        }
+        // Declarations may have been renamed, so lookups must be rebuilt
+        self.index = OnceCell::new();
+        renamer.count
+    }
+}
+
+struct Renamer<'a> {
+    from: &'a str,
+    to: &'a str,
+    // Whether a local named `from` hides the top-level one where we are
+    shadowed: bool,
+    count: usize,
+}
+
+impl Renamer<'_> {
+    fn name(&mut self, name: &mut Ident) {
+        if !self.shadowed && name == self.from {
+            *name = Ident::new(self.to, name.span());
+            self.count += 1;
+        }
+    }
+
+    fn decl(&mut self, value: &mut RtlDeclValue) {
+        match value {
+            RtlDeclValue::RtlFn(func) => {
+                self.name(&mut func.name);
+                self.func(func);
+            }
+            RtlDeclValue::RtlConst(c) => {
+                self.name(&mut c.name);
+                self.ty(&mut c.ty);
+                self.expr(&mut c.data);
+            }
+            RtlDeclValue::RtlVar(var) => {
+                self.name(&mut var.name);
+                self.ty(&mut var.ty);
+                self.expr(&mut var.data);
+            }
+            RtlDeclValue::RtlStatic(st) => {
+                self.name(&mut st.name);
+                self.ty(&mut st.ty);
+                self.expr(&mut st.data);
+            }
+            RtlDeclValue::RtlStruct(st) => {
+                self.name(&mut st.name);
+                let outer = self.enter_generics(&st.generics, &st.const_generics);
+                self.constraints(&mut st.constraints);
+                for field in &mut st.fields {
+                    field
+                        .attrs
+                        .iter_mut()
+                        .flat_map(|attr| &mut attr.args)
+                        .for_each(|e| self.expr(e));
+                    self.ty(&mut field.ty);
+                }
+                self.shadowed = outer;
+            }
+            RtlDeclValue::RtlEnum(en) => {
+                self.name(&mut en.name);
+                for variant in &mut en.variants {
+                    variant
+                        .attrs
+                        .iter_mut()
+                        .flat_map(|attr| &mut attr.args)
+                        .for_each(|e| self.expr(e));
+                    variant.fields.iter_mut().for_each(|ty| self.ty(ty));
+                }
+            }
+            RtlDeclValue::RtlDef(def) => {
+                self.name(&mut def.struct_name);
+                if let Some(tr) = &mut def.def_for {
+                    self.name(tr);
+                }
+                def.defs.iter_mut().for_each(|func| self.func(func));
+            }
+            RtlDeclValue::RtlTrait(tr) => {
+                self.name(&mut tr.name);
+                tr.methods.iter_mut().for_each(|func| self.func(func));
+            }
+            RtlDeclValue::RtlGen(gen) => {
+                gen.over.iter_mut().for_each(|ty| self.ty(ty));
+                for func in &mut gen.methods {
+                    self.name(&mut func.name);
+                    let outer = self.enter_generics(&gen.params, &[]);
+                    self.func(func);
+                    self.shadowed = outer;
+                }
+            }
+            RtlDeclValue::RtlStaticAssert(assert) => self.expr(&mut assert.cond),
+            RtlDeclValue::RtlTypeAlias(alias) => {
+                self.name(&mut alias.name);
+                self.ty(&mut alias.target);
+            }
+            // Its tokens are the extension's, and can't be rewritten
+            RtlDeclValue::Custom(_) => {}
+        }
+    }
+
+    // Hides the top-level `from` if a type or const parameter takes its
+    // name, returning whether it was hidden before
+    fn enter_generics(&mut self, generics: &[Ident], const_generics: &[Ident]) -> bool {
+        let outer = self.shadowed;
+        self.shadowed |= generics
+            .iter()
+            .chain(const_generics)
+            .any(|g| g == self.from);
+        outer
+    }
+
+    fn constraints(&mut self, constraints: &mut [(Ident, Vec<Ident>)]) {
+        for (_, bounds) in constraints {
+            bounds.iter_mut().for_each(|bound| self.name(bound));
+        }
+    }
+
+    // Renames in a function's signature and body, but not its name, which
+    // may be a method's or a local function's
+    fn func(&mut self, func: &mut RtlFn) {
+        let outer = self.enter_generics(&func.generics, &func.const_generics);
+        self.constraints(&mut func.constraints);
+        for arg in &mut func.args {
+            self.ty(&mut arg.ty);
+            if let Some(default) = &mut arg.default {
+                self.expr(default);
+            }
+        }
+        self.ty(&mut func.ret);
+        if func.args.iter().any(|arg| arg.name == self.from) {
+            self.shadowed = true;
+        }
+        if let Some(body) = &mut func.body {
+            self.body(body);
+        }
+        self.shadowed = outer;
+    }
+
+    fn ty(&mut self, ty: &mut RtlType) {
+        match ty {
+            RtlType::Named(name) => self.name(name),
+            // A path names a declaration of another module
+            RtlType::Path(_) => {}
+            RtlType::Array(inner) | RtlType::Optional(inner) | RtlType::Ref { inner, .. } => {
+                self.ty(inner)
+            }
+            RtlType::FixedArray(inner, len) => {
+                self.ty(inner);
+                if let RtlArrayLen::Param(name) = len {
+                    self.name(name);
+                }
+            }
+        }
+    }
+
+    fn body(&mut self, body: &mut RtlBody) {
+        let outer = self.shadowed;
+        body.stmts.iter_mut().for_each(|stmt| self.stmt(stmt));
+        if let Some(value) = &mut body.value {
+            self.expr(value);
+        }
+        self.shadowed = outer;
+    }
+
+    // Renames in a statement, then hides the top-level `from` for the rest
+    // of the body if the statement declares a local of that name
+    fn stmt(&mut self, stmt: &mut RtlStmt) {
+        match stmt {
+            RtlStmt::Var(var) => {
+                self.ty(&mut var.ty);
+                self.expr(&mut var.data);
+                self.shadowed |= var.name == self.from;
+            }
+            RtlStmt::Const(c) => {
+                self.ty(&mut c.ty);
+                self.expr(&mut c.data);
+                self.shadowed |= c.name == self.from;
+            }
+            RtlStmt::Assign { target, value } => {
+                self.expr(target);
+                self.expr(value);
+            }
+            RtlStmt::Return(value) | RtlStmt::Break { value, .. } => {
+                if let Some(value) = value {
+                    self.expr(value);
+                }
+            }
+            RtlStmt::Expr(expr) | RtlStmt::Defer(expr) => self.expr(expr),
+            // A local function is visible in its own body, for recursion
+            RtlStmt::Fn(func) => {
+                self.shadowed |= func.name == self.from;
+                self.func(func);
+            }
+            RtlStmt::IfLet(if_let) => self.if_let(if_let),
+            RtlStmt::If(if_) => self.if_(if_),
+            RtlStmt::While { cond, body } => {
+                self.expr(cond);
+                self.body(body);
+            }
+        }
+    }
+
+    fn if_let(&mut self, if_let: &mut RtlIfLet) {
+        self.expr(&mut if_let.scrutinee);
+        let outer = self.shadowed;
+        self.pat(&mut if_let.pat);
+        self.shadowed |= binds(&if_let.pat, self.from);
+        self.body(&mut if_let.then);
+        self.shadowed = outer;
+        self.else_branch(if_let.else_branch.as_mut());
+    }
+
+    fn if_(&mut self, if_: &mut RtlIf) {
+        self.expr(&mut if_.cond);
+        self.body(&mut if_.then);
+        self.else_branch(if_.else_branch.as_mut());
+    }
+
+    fn else_branch(&mut self, branch: Option<&mut RtlElse>) {
+        match branch {
+            Some(RtlElse::IfLet(next)) => self.if_let(next),
+            Some(RtlElse::If(next)) => self.if_(next),
+            Some(RtlElse::Block(block)) => self.body(block),
+            None => {}
+        }
+    }
+
+    // Renames the struct and variant names a pattern matches, not the
+    // names it binds
+    fn pat(&mut self, pat: &mut RtlPat) {
+        match pat {
+            RtlPat::Struct { name, .. } => self.name(name),
+            RtlPat::Variant { name, fields } => {
+                self.name(name);
+                fields.iter_mut().for_each(|pat| self.pat(pat));
+            }
+            RtlPat::Wild | RtlPat::Lit(_) | RtlPat::Binding(_) => {}
+        }
+    }
+
+    fn expr(&mut self, expr: &mut RtlExpr) {
+        match expr {
+            RtlExpr::Lit(_) | RtlExpr::None(_) => {}
+            RtlExpr::Ident(name) => self.name(name),
+            RtlExpr::Array(elems) => elems.iter_mut().for_each(|elem| self.expr(elem)),
+            RtlExpr::Block(block) | RtlExpr::Loop { body: block, .. } => self.body(block),
+            RtlExpr::Repeat { value, .. } => self.expr(value),
+            RtlExpr::Field { base, .. } => self.expr(base),
+            RtlExpr::Index { base, index } => {
+                self.expr(base);
+                self.expr(index);
+            }
+            RtlExpr::Range { start, end } => {
+                start
+                    .iter_mut()
+                    .chain(end)
+                    .for_each(|bound| self.expr(bound));
+            }
+            RtlExpr::Slice { base, range } => {
+                self.expr(base);
+                self.expr(range);
+            }
+            RtlExpr::Binary { lhs, rhs, .. } => {
+                self.expr(lhs);
+                self.expr(rhs);
+            }
+            RtlExpr::Conditional {
+                cond,
+                then,
+                else_branch,
+            } => {
+                self.expr(cond);
+                self.expr(then);
+                self.expr(else_branch);
+            }
+            RtlExpr::StructLit {
+                name,
+                fields,
+                spread,
+            } => {
+                self.name(name);
+                fields.iter_mut().for_each(|(_, value)| self.expr(value));
+                spread.iter_mut().for_each(|base| self.expr(base));
+            }
+            RtlExpr::Cast { expr, ty } => {
+                self.expr(expr);
+                self.ty(ty);
+            }
+            RtlExpr::Some(value) | RtlExpr::Spread(value) => self.expr(value),
+            RtlExpr::Call { name, args } => {
+                self.name(name);
+                args.iter_mut().for_each(|arg| self.expr(arg));
+            }
+            RtlExpr::Match { scrutinee, arms } => {
+                self.expr(scrutinee);
+                for arm in arms {
+                    let outer = self.shadowed;
+                    self.pat(&mut arm.pat);
+                    self.shadowed |= binds(&arm.pat, self.from);
+                    for (name, value) in &mut arm.bindings {
+                        self.expr(value);
+                        self.shadowed |= *name == self.from;
+                    }
+                    if let Some(guard) = &mut arm.guard {
+                        self.expr(guard);
+                    }
+                    self.expr(&mut arm.body);
+                    self.shadowed = outer;
+                }
+            }
+        }
+    }
+}
+
+// Whether a pattern binds a local named `name`
+fn binds(pat: &RtlPat, name: &str) -> bool {
+    match pat {
+        RtlPat::Binding(binding) => binding == name,
+        RtlPat::Struct { fields, .. } => fields.iter().any(|field| field == name),
+        RtlPat::Variant { fields, .. } => fields.iter().any(|pat| binds(pat, name)),
+        RtlPat::Wild | RtlPat::Lit(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{parse, RtlDeclValue, RtlExpr, RtlStmt};
+
+    #[test]
+    fn test_rename() {
+        let mut rattle = parse(
+            "import ::util::Helper;
+             f Square(Int x) Int { return x * x; }
+             f Sum(Int a, Int b) Int { return Square(a) + Square(b); }
+             const Int Four = Square(2);
+             f Shadow(Int Square) Int { return Square; }
+             f Local(Int n) Int {
+                 var Int y = Square(n);
+                 f Square(Int x) Int { return x; }
+                 return Square(y);
+             }
+             f Arm(Int? v) Int { return match v { some(Square) => Square, none => Square(1) }; }",
+        )
+        .unwrap();
+
+        // The declaration, its four calls outside of shadowing, and none of
+        // the locals named `Square`
+        assert_eq!(rattle.rename("Square", "Sq"), 6);
+        assert!(rattle.find_fn("Square").is_none());
+        assert_eq!(rattle.find_fn("Sq").unwrap().args().len(), 1);
+        let expected = parse(
+            "import ::util::Helper;
+             f Sq(Int x) Int { return x * x; }
+             f Sum(Int a, Int b) Int { return Sq(a) + Sq(b); }
+             const Int Four = Sq(2);
+             f Shadow(Int Square) Int { return Square; }
+             f Local(Int n) Int {
+                 var Int y = Sq(n);
+                 f Square(Int x) Int { return x; }
+                 return Square(y);
+             }
+             f Arm(Int? v) Int { return match v { some(Square) => Square, none => Sq(1) }; }",
+        )
+        .unwrap();
+        assert!(rattle == expected);
+
+        // An import is renamed by giving it an alias
+        assert_eq!(rattle.rename("Helper", "Aid"), 1);
+        assert_eq!(rattle.imports()[0].alias().unwrap(), "Aid");
+        assert_eq!(rattle.rename("Missing", "Other"), 0);
+    }
+
+    #[test]
+    fn test_rename_types() {
+        let mut rattle = parse(
+            "struct Point { Int x, Int y }
+             trait Shape { f Area(This this) Int; }
+             def Point { f Area(This this) Int { return this.x * this.y; } } for Shape;
+             f Origin() Point { return Point { x: 0, y: 0 }; }
+             f Flip(Point p) [Point] { if let Point { x, y } = p { return [Point { x: y, y: x }]; } return []; }
+             f Id<Point>(Point p) Point { return p; }
+             f Biggest<T>(T a) T where T: Shape { return a; }",
+        )
+        .unwrap();
+
+        assert_eq!(rattle.rename("Point", "Vec2"), 8);
+        assert!(rattle.find_struct("Vec2").is_some());
+        let RtlDeclValue::RtlDef(def) = rattle.decls()[2].value() else {
+            panic!("expected a def");
+        };
+        assert_eq!(def.struct_name(), "Vec2");
+        // A type parameter named `Point` hides the struct
+        let id = rattle.find_fn("Id").unwrap();
+        assert_eq!(id.args()[0].ty(), &syn::parse_str("Point").unwrap());
+
+        assert_eq!(rattle.rename("Shape", "Area"), 3);
+        let RtlDeclValue::RtlDef(def) = rattle.decls()[2].value() else {
+            panic!("expected a def");
+        };
+        assert_eq!(def.def_for().unwrap(), "Area");
+        // Field and method names aren't renamed
+        assert_eq!(rattle.rename("x", "z"), 0);
+        let body = rattle.find_fn("Origin").unwrap().body().unwrap();
+        let [RtlStmt::Return(Some(RtlExpr::StructLit { fields, .. }))] = body.stmts() else {
+            panic!("expected a struct literal");
+        };
+        assert_eq!(fields[0].0, "x");
+    }
+}
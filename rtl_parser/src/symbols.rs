@@ -0,0 +1,197 @@
+use std::fmt::Write;
+
+use syn::Ident;
+
+use crate::{Rattle, RtlDeclValue, RtlFn, SpanInfo};
+
+// The kinds of top-level symbols a program declares
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RtlSymbolKind {
+    Fn,
+    Const,
+    Var,
+    Static,
+    Struct,
+    Enum,
+    Def,
+    Trait,
+    Type,
+}
+
+impl RtlSymbolKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RtlSymbolKind::Fn => "fn",
+            RtlSymbolKind::Const => "const",
+            RtlSymbolKind::Var => "var",
+            RtlSymbolKind::Static => "static",
+            RtlSymbolKind::Struct => "struct",
+            RtlSymbolKind::Enum => "enum",
+            RtlSymbolKind::Def => "def",
+            RtlSymbolKind::Trait => "trait",
+            RtlSymbolKind::Type => "type",
+        }
+    }
+}
+
+// A declared name and where it was declared
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RtlSymbol {
+    kind: RtlSymbolKind,
+    name: String,
+    span: SpanInfo,
+}
+
+impl RtlSymbol {
+    pub(crate) fn new(kind: RtlSymbolKind, name: &Ident) -> Self {
+        RtlSymbol {
+            kind,
+            name: name.to_string(),
+            span: SpanInfo::of(name.span()),
+        }
+    }
+
+    pub fn kind(&self) -> RtlSymbolKind {
+        self.kind
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn span(&self) -> SpanInfo {
+        self.span
+    }
+}
+
+// The top-level symbols of a program, in declaration order. A `def` block is
+// listed under the struct it defines methods for, and the methods of a `gen`
+// block are listed as functions.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SymbolTable {
+    symbols: Vec<RtlSymbol>,
+}
+
+// A top-level item a symbol names: a whole declaration, or one method of a
+// `gen` block
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum RtlItem<'a> {
+    Decl(&'a RtlDeclValue),
+    GenFn(&'a RtlFn),
+}
+
+// The kind and name of a declaration, or `None` for a `gen` block, which has
+// no name of its own, or a custom declaration, whose name the parser can't know
+pub(crate) fn kind_and_name(value: &RtlDeclValue) -> Option<(RtlSymbolKind, &Ident)> {
+    Some(match value {
+        RtlDeclValue::RtlFn(func) => (RtlSymbolKind::Fn, func.name()),
+        RtlDeclValue::RtlConst(c) => (RtlSymbolKind::Const, c.name()),
+        RtlDeclValue::RtlVar(var) => (RtlSymbolKind::Var, var.name()),
+        RtlDeclValue::RtlStatic(st) => (RtlSymbolKind::Static, &st.name),
+        RtlDeclValue::RtlStruct(st) => (RtlSymbolKind::Struct, st.name()),
+        RtlDeclValue::RtlEnum(en) => (RtlSymbolKind::Enum, &en.name),
+        RtlDeclValue::RtlDef(def) => (RtlSymbolKind::Def, def.struct_name()),
+        RtlDeclValue::RtlTrait(tr) => (RtlSymbolKind::Trait, tr.name()),
+        RtlDeclValue::RtlTypeAlias(alias) => (RtlSymbolKind::Type, alias.name()),
+        RtlDeclValue::RtlGen(_) | RtlDeclValue::RtlStaticAssert(_) | RtlDeclValue::Custom(_) => {
+            return None
+        }
+    })
+}
+
+// Every top-level item of a program in declaration order, with its kind and name
+pub(crate) fn items(rattle: &Rattle) -> Vec<(RtlSymbolKind, &Ident, RtlItem<'_>)> {
+    let mut items = Vec::new();
+    for decl in rattle.decls() {
+        let value = decl.value();
+        match (value, kind_and_name(value)) {
+            (_, Some((kind, name))) => items.push((kind, name, RtlItem::Decl(value))),
+            (RtlDeclValue::RtlGen(gen), None) => items.extend(
+                gen.methods
+                    .iter()
+                    .map(|func| (RtlSymbolKind::Fn, func.name(), RtlItem::GenFn(func))),
+            ),
+            (_, None) => {}
+        }
+    }
+    items
+}
+
+impl SymbolTable {
+    pub fn new(rattle: &Rattle) -> Self {
+        let symbols = items(rattle)
+            .into_iter()
+            .map(|(kind, name, _)| RtlSymbol::new(kind, name))
+            .collect();
+        SymbolTable { symbols }
+    }
+
+    pub(crate) fn from_symbols(symbols: Vec<RtlSymbol>) -> Self {
+        SymbolTable { symbols }
+    }
+
+    pub fn symbols(&self) -> &[RtlSymbol] {
+        &self.symbols
+    }
+
+    // The first symbol declared with a name, other than a `def` block
+    pub fn get(&self, name: &str) -> Option<&RtlSymbol> {
+        self.symbols
+            .iter()
+            .find(|sym| sym.name == name && sym.kind != RtlSymbolKind::Def)
+    }
+
+    // A JSON array of every symbol, like
+    // `[{"kind":"fn","name":"Add","line":1,"column":2}]`, for editors' symbol search
+    pub fn to_index(&self) -> String {
+        let mut json = String::from("[");
+        for (i, sym) in self.symbols.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            // Names are identifiers, so they never need escaping
+            write!(
+                json,
+                r#"{{"kind":"{}","name":"{}","line":{},"column":{}}}"#,
+                sym.kind.as_str(),
+                sym.name,
+                sym.span.line,
+                sym.span.column
+            )
+            .unwrap();
+        }
+        json.push(']');
+        json
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    #[test]
+    fn test_to_index() {
+        let rattle = parse(
+            "struct Point { Int x, Int y }
+const Int Zero = 0;
+f Norm(Point p) Int { return p.x * p.x + p.y * p.y; }
+def Point { f Origin() This; }",
+        )
+        .unwrap();
+        let table = SymbolTable::new(&rattle);
+
+        assert_eq!(
+            table.to_index(),
+            concat!(
+                r#"[{"kind":"struct","name":"Point","line":1,"column":7},"#,
+                r#"{"kind":"const","name":"Zero","line":2,"column":10},"#,
+                r#"{"kind":"fn","name":"Norm","line":3,"column":2},"#,
+                r#"{"kind":"def","name":"Point","line":4,"column":4}]"#,
+            )
+        );
+        assert_eq!(table.get("Point").unwrap().kind(), RtlSymbolKind::Struct);
+        assert!(table.get("Missing").is_none());
+        assert_eq!(SymbolTable::default().to_index(), "[]");
+    }
+}
@@ -0,0 +1,397 @@
+use syn::Ident;
+
+use crate::{
+    RtlArrayLen, RtlBody, RtlElse, RtlExpr, RtlFn, RtlGen, RtlIf, RtlIfLet, RtlStmt, RtlType,
+};
+
+// The most copies of its functions a template may expand to, counting one per
+// way of choosing its parameters' types
+pub(crate) const MAX_GEN_COPIES: usize = 256;
+
+impl RtlGen {
+    // The functions the block stands for. A template has a copy of its
+    // functions for each way of choosing a type from `over` for each
+    // parameter, the first parameter varying slowest, with every use of a
+    // parameter as a type replaced by the type chosen for it. A copy is named
+    // after its function and the chosen types, like `Zero_Int`, and calls
+    // between the template's functions go to the copies for the same types.
+    // Plain functions stand for themselves.
+    pub fn expand(&self) -> Vec<RtlFn> {
+        let mut choices: Vec<Vec<&RtlType>> = vec![Vec::new()];
+        for _ in &self.params {
+            choices = choices
+                .iter()
+                .flat_map(|chosen| {
+                    self.over.iter().map(move |ty| {
+                        let mut chosen = chosen.clone();
+                        chosen.push(ty);
+                        chosen
+                    })
+                })
+                .collect();
+        }
+        let mut funcs = Vec::new();
+        for chosen in &choices {
+            let renames: Vec<(&Ident, Ident)> = self
+                .methods
+                .iter()
+                .map(|method| (method.name(), copy_name(method.name(), chosen)))
+                .collect();
+            for (method, (_, name)) in self.methods.iter().zip(&renames) {
+                let mut func = method.clone();
+                let mut substituter = Substituter {
+                    params: &self.params,
+                    types: chosen,
+                    renames: &renames,
+                    hidden: Vec::new(),
+                };
+                substituter.func(&mut func);
+                func.name = name.clone();
+                funcs.push(func);
+            }
+        }
+        funcs
+    }
+}
+
+// The name of a function's copy for the chosen types, or its own name when
+// there are none
+fn copy_name(name: &Ident, chosen: &[&RtlType]) -> Ident {
+    let mut copy = name.to_string();
+    for ty in chosen {
+        copy.push('_');
+        mangle(ty, &mut copy);
+    }
+    Ident::new(&copy, name.span())
+}
+
+// Spells a type with only the characters an identifier may have
+fn mangle(ty: &RtlType, out: &mut String) {
+    match ty {
+        RtlType::Named(name) => out.push_str(&name.to_string()),
+        RtlType::Path(segments) => {
+            let segments: Vec<String> = segments.iter().map(Ident::to_string).collect();
+            out.push_str(&segments.join("_"));
+        }
+        RtlType::Array(inner) => {
+            out.push_str("ArrayOf");
+            mangle(inner, out);
+        }
+        RtlType::FixedArray(inner, len) => {
+            out.push_str("ArrayOf");
+            mangle(inner, out);
+            match len {
+                RtlArrayLen::Lit(len) => out.push_str(&format!("x{}", len)),
+                RtlArrayLen::Param(name) => out.push_str(&format!("x{}", name)),
+            }
+        }
+        RtlType::Optional(inner) => {
+            out.push_str("OptionOf");
+            mangle(inner, out);
+        }
+        RtlType::Ref { mutable, inner } => {
+            out.push_str(if *mutable { "RefMutOf" } else { "RefOf" });
+            mangle(inner, out);
+        }
+    }
+}
+
+// Replaces a template's parameters with the types chosen for them
+struct Substituter<'a> {
+    params: &'a [Ident],
+    types: &'a [&'a RtlType],
+    // The template's functions, with the names of their copies for `types`
+    renames: &'a [(&'a Ident, Ident)],
+    // Parameters a function inside declares as its own type parameters,
+    // hiding the template's where we are
+    hidden: Vec<Ident>,
+}
+
+impl Substituter<'_> {
+    fn func(&mut self, func: &mut RtlFn) {
+        let outer = self.hidden.len();
+        let own = func.generics.iter().filter(|g| self.params.contains(g));
+        self.hidden.extend(own.cloned().collect::<Vec<_>>());
+        for arg in &mut func.args {
+            self.ty(&mut arg.ty);
+            if let Some(default) = &mut arg.default {
+                self.expr(default);
+            }
+        }
+        self.ty(&mut func.ret);
+        if let Some(body) = &mut func.body {
+            self.body(body);
+        }
+        self.hidden.truncate(outer);
+    }
+
+    fn ty(&mut self, ty: &mut RtlType) {
+        let chosen = match ty {
+            RtlType::Named(name) if !self.hidden.contains(name) => {
+                self.params.iter().position(|param| param == name)
+            }
+            RtlType::Named(_) | RtlType::Path(_) => None,
+            RtlType::Array(inner)
+            | RtlType::FixedArray(inner, _)
+            | RtlType::Optional(inner)
+            | RtlType::Ref { inner, .. } => {
+                self.ty(inner);
+                None
+            }
+        };
+        if let Some(i) = chosen {
+            *ty = self.types[i].clone();
+        }
+    }
+
+    fn body(&mut self, body: &mut RtlBody) {
+        body.stmts.iter_mut().for_each(|stmt| self.stmt(stmt));
+        if let Some(value) = &mut body.value {
+            self.expr(value);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &mut RtlStmt) {
+        match stmt {
+            RtlStmt::Var(var) => {
+                self.ty(&mut var.ty);
+                self.expr(&mut var.data);
+            }
+            RtlStmt::Const(c) => {
+                self.ty(&mut c.ty);
+                self.expr(&mut c.data);
+            }
+            RtlStmt::Assign { target, value } => {
+                self.expr(target);
+                self.expr(value);
+            }
+            RtlStmt::Return(value) | RtlStmt::Break { value, .. } => {
+                if let Some(value) = value {
+                    self.expr(value);
+                }
+            }
+            RtlStmt::Expr(expr) | RtlStmt::Defer(expr) => self.expr(expr),
+            RtlStmt::Fn(func) => self.func(func),
+            RtlStmt::IfLet(if_let) => self.if_let(if_let),
+            RtlStmt::If(if_) => self.if_(if_),
+            RtlStmt::While { cond, body } => {
+                self.expr(cond);
+                self.body(body);
+            }
+        }
+    }
+
+    fn if_let(&mut self, if_let: &mut RtlIfLet) {
+        self.expr(&mut if_let.scrutinee);
+        self.body(&mut if_let.then);
+        self.else_branch(if_let.else_branch.as_mut());
+    }
+
+    fn if_(&mut self, if_: &mut RtlIf) {
+        self.expr(&mut if_.cond);
+        self.body(&mut if_.then);
+        self.else_branch(if_.else_branch.as_mut());
+    }
+
+    fn else_branch(&mut self, branch: Option<&mut RtlElse>) {
+        match branch {
+            Some(RtlElse::IfLet(next)) => self.if_let(next),
+            Some(RtlElse::If(next)) => self.if_(next),
+            Some(RtlElse::Block(block)) => self.body(block),
+            None => {}
+        }
+    }
+
+    // Types only appear in casts, and in the statements of blocks
+    fn expr(&mut self, expr: &mut RtlExpr) {
+        match expr {
+            RtlExpr::Lit(_) | RtlExpr::None(_) | RtlExpr::Ident(_) => {}
+            RtlExpr::Array(elems) => elems.iter_mut().for_each(|elem| self.expr(elem)),
+            RtlExpr::Block(block) | RtlExpr::Loop { body: block, .. } => self.body(block),
+            RtlExpr::Repeat { value, .. } => self.expr(value),
+            RtlExpr::Field { base, .. } => self.expr(base),
+            RtlExpr::Index { base, index } => {
+                self.expr(base);
+                self.expr(index);
+            }
+            RtlExpr::Range { start, end } => {
+                start
+                    .iter_mut()
+                    .chain(end)
+                    .for_each(|bound| self.expr(bound));
+            }
+            RtlExpr::Slice { base, range } => {
+                self.expr(base);
+                self.expr(range);
+            }
+            RtlExpr::Binary { lhs, rhs, .. } => {
+                self.expr(lhs);
+                self.expr(rhs);
+            }
+            RtlExpr::Conditional {
+                cond,
+                then,
+                else_branch,
+            } => {
+                self.expr(cond);
+                self.expr(then);
+                self.expr(else_branch);
+            }
+            RtlExpr::StructLit { fields, spread, .. } => {
+                fields.iter_mut().for_each(|(_, value)| self.expr(value));
+                spread.iter_mut().for_each(|base| self.expr(base));
+            }
+            RtlExpr::Cast { expr, ty } => {
+                self.expr(expr);
+                self.ty(ty);
+            }
+            RtlExpr::Some(value) | RtlExpr::Spread(value) => self.expr(value),
+            RtlExpr::Call { name, args } => {
+                if let Some((_, copy)) = self.renames.iter().find(|(from, _)| *from == name) {
+                    *name = copy.clone();
+                }
+                args.iter_mut().for_each(|arg| self.expr(arg));
+            }
+            RtlExpr::Match { scrutinee, arms } => {
+                self.expr(scrutinee);
+                for arm in arms {
+                    arm.bindings
+                        .iter_mut()
+                        .for_each(|(_, value)| self.expr(value));
+                    if let Some(guard) = &mut arm.guard {
+                        self.expr(guard);
+                    }
+                    self.expr(&mut arm.body);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{parse, RtlDeclValue, RtlExpr, RtlGen, RtlStmt};
+
+    fn parse_gen(src: &str) -> RtlGen {
+        let mut rattle = parse(src).unwrap();
+        match rattle.decls.remove(0).value {
+            RtlDeclValue::RtlGen(gen) => gen,
+            other => panic!("expected a gen block, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_expand() {
+        let gen = parse_gen(
+            "gen<T> over [Int, Float] {
+                f Zero() T { var T z = 0 as T; return z; }
+                f Wrap(T x) [T?] { return [some(x)]; }
+            }",
+        );
+        let sigs: Vec<String> = gen
+            .expand()
+            .iter()
+            .map(|func| format!("{} {}", func.args().len(), func.ret()))
+            .collect();
+        assert_eq!(sigs, ["0 Int", "1 [Int?]", "0 Float", "1 [Float?]"]);
+        let names: Vec<String> = gen
+            .expand()
+            .iter()
+            .map(|func| func.name().to_string())
+            .collect();
+        assert_eq!(names, ["Zero_Int", "Wrap_Int", "Zero_Float", "Wrap_Float"]);
+
+        // Inside bodies too
+        let funcs = gen.expand();
+        let Some(RtlStmt::Var(var)) = funcs[2].body().unwrap().stmts().first() else {
+            panic!("expected a var");
+        };
+        assert_eq!(var.ty().to_string(), "Float");
+        assert_eq!(funcs[3].args()[0].ty().to_string(), "Float");
+
+        // Every parameter ranges over every type, and a function's own type
+        // parameter hides the template's
+        let gen = parse_gen("gen<K, V> over [Int, String] { f Get(K k, V v) V; f Id<V>(V v) V; }");
+        let sigs: Vec<String> = gen
+            .expand()
+            .iter()
+            .map(|func| format!("{} {}", func.args()[0].ty(), func.ret()))
+            .collect();
+        assert_eq!(
+            sigs,
+            [
+                "Int Int",
+                "V V",
+                "Int String",
+                "V V",
+                "String Int",
+                "V V",
+                "String String",
+                "V V"
+            ]
+        );
+
+        // Calls between the template's functions stay with the same types
+        let gen = parse_gen(
+            "gen<T> over [[Int], &mut Float?] {
+                f One() T; f Two() T { return One(); }
+            }",
+        );
+        let funcs = gen.expand();
+        let names: Vec<String> = funcs.iter().map(|func| func.name().to_string()).collect();
+        assert_eq!(
+            names,
+            [
+                "One_ArrayOfInt",
+                "Two_ArrayOfInt",
+                "One_RefMutOfOptionOfFloat",
+                "Two_RefMutOfOptionOfFloat"
+            ]
+        );
+        let Some(RtlStmt::Return(Some(RtlExpr::Call { name, .. }))) =
+            funcs[3].body().unwrap().stmts().first()
+        else {
+            panic!("expected a call");
+        };
+        assert_eq!(name, "One_RefMutOfOptionOfFloat");
+
+        // Plain functions stand for themselves
+        let gen = parse_gen("gen f A() Int; f B() Int;");
+        assert!(gen.params().is_empty());
+        let funcs = gen.expand();
+        assert_eq!(funcs.len(), 2);
+        assert_eq!(funcs[0].name(), "A");
+    }
+
+    #[test]
+    fn test_template_errors() {
+        for (src, expected) in [
+            (
+                "gen<> over [Int] {}",
+                "`gen` template needs a type parameter",
+            ),
+            (
+                "gen<T> over [] {}",
+                "`gen` template needs a type to expand over",
+            ),
+            (
+                "gen<const N> over [Int] {}",
+                "`gen` parameter `N` cannot be const",
+            ),
+            ("gen<T> [Int] {}", "expected `over`"),
+            (
+                "gen<A, B, C> over [Int, I8, I16, I32, I64, U8, U16] {}",
+                "`gen` template expands to more than 256 copies",
+            ),
+            (
+                "gen<A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q> over [Int, Float, String, Bool, Char, I8, I16, I32, I64, U8, U16, U32, U64, F32, Bytes] {}",
+                "`gen` template expands to more than 256 copies",
+            ),
+        ] {
+            assert_eq!(parse(src).unwrap_err().to_string(), expected, "{}", src);
+        }
+        // A template ends at its brace, so declarations may follow it
+        let rattle = parse("gen<T> over [Int] { f Id(T x) T; } f Main() Int;").unwrap();
+        assert_eq!(rattle.decls().len(), 2);
+    }
+}
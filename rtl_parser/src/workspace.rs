@@ -0,0 +1,195 @@
+use std::collections::HashMap;
+
+use proc_macro2::Span;
+use syn::{Error, Ident, Result};
+
+use crate::{symbols, Rattle, RtlDecl, RtlImport, RtlSymbol, RtlSymbolKind, SymbolTable};
+
+// A program spread across files: parsed modules, keyed by the path other
+// modules import from them with, like `std::math`
+#[derive(Debug, Default)]
+pub struct RattleWorkspace {
+    modules: HashMap<String, Rattle>,
+}
+
+impl RattleWorkspace {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Adds a module under an import path, returning the module it replaces
+    pub fn add_module(&mut self, path: &str, rattle: Rattle) -> Option<Rattle> {
+        self.modules.insert(path.to_string(), rattle)
+    }
+
+    pub fn module(&self, path: &str) -> Option<&Rattle> {
+        self.modules.get(path)
+    }
+
+    // The declaration an import brings in: its last segment, declared `pub`
+    // in the module the rest of its path names, so `import std::math::Max;`
+    // finds `pub f Max` in `std::math`. `None` when no module here declares
+    // it, when the declaration isn't public, and for imports of a whole
+    // module.
+    pub fn resolve(&self, import: &RtlImport) -> Option<&RtlDecl> {
+        self.find(import).filter(|decl| decl.is_public())
+    }
+
+    // The declaration an import names, whether or not it's public
+    fn find(&self, import: &RtlImport) -> Option<&RtlDecl> {
+        let (name, module) = import.path().split_last()?;
+        self.modules
+            .get(&join(module))?
+            .find_decl(&name.to_string())
+    }
+
+    // The symbols visible in a module: its own, then one for each import the
+    // workspace resolves, named by the import's alias or the declaration's
+    // name and placed at that name in the import. Imports from outside the
+    // workspace are left out. An import of a declaration that isn't `pub`, or
+    // whose name the module also declares or another import also brings in,
+    // is an error; a `def` block for an imported struct isn't a conflict.
+    pub fn symbol_table(&self, path: &str) -> Result<SymbolTable> {
+        let rattle = self.modules.get(path).ok_or_else(|| {
+            Error::new(
+                Span::call_site(),
+                format!("no module `{}` in the workspace", path),
+            )
+        })?;
+        let mut symbols = SymbolTable::new(rattle).symbols().to_vec();
+        let declared = symbols.len();
+
+        let mut errors: Option<Error> = None;
+        let mut push_error = |err: Error| match &mut errors {
+            Some(errors) => errors.combine(err),
+            None => errors = Some(err),
+        };
+        for import in rattle.imports() {
+            let Some(decl) = self.find(import) else {
+                continue;
+            };
+            if !decl.is_public() {
+                let (name, module) = import.path().split_last().unwrap();
+                push_error(Error::new(
+                    name.span(),
+                    format!("`{}` in `{}` is not public", name, join(module)),
+                ));
+                continue;
+            }
+            let Some((kind, decl_name)) = symbols::kind_and_name(decl.value()) else {
+                continue;
+            };
+            let name: &Ident = import.alias().unwrap_or(decl_name);
+            let clash = symbols
+                .iter()
+                .position(|sym| name == sym.name() && sym.kind() != RtlSymbolKind::Def);
+            if let Some(i) = clash {
+                let msg = if i < declared {
+                    format!("`{}` is imported but also declared in `{}`", name, path)
+                } else {
+                    format!("`{}` is imported twice into `{}`", name, path)
+                };
+                push_error(Error::new(name.span(), msg));
+                continue;
+            }
+            symbols.push(RtlSymbol::new(kind, name));
+        }
+        errors.map_or(Ok(SymbolTable::from_symbols(symbols)), Err)
+    }
+}
+
+fn join(path: &[Ident]) -> String {
+    path.iter()
+        .map(Ident::to_string)
+        .collect::<Vec<_>>()
+        .join("::")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse, RtlDeclValue};
+
+    fn workspace(app: &str) -> RattleWorkspace {
+        let mut workspace = RattleWorkspace::new();
+        let math = "pub f Max(Int a, Int b) -> Int { return a; }
+                    pub const Int Zero = 0;
+                    f Clamp(Int x) -> Int { return x; }";
+        workspace.add_module("std::math", parse(math).unwrap());
+        workspace.add_module("app", parse(app).unwrap());
+        workspace
+    }
+
+    #[test]
+    fn test_resolve() {
+        let workspace = workspace(
+            "import std::math::Max;
+             import std::math::Zero as Origin;
+             import std::io::Print;
+             f Main() Int { return Max(1, Origin); }",
+        );
+        let imports = workspace.module("app").unwrap().imports();
+        let RtlDeclValue::RtlFn(max) = workspace.resolve(&imports[0]).unwrap().value() else {
+            panic!("expected a function");
+        };
+        assert_eq!(max.name(), "Max");
+        assert!(workspace.resolve(&imports[2]).is_none());
+
+        let table = workspace.symbol_table("app").unwrap();
+        let names: Vec<_> = table
+            .symbols()
+            .iter()
+            .map(|sym| (sym.kind(), sym.name()))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                (RtlSymbolKind::Fn, "Main"),
+                (RtlSymbolKind::Fn, "Max"),
+                (RtlSymbolKind::Const, "Origin"),
+            ]
+        );
+        // The imported symbol sits at its name in the import
+        assert_eq!(table.get("Max").unwrap().span().line, 1);
+        assert!(workspace.symbol_table("lib").is_err());
+    }
+
+    #[test]
+    fn test_import_conflicts() {
+        let workspace = workspace(
+            "import std::math::Max;
+             import std::math::Zero as Max;
+             import std::math::Zero;
+             const Int Zero = 1;",
+        );
+        let errs: Vec<_> = workspace
+            .symbol_table("app")
+            .unwrap_err()
+            .into_iter()
+            .map(|err| err.to_string())
+            .collect();
+        assert_eq!(
+            errs,
+            [
+                "`Max` is imported twice into `app`",
+                "`Zero` is imported but also declared in `app`",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_private_import() {
+        let workspace = workspace(
+            "import std::math::Max;
+             import std::math::Clamp;
+             f Main() -> Int { return Clamp(Max(1, 2)); }",
+        );
+        let imports = workspace.module("app").unwrap().imports();
+        assert!(workspace.resolve(&imports[0]).is_some());
+        assert!(workspace.resolve(&imports[1]).is_none());
+
+        let err = workspace.symbol_table("app").unwrap_err();
+        assert_eq!(err.to_string(), "`Clamp` in `std::math` is not public");
+        assert_eq!(err.span().start().line, 2);
+    }
+}
@@ -0,0 +1,1645 @@
+use std::{
+    collections::HashMap,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use inkwell::{
+    debug_info::{
+        AsDIScope, DIFlags, DIFlagsConstants, DIScope, DWARFEmissionKind, DWARFSourceLanguage,
+        DebugInfoBuilder,
+    },
+    module::{FlagBehavior, Linkage},
+    targets::{
+        CodeModel, FileType, InitializationConfig, RelocMode, Target, TargetMachine, TargetTriple,
+    },
+    types::{BasicMetadataTypeEnum, BasicType, BasicTypeEnum, IntType},
+    values::{
+        BasicMetadataValueEnum, BasicValue, BasicValueEnum, FunctionValue, GlobalValue, IntValue,
+        PointerValue,
+    },
+    AddressSpace, FloatPredicate, IntPredicate, OptimizationLevel,
+};
+use rtl_parser::{
+    Ident, IdentExt, Lit, Rattle, RtlArrayLen, RtlBinOp, RtlConstExpr, RtlDeclValue, RtlExpr,
+    RtlFn, RtlStmt, RtlType, Span,
+};
+
+use crate::{eval, JitCompiler, JitError, JitKind, JitValue, RtlResult};
+
+/// The signature of a host function registered with
+/// [`JitCompiler::register_extern`], in the kinds of its parameters and result.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExternSig {
+    pub params: Vec<JitKind>,
+    pub ret: JitKind,
+}
+
+/// The state of a function being compiled.
+struct FnScope<'ctx> {
+    /// The stack slot and type of each local.
+    locals: HashMap<String, (PointerValue<'ctx>, BasicTypeEnum<'ctx>)>,
+    /// The debug info builder and the function's subprogram, when debug info
+    /// is enabled.
+    debug: Option<(DebugInfoBuilder<'ctx>, DIScope<'ctx>)>,
+}
+
+/// How long each phase of compiling a function took.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CompileTimings {
+    /// Lowering the Rattle function to LLVM IR.
+    pub ir_gen: Duration,
+    /// Having the execution engine generate machine code for the module.
+    pub finalize: Duration,
+}
+
+/// Maps type-name aliases onto a single spelling, so `I128` and `Int` agree.
+fn canonical_type_name(name: &str) -> &str {
+    match name {
+        "I128" => "Int",
+        "F64" => "Float",
+        other => other,
+    }
+}
+
+/// Maps a literal suffix like `i32` onto the Rattle type it pins.
+fn suffix_type_name(suffix: &str) -> Option<&'static str> {
+    Some(match suffix {
+        "i8" => "I8",
+        "i16" => "I16",
+        "i32" => "I32",
+        "i64" => "I64",
+        "i128" => "Int",
+        "u8" => "U8",
+        "u16" => "U16",
+        "u32" => "U32",
+        "u64" => "U64",
+        "u128" => "U128",
+        "f32" => "F32",
+        "f64" => "Float",
+        _ => return None,
+    })
+}
+
+/// Whether a Rattle type name is one of the unsigned integers.
+fn is_unsigned(name: &str) -> bool {
+    matches!(name, "U8" | "U16" | "U32" | "U64" | "U128")
+}
+
+/// Builds an integer constant of any width from an `i128`, failing if it's
+/// out of the type's range, signed or unsigned as `signed` says. When that
+/// isn't known, either range will do.
+fn const_int(ty: IntType<'_>, v: i128, signed: Option<bool>) -> RtlResult<IntValue<'_>> {
+    let bits = ty.get_bit_width();
+    let (min, max) = match bits {
+        128.. => (if signed == Some(false) { 0 } else { i128::MIN }, i128::MAX),
+        _ => {
+            let half = 1i128 << (bits - 1);
+            match signed {
+                Some(true) => (-half, half - 1),
+                Some(false) => (0, 2 * half - 1),
+                None => (-half, 2 * half - 1),
+            }
+        }
+    };
+    if !(min..=max).contains(&v) {
+        return Err(JitError::LiteralOutOfRange { value: v, bits }.into());
+    }
+    Ok(ty.const_int_arbitrary_precision(&[v as u64, (v >> 64) as u64]))
+}
+
+/// The span a statement's debug location points at, if it has one.
+fn stmt_span(stmt: &RtlStmt) -> Option<Span> {
+    match stmt {
+        RtlStmt::Var(var) => Some(var.name().span()),
+        RtlStmt::Const(c) => Some(c.name().span()),
+        RtlStmt::Assign { target, .. } => Some(target.span()),
+        RtlStmt::Return(Some(expr)) | RtlStmt::Expr(expr) | RtlStmt::Defer(expr) => {
+            Some(expr.span())
+        }
+        RtlStmt::Return(None) => None,
+        RtlStmt::Fn(func) => Some(func.name().span()),
+        RtlStmt::IfLet(if_let) => Some(if_let.scrutinee().span()),
+        RtlStmt::If(if_) => Some(if_.cond().span()),
+        RtlStmt::While { cond, .. } => Some(cond.span()),
+        RtlStmt::Break { value, .. } => value.as_ref().map(RtlExpr::span),
+    }
+}
+
+impl<'ctx> JitCompiler<'ctx> {
+    /// Lowers a Rattle type to its LLVM representation.
+    ///
+    /// Array types without a literal length, like `[Int]`, have no static
+    /// length, so `len` supplies it.
+    pub fn llvm_type(&self, ty: &RtlType, len: u32) -> RtlResult<BasicTypeEnum<'ctx>> {
+        match ty {
+            RtlType::Named(name) => self.llvm_named_type(&name.to_string()),
+            RtlType::Array(inner) => match self.llvm_type(inner, 0)? {
+                BasicTypeEnum::IntType(elem) => Ok(elem.array_type(len).into()),
+                BasicTypeEnum::FloatType(elem) => Ok(elem.array_type(len).into()),
+                _ => Err(JitError::Unsupported(format!("array of '{}'", inner)).into()),
+            },
+            RtlType::FixedArray(inner, RtlArrayLen::Lit(len)) => {
+                let len = u32::try_from(*len)
+                    .map_err(|_| JitError::Unsupported(format!("type '{}'", ty)))?;
+                self.llvm_type(&RtlType::Array(inner.clone()), len)
+            }
+            // A const parameter's length is only known once instantiated
+            RtlType::FixedArray(_, RtlArrayLen::Param(_)) => {
+                Err(JitError::Unsupported(format!("type '{}'", ty)).into())
+            }
+            // Neither imported types nor references are compiled yet
+            RtlType::Path(_) | RtlType::Ref { .. } => {
+                Err(JitError::Unsupported(format!("type '{}'", ty)).into())
+            }
+            // Tagged like `jit_to_llvm` does: a presence flag, then the payload
+            RtlType::Optional(inner) => {
+                let payload = self.llvm_type(inner, len)?;
+                let flag = self.context.bool_type().into();
+                Ok(self.context.struct_type(&[flag, payload], false).into())
+            }
+        }
+    }
+
+    fn llvm_named_type(&self, name: &str) -> RtlResult<BasicTypeEnum<'ctx>> {
+        let ctx = self.context;
+        match canonical_type_name(name) {
+            "Int" => Ok(ctx.i128_type().into()),
+            "I8" | "U8" => Ok(ctx.i8_type().into()),
+            "I16" | "U16" => Ok(ctx.custom_width_int_type(16).into()),
+            "I32" | "U32" => Ok(ctx.i32_type().into()),
+            "I64" | "U64" => Ok(ctx.i64_type().into()),
+            "U128" => Ok(ctx.i128_type().into()),
+            "Float" => Ok(ctx.f64_type().into()),
+            "F32" => Ok(ctx.f32_type().into()),
+            "String" | "Bytes" => Ok(ctx.ptr_type(AddressSpace::default()).into()),
+            other => Err(JitError::Unsupported(format!("type '{}'", other)).into()),
+        }
+    }
+
+    /// Builds an LLVM constant for an expression of the given type.
+    pub fn const_value(&self, ty: &RtlType, expr: &RtlExpr) -> RtlResult<BasicValueEnum<'ctx>> {
+        match (ty, expr) {
+            (
+                RtlType::Array(inner) | RtlType::FixedArray(inner, _),
+                RtlExpr::Array(_) | RtlExpr::Repeat { .. },
+            ) => {
+                let values = match expr {
+                    RtlExpr::Repeat { value, count } => {
+                        eval::repeat(self.const_value(inner, value)?, *count)?
+                    }
+                    RtlExpr::Array(elems) => elems
+                        .iter()
+                        .map(|elem| self.const_value(inner, elem))
+                        .collect::<RtlResult<Vec<_>>>()?,
+                    _ => unreachable!(),
+                };
+                match self.llvm_type(inner, 0)? {
+                    BasicTypeEnum::IntType(elem) => {
+                        let values: Vec<_> = values.iter().map(|v| v.into_int_value()).collect();
+                        Ok(elem.const_array(&values).into())
+                    }
+                    BasicTypeEnum::FloatType(elem) => {
+                        let values: Vec<_> = values.iter().map(|v| v.into_float_value()).collect();
+                        Ok(elem.const_array(&values).into())
+                    }
+                    _ => Err(JitError::Unsupported(format!("array of '{}'", inner)).into()),
+                }
+            }
+            (RtlType::Named(name), RtlExpr::Lit(lit)) => {
+                let suffix = match lit {
+                    Lit::Int(int) => int.suffix(),
+                    Lit::Float(float) => float.suffix(),
+                    _ => "",
+                };
+                // A suffix pins the literal's type, which must agree with the declaration
+                if !suffix.is_empty() {
+                    let suffix_ty = suffix_type_name(suffix)
+                        .ok_or_else(|| JitError::Unsupported(format!("suffix '{}'", suffix)))?;
+                    if canonical_type_name(suffix_ty) != canonical_type_name(&name.to_string()) {
+                        return Err(JitError::Unsupported(format!(
+                            "'{}' literal for a '{}' constant",
+                            suffix_ty, name
+                        ))
+                        .into());
+                    }
+                }
+                self.const_lit(ty, lit)
+            }
+            _ => Err(JitError::Unsupported(format!("'{}' constant", ty)).into()),
+        }
+    }
+
+    fn const_lit(&self, ty: &RtlType, lit: &Lit) -> RtlResult<BasicValueEnum<'ctx>> {
+        match (self.llvm_type(ty, 0)?, lit) {
+            (BasicTypeEnum::IntType(int), Lit::Int(v)) => {
+                let signed = !is_unsigned(&ty.to_string());
+                Ok(const_int(int, v.base10_parse::<i128>()?, Some(signed))?.into())
+            }
+            (BasicTypeEnum::FloatType(float), Lit::Float(v)) => {
+                Ok(float.const_float(v.base10_parse::<f64>()?).into())
+            }
+            (BasicTypeEnum::FloatType(float), Lit::Int(v)) => {
+                Ok(float.const_float(v.base10_parse::<f64>()?).into())
+            }
+            _ => Err(JitError::Unsupported(format!("'{}' constant", ty)).into()),
+        }
+    }
+
+    /// Builds an LLVM constant holding a jit value.
+    pub fn const_jit(&self, value: &JitValue) -> RtlResult<BasicValueEnum<'ctx>> {
+        match value {
+            JitValue::Int(v) => Ok(const_int(self.context.i128_type(), *v, Some(true))?.into()),
+            JitValue::Float(v) => Ok(self.context.f64_type().const_float(*v).into()),
+            JitValue::I32(v) => Ok(self.context.i32_type().const_int(*v as u64, true).into()),
+            JitValue::I64(v) => Ok(self.context.i64_type().const_int(*v as u64, true).into()),
+            JitValue::F32(v) => Ok(self.context.f32_type().const_float(*v as f64).into()),
+            JitValue::Bool(v) => Ok(self.context.bool_type().const_int(*v as u64, false).into()),
+            JitValue::Char(v) => Ok(self.context.i32_type().const_int(*v as u64, false).into()),
+            JitValue::Bytes(v) => Ok(self.context.const_string(v, false).into()),
+            JitValue::String(_)
+            | JitValue::Struct { .. }
+            | JitValue::Array { .. }
+            | JitValue::Unit
+            | JitValue::Option(_)
+            | JitValue::Enum { .. } => {
+                Err(JitError::Unsupported(format!("{} constant", value.kind_name())).into())
+            }
+        }
+    }
+
+    /// Emits the [`switch`](JitCompiler::switch) helper as an LLVM `switch`
+    /// instruction at the builder's position.
+    ///
+    /// Every case jumps to its own block and then to a merge block, whose
+    /// `phi` of the case values is returned. Case keys are truncated to the
+    /// scrutinee's width; like the runtime helper, the first of several equal
+    /// keys wins.
+    pub fn compile_switch(
+        &self,
+        scrutinee: IntValue<'ctx>,
+        cases: &[(JitValue, JitValue)],
+        default: JitValue,
+    ) -> RtlResult<BasicValueEnum<'ctx>> {
+        let function = self
+            .builder
+            .get_insert_block()
+            .and_then(|block| block.get_parent())
+            .ok_or_else(|| JitError::Unsupported("switch outside a function".into()))?;
+        let default_block = self.context.append_basic_block(function, "switch.default");
+        let merge_block = self.context.append_basic_block(function, "switch.end");
+
+        let mut keys = Vec::new();
+        let mut arms = Vec::new();
+        for (key, value) in cases {
+            let key = key
+                .as_int()
+                .ok_or_else(|| JitError::Unsupported(format!("{} switch case", key.kind_name())))?;
+            if value.kind() != default.kind() {
+                return Err(JitError::Unsupported(format!(
+                    "switch yielding both {} and {}",
+                    value.kind_name(),
+                    default.kind_name()
+                ))
+                .into());
+            }
+            if keys.contains(key) {
+                continue;
+            }
+            keys.push(*key);
+            let block = self.context.append_basic_block(function, "switch.case");
+            arms.push((const_int(scrutinee.get_type(), *key, None)?, block, value));
+        }
+
+        let case_blocks: Vec<_> = arms.iter().map(|(key, block, _)| (*key, *block)).collect();
+        self.builder
+            .build_switch(scrutinee, default_block, &case_blocks)?;
+
+        let mut incoming = Vec::new();
+        for (_, block, value) in &arms {
+            self.builder.position_at_end(*block);
+            self.builder.build_unconditional_branch(merge_block)?;
+            incoming.push((self.const_jit(value)?, *block));
+        }
+        self.builder.position_at_end(default_block);
+        self.builder.build_unconditional_branch(merge_block)?;
+        let default_value = self.const_jit(&default)?;
+        incoming.push((default_value, default_block));
+
+        self.builder.position_at_end(merge_block);
+        let phi = self
+            .builder
+            .build_phi(default_value.get_type(), "switch.value")?;
+        let incoming: Vec<_> = incoming
+            .iter()
+            .map(|(value, block)| (value as &dyn BasicValue<'ctx>, *block))
+            .collect();
+        phi.add_incoming(&incoming);
+        Ok(phi.as_basic_value())
+    }
+
+    /// Emits a binary operation on two already-evaluated operands.
+    ///
+    /// This is the single place operator lowering lives: ints (including `i1`
+    /// bools) use signed integer instructions, floats use ordered float ones.
+    /// `>>` shifts arithmetically, keeping the sign. Both operands are
+    /// evaluated, so `&&`/`||` here do not short-circuit.
+    ///
+    /// A shift by the operand's width or more is poison in LLVM, so a
+    /// constant amount out of range is an [`InvalidShift`](JitError::InvalidShift)
+    /// error, like in the interpreter, and any other amount is taken modulo
+    /// the width, like Rust's `wrapping_shl`.
+    pub fn emit_binop(
+        &self,
+        op: RtlBinOp,
+        lhs: BasicValueEnum<'ctx>,
+        rhs: BasicValueEnum<'ctx>,
+    ) -> RtlResult<BasicValueEnum<'ctx>> {
+        let b = &self.builder;
+        match (lhs, rhs) {
+            (BasicValueEnum::IntValue(l), BasicValueEnum::IntValue(r)) => {
+                let is_bool = l.get_type().get_bit_width() == 1;
+                let shift_amount = |r: IntValue<'ctx>| -> RtlResult<IntValue<'ctx>> {
+                    let bits = r.get_type().get_bit_width();
+                    if let Some(amount) = r.get_sign_extended_constant() {
+                        if !(0..i64::from(bits)).contains(&amount) {
+                            let amount = amount.into();
+                            return Err(JitError::InvalidShift { amount, bits }.into());
+                        }
+                        return Ok(r);
+                    }
+                    let mask = r.get_type().const_int(u64::from(bits - 1), false);
+                    Ok(b.build_and(r, mask, "shamt")?)
+                };
+                let value = match op {
+                    RtlBinOp::Add => b.build_int_add(l, r, "add")?,
+                    RtlBinOp::Sub => b.build_int_sub(l, r, "sub")?,
+                    RtlBinOp::Mul => b.build_int_mul(l, r, "mul")?,
+                    RtlBinOp::Div => b.build_int_signed_div(l, r, "div")?,
+                    RtlBinOp::Rem => b.build_int_signed_rem(l, r, "rem")?,
+                    RtlBinOp::Eq => b.build_int_compare(IntPredicate::EQ, l, r, "eq")?,
+                    RtlBinOp::Ne => b.build_int_compare(IntPredicate::NE, l, r, "ne")?,
+                    RtlBinOp::Lt => b.build_int_compare(IntPredicate::SLT, l, r, "lt")?,
+                    RtlBinOp::Le => b.build_int_compare(IntPredicate::SLE, l, r, "le")?,
+                    RtlBinOp::Gt => b.build_int_compare(IntPredicate::SGT, l, r, "gt")?,
+                    RtlBinOp::Ge => b.build_int_compare(IntPredicate::SGE, l, r, "ge")?,
+                    RtlBinOp::And if is_bool => b.build_and(l, r, "and")?,
+                    RtlBinOp::Or if is_bool => b.build_or(l, r, "or")?,
+                    RtlBinOp::And | RtlBinOp::Or => {
+                        return Err(JitError::Unsupported(format!("`{}` on integers", op)).into())
+                    }
+                    RtlBinOp::BitAnd => b.build_and(l, r, "bitand")?,
+                    RtlBinOp::BitOr => b.build_or(l, r, "bitor")?,
+                    RtlBinOp::BitXor => b.build_xor(l, r, "bitxor")?,
+                    RtlBinOp::Shl => b.build_left_shift(l, shift_amount(r)?, "shl")?,
+                    RtlBinOp::Shr => b.build_right_shift(l, shift_amount(r)?, true, "shr")?,
+                };
+                Ok(value.into())
+            }
+            (BasicValueEnum::FloatValue(l), BasicValueEnum::FloatValue(r)) => {
+                let cmp = |pred, name| b.build_float_compare(pred, l, r, name);
+                let value: BasicValueEnum = match op {
+                    RtlBinOp::Add => b.build_float_add(l, r, "fadd")?.into(),
+                    RtlBinOp::Sub => b.build_float_sub(l, r, "fsub")?.into(),
+                    RtlBinOp::Mul => b.build_float_mul(l, r, "fmul")?.into(),
+                    RtlBinOp::Div => b.build_float_div(l, r, "fdiv")?.into(),
+                    RtlBinOp::Rem => b.build_float_rem(l, r, "frem")?.into(),
+                    RtlBinOp::Eq => cmp(FloatPredicate::OEQ, "feq")?.into(),
+                    RtlBinOp::Ne => cmp(FloatPredicate::ONE, "fne")?.into(),
+                    RtlBinOp::Lt => cmp(FloatPredicate::OLT, "flt")?.into(),
+                    RtlBinOp::Le => cmp(FloatPredicate::OLE, "fle")?.into(),
+                    RtlBinOp::Gt => cmp(FloatPredicate::OGT, "fgt")?.into(),
+                    RtlBinOp::Ge => cmp(FloatPredicate::OGE, "fge")?.into(),
+                    RtlBinOp::And
+                    | RtlBinOp::Or
+                    | RtlBinOp::BitAnd
+                    | RtlBinOp::BitOr
+                    | RtlBinOp::BitXor
+                    | RtlBinOp::Shl
+                    | RtlBinOp::Shr => {
+                        return Err(JitError::Unsupported(format!("`{}` on floats", op)).into())
+                    }
+                };
+                Ok(value)
+            }
+            _ => Err(JitError::Unsupported(format!("`{}` on mismatched operands", op)).into()),
+        }
+    }
+
+    /// Compiles a constant declaration into a constant LLVM global.
+    pub fn compile_const(&self, decl: &RtlConstExpr) -> RtlResult<GlobalValue<'ctx>> {
+        let value = self.const_value(decl.ty(), decl.data())?;
+        let global =
+            self.module
+                .add_global(value.get_type(), None, &decl.name().unraw().to_string());
+        global.set_initializer(&value);
+        global.set_constant(true);
+        Ok(global)
+    }
+
+    /// Makes a host function callable from compiled code as `name`.
+    /// Functions compiled afterwards call it like any other function.
+    ///
+    /// # Safety
+    ///
+    /// `addr` must be the address of an `extern "C"` function that stays
+    /// alive as long as the compiler's code may run, and whose parameters and
+    /// result have the C types `signature` lowers to, like `i64` for
+    /// [`JitKind::I64`]. Compiled code calls it through `addr` unchecked.
+    pub unsafe fn register_extern(
+        &mut self,
+        name: &str,
+        addr: usize,
+        signature: ExternSig,
+    ) -> RtlResult<FunctionValue<'ctx>> {
+        if self.module.get_function(name).is_some() {
+            return Err(JitError::Unsupported(format!("redefining function '{}'", name)).into());
+        }
+        let params = signature
+            .params
+            .iter()
+            .map(|kind| Ok(self.llvm_named_type(kind.name())?.into()))
+            .collect::<RtlResult<Vec<BasicMetadataTypeEnum>>>()?;
+        let ret = self.llvm_named_type(signature.ret.name())?;
+        let function =
+            self.module
+                .add_function(name, ret.fn_type(&params, false), Some(Linkage::External));
+        self.execution_engine.add_global_mapping(&function, addr);
+        Ok(function)
+    }
+
+    /// Declares a Rattle function's prototype in the module without its body,
+    /// so calls to it can be compiled before it is.
+    ///
+    /// Declaring a function that is already declared with the same signature
+    /// returns the existing declaration. The parameters' defaults are kept
+    /// for compiling calls that leave them out.
+    pub fn declare_fn(&self, func: &RtlFn) -> RtlResult<FunctionValue<'ctx>> {
+        let name = func.name().unraw().to_string();
+        let params = func
+            .args()
+            .iter()
+            .map(|arg| Ok(self.llvm_type(arg.ty(), 0)?.into()))
+            .collect::<RtlResult<Vec<BasicMetadataTypeEnum>>>()?;
+        let fn_type = match self.llvm_ret_type(func)? {
+            Some(ret) => ret.fn_type(&params, false),
+            None => self.context.void_type().fn_type(&params, false),
+        };
+        let function = match self.module.get_function(&name) {
+            Some(function) if function.count_basic_blocks() > 0 => {
+                return Err(JitError::Unsupported(format!("redefining function '{}'", name)).into())
+            }
+            Some(function) if function.get_type() != fn_type => {
+                return Err(JitError::Unsupported(format!(
+                    "redeclaring function '{}' with another signature",
+                    name
+                ))
+                .into())
+            }
+            Some(function) => function,
+            None => self.module.add_function(&name, fn_type, None),
+        };
+        let defaults = func.args().iter().map(|arg| arg.default().cloned());
+        self.defaults.borrow_mut().insert(name, defaults.collect());
+        Ok(function)
+    }
+
+    /// The LLVM type a function returns, or `None` for a `Unit` function,
+    /// which returns `void`.
+    fn llvm_ret_type(&self, func: &RtlFn) -> RtlResult<Option<BasicTypeEnum<'ctx>>> {
+        if func.ret().is_unit() {
+            return Ok(None);
+        }
+        self.llvm_type(func.ret(), 0).map(Some)
+    }
+
+    /// Compiles a Rattle function into the module.
+    ///
+    /// A function without a body becomes a declaration, to be resolved by
+    /// another compiled function or a global mapping. Arguments and locals
+    /// live in stack slots; a call may leave out trailing arguments that have
+    /// defaults, which are compiled in the caller. With [debug info](JitCompiler::set_debug_info)
+    /// enabled, each statement, operation and call is tagged with the line and
+    /// column it came from.
+    ///
+    /// The function may call itself, and any function already
+    /// [declared](JitCompiler::declare_fn); use
+    /// [`compile_program`](JitCompiler::compile_program) to declare a whole
+    /// program's functions first.
+    ///
+    /// A function named with a raw identifier, like `r#type`, is named without
+    /// the `r#` in the module, so it can declare a host function of that name.
+    /// With the `trace` feature, compiling is traced as a `compile_fn` span.
+    pub fn compile_fn(&self, func: &RtlFn) -> RtlResult<FunctionValue<'ctx>> {
+        crate::trace_span!("compile_fn", function = %func.name());
+        let declared = self
+            .module
+            .get_function(&func.name().unraw().to_string())
+            .is_some();
+        let function = self.declare_fn(func)?;
+        let ret = self.llvm_ret_type(func)?;
+
+        if func.body().is_some() {
+            if let Err(err) = self.compile_fn_body(func, function, ret) {
+                // Don't leave a half-built function behind to fail verification,
+                // but keep a declaration other functions may already call
+                if declared {
+                    for block in function.get_basic_blocks() {
+                        let _ = unsafe { block.delete() };
+                    }
+                } else {
+                    unsafe { function.delete() };
+                }
+                return Err(err);
+            }
+        }
+        Ok(function)
+    }
+
+    /// Compiles a program's constants and functions into the module.
+    ///
+    /// Every function is declared before any body is compiled, so functions
+    /// can call each other regardless of their order in the source, including
+    /// recursively. If anything fails, everything this call added is removed
+    /// again, so no compiled function is left calling one without a body.
+    pub fn compile_program(&self, rattle: &Rattle) -> RtlResult<()> {
+        let mut globals = Vec::new();
+        let mut functions = Vec::new();
+        let result = self.compile_program_into(rattle, &mut globals, &mut functions);
+        if result.is_err() {
+            for (function, declared) in functions {
+                if declared {
+                    for block in function.get_basic_blocks() {
+                        let _ = unsafe { block.delete() };
+                    }
+                } else {
+                    unsafe { function.delete() };
+                }
+            }
+            for global in globals {
+                unsafe { global.delete() };
+            }
+        }
+        result
+    }
+
+    /// Compiles a program like [`compile_program`](JitCompiler::compile_program),
+    /// recording the globals it adds and the functions it declares, each with
+    /// whether it was already declared before.
+    fn compile_program_into(
+        &self,
+        rattle: &Rattle,
+        globals: &mut Vec<GlobalValue<'ctx>>,
+        functions: &mut Vec<(FunctionValue<'ctx>, bool)>,
+    ) -> RtlResult<()> {
+        let mut funcs = Vec::new();
+        for decl in rattle.decls() {
+            match decl.value() {
+                RtlDeclValue::RtlConst(c) => globals.push(self.compile_const(c)?),
+                RtlDeclValue::RtlFn(func) => {
+                    let declared = self
+                        .module
+                        .get_function(&func.name().unraw().to_string())
+                        .is_some();
+                    let function = self.declare_fn(func)?;
+                    if !functions.iter().any(|(f, _)| *f == function) {
+                        functions.push((function, declared));
+                    }
+                    funcs.push(func);
+                }
+                _ => {}
+            }
+        }
+        for func in funcs {
+            self.compile_fn(func)?;
+        }
+        Ok(())
+    }
+
+    /// Compiles a Rattle function like [`compile_fn`](JitCompiler::compile_fn),
+    /// then has the execution engine generate its machine code, timing each
+    /// phase.
+    pub fn compile_fn_timed(
+        &self,
+        func: &RtlFn,
+    ) -> RtlResult<(FunctionValue<'ctx>, CompileTimings)> {
+        let start = Instant::now();
+        let function = self.compile_fn(func)?;
+        let ir_gen = start.elapsed();
+
+        let start = Instant::now();
+        self.execution_engine
+            .get_function_address(&func.name().unraw().to_string())?;
+        let finalize = start.elapsed();
+
+        Ok((function, CompileTimings { ir_gen, finalize }))
+    }
+
+    fn compile_fn_body(
+        &self,
+        func: &RtlFn,
+        function: FunctionValue<'ctx>,
+        ret: Option<BasicTypeEnum<'ctx>>,
+    ) -> RtlResult<()> {
+        let entry = self.context.append_basic_block(function, "entry");
+        self.builder.position_at_end(entry);
+
+        let mut scope = FnScope {
+            locals: HashMap::new(),
+            debug: self.debug_info.then(|| self.debug_scope(func, function)),
+        };
+        // The prologue is attributed to the function's name
+        self.set_debug_location(&scope, func.name().span());
+        let result = self.compile_stmts(func, function, &mut scope, ret);
+        if let Some((dibuilder, _)) = &scope.debug {
+            dibuilder.finalize();
+            self.builder.unset_current_debug_location();
+        }
+        result
+    }
+
+    fn compile_stmts(
+        &self,
+        func: &RtlFn,
+        function: FunctionValue<'ctx>,
+        scope: &mut FnScope<'ctx>,
+        ret: Option<BasicTypeEnum<'ctx>>,
+    ) -> RtlResult<()> {
+        for (i, arg) in func.args().iter().enumerate() {
+            let param = function.get_nth_param(i as u32).unwrap();
+            let name = arg.name().to_string();
+            let slot = self.builder.build_alloca(param.get_type(), &name)?;
+            self.builder.build_store(slot, param)?;
+            scope.locals.insert(name, (slot, param.get_type()));
+        }
+
+        for stmt in func.body().map_or(&[][..], |body| body.stmts()) {
+            if let Some(span) = stmt_span(stmt) {
+                self.set_debug_location(scope, span);
+            }
+            match stmt {
+                RtlStmt::Var(var) => {
+                    let ty = self.llvm_type(var.ty(), 0)?;
+                    let value = self.compile_expr(scope, var.data(), Some(ty))?;
+                    let name = var.name().to_string();
+                    let slot = self.builder.build_alloca(ty, &name)?;
+                    self.builder.build_store(slot, value)?;
+                    scope.locals.insert(name, (slot, ty));
+                }
+                RtlStmt::Const(c) => {
+                    let ty = self.llvm_type(c.ty(), 0)?;
+                    let value = self.compile_expr(scope, c.data(), Some(ty))?;
+                    let name = c.name().to_string();
+                    let slot = self.builder.build_alloca(ty, &name)?;
+                    self.builder.build_store(slot, value)?;
+                    scope.locals.insert(name, (slot, ty));
+                }
+                RtlStmt::Assign {
+                    target: RtlExpr::Ident(name),
+                    value,
+                } => {
+                    let (slot, ty) = *scope
+                        .locals
+                        .get(&name.to_string())
+                        .ok_or_else(|| JitError::undefined_variable_at(name))?;
+                    let value = self.compile_expr(scope, value, Some(ty))?;
+                    self.builder.build_store(slot, value)?;
+                }
+                RtlStmt::Assign { .. } => {
+                    return Err(JitError::Unsupported("compiled assignment target".into()).into())
+                }
+                RtlStmt::Return(Some(expr)) => {
+                    let Some(ret) = ret else {
+                        return Err(JitError::Unsupported(format!(
+                            "returning a value from '{}', which returns 'Unit'",
+                            func.name()
+                        ))
+                        .into());
+                    };
+                    let value = self.compile_expr(scope, expr, Some(ret))?;
+                    self.builder.build_return(Some(&value))?;
+                    // Anything after the return is unreachable
+                    return Ok(());
+                }
+                RtlStmt::Return(None) if ret.is_none() => {
+                    self.builder.build_return(None)?;
+                    return Ok(());
+                }
+                RtlStmt::Return(None) => {
+                    return Err(JitError::Unsupported(format!(
+                        "`return;` in '{}', which returns '{}'",
+                        func.name(),
+                        func.ret()
+                    ))
+                    .into())
+                }
+                // A call's value may be dropped, including a `Unit` call's,
+                // which has none
+                RtlStmt::Expr(call @ RtlExpr::Call { name, args }) => {
+                    self.compile_call(scope, name, args, call.span())?;
+                }
+                RtlStmt::Expr(expr) => {
+                    self.compile_expr(scope, expr, None)?;
+                }
+                RtlStmt::Fn(inner) => {
+                    return Err(JitError::Unsupported(format!(
+                        "compiled nested function '{}'",
+                        inner.name()
+                    ))
+                    .into())
+                }
+                RtlStmt::IfLet(_) => {
+                    return Err(JitError::Unsupported("compiled `if let`".into()).into())
+                }
+                RtlStmt::If(_) => return Err(JitError::Unsupported("compiled `if`".into()).into()),
+                RtlStmt::While { .. } => {
+                    return Err(JitError::Unsupported("compiled `while`".into()).into())
+                }
+                RtlStmt::Defer(_) => {
+                    return Err(JitError::Unsupported("compiled `defer`".into()).into())
+                }
+                RtlStmt::Break { .. } => {
+                    return Err(JitError::Unsupported("compiled `break`".into()).into())
+                }
+            }
+        }
+        // A `Unit` function may end without a `return`
+        if ret.is_none() {
+            self.builder.build_return(None)?;
+            return Ok(());
+        }
+        Err(JitError::Unsupported(format!("'{}' ending without a return", func.name())).into())
+    }
+
+    /// Creates the compile unit and subprogram describing a function, and
+    /// attaches the subprogram to it.
+    fn debug_scope(
+        &self,
+        func: &RtlFn,
+        function: FunctionValue<'ctx>,
+    ) -> (DebugInfoBuilder<'ctx>, DIScope<'ctx>) {
+        // Without the version flag, LLVM strips debug info as malformed
+        if self.module.get_flag("Debug Info Version").is_none() {
+            let version = self.context.i32_type().const_int(3, false);
+            self.module
+                .add_basic_value_flag("Debug Info Version", FlagBehavior::Warning, version);
+        }
+
+        let file_name = format!("{}.rtl", self.module.get_name().to_string_lossy());
+        let (dibuilder, unit) = self.module.create_debug_info_builder(
+            true,
+            // DWARF has no code for Rattle; C is the closest match for debuggers
+            DWARFSourceLanguage::C,
+            &file_name,
+            ".",
+            "rtlc",
+            false,
+            "",
+            0,
+            "",
+            DWARFEmissionKind::Full,
+            0,
+            false,
+            false,
+            "",
+            "",
+        );
+        let file = unit.get_file();
+        let ty = dibuilder.create_subroutine_type(file, None, &[], DIFlags::PUBLIC);
+        let line = func.name().span().start().line as u32;
+        let subprogram = dibuilder.create_function(
+            unit.as_debug_info_scope(),
+            &func.name().unraw().to_string(),
+            None,
+            file,
+            line,
+            ty,
+            false,
+            true,
+            line,
+            DIFlags::PUBLIC,
+            false,
+        );
+        function.set_subprogram(subprogram);
+        (dibuilder, subprogram.as_debug_info_scope())
+    }
+
+    /// Tags the instructions built from here on with a span's location, if
+    /// debug info is enabled.
+    fn set_debug_location(&self, scope: &FnScope<'ctx>, span: Span) {
+        if let Some((dibuilder, di_scope)) = &scope.debug {
+            let start = span.start();
+            // Spans count columns from 0, DWARF from 1
+            let location = dibuilder.create_debug_location(
+                self.context,
+                start.line as u32,
+                start.column as u32 + 1,
+                *di_scope,
+                None,
+            );
+            self.builder.set_current_debug_location(location);
+        }
+    }
+
+    /// Compiles a call, giving its value, or `None` for a call to a `void`
+    /// function.
+    fn compile_call(
+        &self,
+        scope: &FnScope<'ctx>,
+        name: &Ident,
+        args: &[RtlExpr],
+        span: Span,
+    ) -> RtlResult<Option<BasicValueEnum<'ctx>>> {
+        let callee = self
+            .module
+            .get_function(&name.unraw().to_string())
+            .ok_or_else(|| JitError::UndefinedFunction(name.to_string()))?;
+        let params = callee.get_param_iter().collect::<Vec<_>>();
+        // Trailing arguments left out take their parameters' defaults
+        let defaults: Vec<RtlExpr> = match self.defaults.borrow().get(&name.unraw().to_string()) {
+            Some(defaults) if args.len() < defaults.len() => defaults[args.len()..]
+                .iter()
+                .map_while(Clone::clone)
+                .collect(),
+            _ => Vec::new(),
+        };
+        if params.len() != args.len() + defaults.len() {
+            return Err(JitError::ArityMismatch {
+                name: name.to_string(),
+                expected: params.len(),
+                found: args.len(),
+            }
+            .into());
+        }
+        let args = args
+            .iter()
+            .chain(&defaults)
+            .zip(&params)
+            .map(|(arg, param)| {
+                Ok(self
+                    .compile_expr(scope, arg, Some(param.get_type()))?
+                    .into())
+            })
+            .collect::<RtlResult<Vec<BasicMetadataValueEnum>>>()?;
+        self.set_debug_location(scope, span);
+        Ok(self
+            .builder
+            .build_call(callee, &args, "call")?
+            .try_as_basic_value()
+            .left())
+    }
+
+    /// Compiles an expression at the builder's position. `expected` is the
+    /// type the value is wanted at, which unsuffixed literals take on.
+    fn compile_expr(
+        &self,
+        scope: &FnScope<'ctx>,
+        expr: &RtlExpr,
+        expected: Option<BasicTypeEnum<'ctx>>,
+    ) -> RtlResult<BasicValueEnum<'ctx>> {
+        match expr {
+            RtlExpr::Lit(lit) => self.compile_lit(lit, expected),
+            RtlExpr::Ident(ident) => {
+                let name = ident.to_string();
+                let (slot, ty) = scope
+                    .locals
+                    .get(&name)
+                    .ok_or_else(|| JitError::undefined_variable_at(ident))?;
+                Ok(self.builder.build_load(*ty, *slot, &name)?)
+            }
+            RtlExpr::Binary { op, lhs, rhs } => {
+                let arithmetic = matches!(
+                    op,
+                    RtlBinOp::Add
+                        | RtlBinOp::Sub
+                        | RtlBinOp::Mul
+                        | RtlBinOp::Div
+                        | RtlBinOp::Rem
+                        | RtlBinOp::BitAnd
+                        | RtlBinOp::BitOr
+                        | RtlBinOp::BitXor
+                        | RtlBinOp::Shl
+                        | RtlBinOp::Shr
+                );
+                let expected = if arithmetic { expected } else { None };
+                // A literal operand takes the other operand's type, whichever side it's on
+                let (l, r) = if matches!(**lhs, RtlExpr::Lit(_)) {
+                    let r = self.compile_expr(scope, rhs, expected)?;
+                    (self.compile_expr(scope, lhs, Some(r.get_type()))?, r)
+                } else {
+                    let l = self.compile_expr(scope, lhs, expected)?;
+                    (l, self.compile_expr(scope, rhs, Some(l.get_type()))?)
+                };
+                // Operands may have moved the location onto their own spans
+                self.set_debug_location(scope, expr.span());
+                self.emit_binop(*op, l, r)
+            }
+            RtlExpr::Call { name, args } => self
+                .compile_call(scope, name, args, expr.span())?
+                .ok_or_else(|| JitError::Unsupported(format!("void call to '{}'", name)).into()),
+            RtlExpr::Conditional {
+                cond,
+                then,
+                else_branch,
+            } => {
+                let cond = match self.compile_expr(scope, cond, None)? {
+                    BasicValueEnum::IntValue(cond) if cond.get_type().get_bit_width() == 1 => cond,
+                    _ => return Err(JitError::Unsupported("non-`Bool` condition".into()).into()),
+                };
+                let function = self
+                    .builder
+                    .get_insert_block()
+                    .and_then(|block| block.get_parent())
+                    .ok_or_else(|| {
+                        JitError::Unsupported("conditional outside a function".into())
+                    })?;
+                let then_block = self.context.append_basic_block(function, "cond.then");
+                let else_block = self.context.append_basic_block(function, "cond.else");
+                let merge_block = self.context.append_basic_block(function, "cond.end");
+                self.set_debug_location(scope, expr.span());
+                self.builder
+                    .build_conditional_branch(cond, then_block, else_block)?;
+
+                // Only the chosen branch is evaluated; each may end in a block
+                // of its own, which is what the phi has to name
+                self.builder.position_at_end(then_block);
+                let then_value = self.compile_expr(scope, then, expected)?;
+                let then_end = self.builder.get_insert_block().unwrap();
+                self.builder.build_unconditional_branch(merge_block)?;
+
+                self.builder.position_at_end(else_block);
+                let else_value =
+                    self.compile_expr(scope, else_branch, Some(then_value.get_type()))?;
+                let else_end = self.builder.get_insert_block().unwrap();
+                self.builder.build_unconditional_branch(merge_block)?;
+
+                if then_value.get_type() != else_value.get_type() {
+                    return Err(
+                        JitError::Unsupported("conditional of mismatched branches".into()).into(),
+                    );
+                }
+                self.builder.position_at_end(merge_block);
+                let phi = self.builder.build_phi(then_value.get_type(), "cond")?;
+                phi.add_incoming(&[(&then_value, then_end), (&else_value, else_end)]);
+                Ok(phi.as_basic_value())
+            }
+            _ => Err(JitError::Unsupported("compiled expression".into()).into()),
+        }
+    }
+
+    /// Compiles a literal at its suffix's type, else the expected type, else
+    /// `Int` or `Float`.
+    fn compile_lit(
+        &self,
+        lit: &Lit,
+        expected: Option<BasicTypeEnum<'ctx>>,
+    ) -> RtlResult<BasicValueEnum<'ctx>> {
+        let suffix = match lit {
+            Lit::Int(int) => int.suffix(),
+            Lit::Float(float) => float.suffix(),
+            Lit::Bool(b) => {
+                return Ok(self
+                    .context
+                    .bool_type()
+                    .const_int(b.value as u64, false)
+                    .into())
+            }
+            _ => return Err(JitError::Unsupported("compiled literal".into()).into()),
+        };
+        let name = suffix_type_name(suffix);
+        let ty = match (name, expected) {
+            (Some(name), _) => self.llvm_named_type(name)?,
+            (None, Some(ty)) => ty,
+            (None, None) if matches!(lit, Lit::Float(_)) => self.context.f64_type().into(),
+            (None, None) => self.context.i128_type().into(),
+        };
+        match (ty, lit) {
+            // An unsuffixed literal's type only tells its width, not its sign
+            (BasicTypeEnum::IntType(int), Lit::Int(v)) => {
+                let signed = name.map(|name| !is_unsigned(name));
+                Ok(const_int(int, v.base10_parse::<i128>()?, signed)?.into())
+            }
+            (BasicTypeEnum::FloatType(float), Lit::Int(v)) => {
+                Ok(float.const_float(v.base10_parse::<f64>()?).into())
+            }
+            (BasicTypeEnum::FloatType(float), Lit::Float(v)) => {
+                Ok(float.const_float(v.base10_parse::<f64>()?).into())
+            }
+            _ => Err(JitError::Unsupported("literal of a mismatched type".into()).into()),
+        }
+    }
+
+    /// Compiles a program's constants and functions to an object file for
+    /// another machine, like `wasm32-unknown-unknown`.
+    ///
+    /// The program goes into a module of its own, so the JIT's module is left
+    /// as it was. Functions are compiled as by
+    /// [`compile_program`](JitCompiler::compile_program), so they may be in any
+    /// order.
+    pub fn compile_for_target(
+        &self,
+        rattle: &Rattle,
+        triple: &str,
+        out_path: impl AsRef<Path>,
+    ) -> RtlResult<()> {
+        Target::initialize_all(&InitializationConfig::default());
+        let target_triple = TargetTriple::create(triple);
+        let target = Target::from_triple(&target_triple)
+            .map_err(|msg| JitError::InvalidTarget(format!("'{}': {}", triple, msg)))?;
+        let machine = target
+            .create_target_machine(
+                &target_triple,
+                "generic",
+                "",
+                OptimizationLevel::Default,
+                RelocMode::Default,
+                CodeModel::Default,
+            )
+            .ok_or_else(|| {
+                JitError::InvalidTarget(format!("'{}' has no target machine", triple))
+            })?;
+
+        let name = self.module.get_name().to_string_lossy();
+        let mut cross = JitCompiler::new(self.context, &format!("{}.{}", name, triple));
+        cross.set_debug_info(self.debug_info);
+        cross.module.set_triple(&target_triple);
+        cross
+            .module
+            .set_data_layout(&machine.get_target_data().get_data_layout());
+        cross.compile_program(rattle)?;
+        cross.verify()?;
+
+        machine
+            .write_to_file(&cross.module, FileType::Object, out_path.as_ref())
+            .map_err(|msg| JitError::InvalidTarget(format!("'{}': {}", triple, msg)).into())
+    }
+
+    /// Writes the functions compiled so far to a relocatable object file for
+    /// the host machine, to be linked into a larger C or Rust program.
+    pub fn write_object(&self, path: &Path) -> RtlResult<()> {
+        if !self
+            .module
+            .get_functions()
+            .any(|func| func.count_basic_blocks() > 0)
+        {
+            return Err(JitError::InvalidModule("no functions have been compiled".into()).into());
+        }
+
+        let triple = TargetMachine::get_default_triple();
+        let target = Target::from_triple(&triple)
+            .map_err(|msg| JitError::InvalidTarget(format!("host: {}", msg)))?;
+        let machine = target
+            .create_target_machine(
+                &triple,
+                &TargetMachine::get_host_cpu_name().to_string(),
+                &TargetMachine::get_host_cpu_features().to_string(),
+                OptimizationLevel::Default,
+                RelocMode::PIC,
+                CodeModel::Default,
+            )
+            .ok_or_else(|| JitError::InvalidTarget("host has no target machine".into()))?;
+        self.module.set_triple(&triple);
+        machine
+            .write_to_file(&self.module, FileType::Object, path)
+            .map_err(|msg| JitError::InvalidModule(msg.to_string()).into())
+    }
+
+    /// Returns the textual LLVM IR of the module.
+    pub fn print_ir(&self) -> String {
+        self.module.print_to_string().to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use inkwell::{context::Context, values::AnyValue};
+    use rtl_parser::{parse, RtlDeclValue};
+
+    #[test]
+    fn test_emit_binop() {
+        let context = Context::create();
+        let jit_compiler = JitCompiler::new(&context, "jit_test");
+        let (i128_type, f64_type, bool_type) =
+            (context.i128_type(), context.f64_type(), context.bool_type());
+
+        let fn_type = context.void_type().fn_type(
+            &[
+                i128_type.into(),
+                i128_type.into(),
+                f64_type.into(),
+                f64_type.into(),
+                bool_type.into(),
+                bool_type.into(),
+            ],
+            false,
+        );
+        let function = jit_compiler.module.add_function("binops", fn_type, None);
+        let entry = context.append_basic_block(function, "entry");
+        jit_compiler.builder.position_at_end(entry);
+        let param = |n| function.get_nth_param(n).unwrap();
+
+        let cases = [
+            (RtlBinOp::Add, 0, "add i128"),
+            (RtlBinOp::Sub, 0, "sub i128"),
+            (RtlBinOp::Mul, 0, "mul i128"),
+            (RtlBinOp::Div, 0, "sdiv i128"),
+            (RtlBinOp::Rem, 0, "srem i128"),
+            (RtlBinOp::Eq, 0, "icmp eq i128"),
+            (RtlBinOp::Ne, 0, "icmp ne i128"),
+            (RtlBinOp::Lt, 0, "icmp slt i128"),
+            (RtlBinOp::Le, 0, "icmp sle i128"),
+            (RtlBinOp::Gt, 0, "icmp sgt i128"),
+            (RtlBinOp::Ge, 0, "icmp sge i128"),
+            (RtlBinOp::BitAnd, 0, "and i128"),
+            (RtlBinOp::BitOr, 0, "or i128"),
+            (RtlBinOp::BitXor, 0, "xor i128"),
+            (RtlBinOp::Shl, 0, "shl i128"),
+            (RtlBinOp::Shr, 0, "ashr i128"),
+            (RtlBinOp::Add, 2, "fadd double"),
+            (RtlBinOp::Sub, 2, "fsub double"),
+            (RtlBinOp::Mul, 2, "fmul double"),
+            (RtlBinOp::Div, 2, "fdiv double"),
+            (RtlBinOp::Rem, 2, "frem double"),
+            (RtlBinOp::Eq, 2, "fcmp oeq double"),
+            (RtlBinOp::Lt, 2, "fcmp olt double"),
+            (RtlBinOp::And, 4, "and i1"),
+            (RtlBinOp::Or, 4, "or i1"),
+        ];
+        for (op, first, instr) in cases {
+            let value = jit_compiler
+                .emit_binop(op, param(first), param(first + 1))
+                .unwrap();
+            let ir = value.print_to_string().to_string();
+            assert!(ir.contains(instr), "{}: {}", op, ir);
+        }
+
+        assert!(jit_compiler
+            .emit_binop(RtlBinOp::And, param(0), param(1))
+            .is_err());
+        assert!(jit_compiler
+            .emit_binop(RtlBinOp::Add, param(0), param(2))
+            .is_err());
+        assert!(jit_compiler
+            .emit_binop(RtlBinOp::Shl, param(2), param(3))
+            .is_err());
+
+        // A constant shift out of range is an error, and any other is masked
+        let too_far = i128_type.const_int(200, false).into();
+        let err = jit_compiler
+            .emit_binop(RtlBinOp::Shl, param(0), too_far)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Shift by 200 out of range for 128-bit integer"
+        );
+        let shifted = jit_compiler
+            .emit_binop(RtlBinOp::Shr, param(0), param(1))
+            .unwrap();
+        assert!(shifted.print_to_string().to_string().contains("%shamt"));
+    }
+
+    #[test]
+    fn test_compile_switch() {
+        let context = Context::create();
+        let jit_compiler = JitCompiler::new(&context, "jit_test");
+
+        let fn_type = context
+            .f64_type()
+            .fn_type(&[context.i64_type().into()], false);
+        let function = jit_compiler.module.add_function("pick", fn_type, None);
+        let entry = context.append_basic_block(function, "entry");
+        jit_compiler.builder.position_at_end(entry);
+
+        let scrutinee = function.get_nth_param(0).unwrap().into_int_value();
+        let cases = vec![
+            (JitValue::Int(0), JitValue::Float(10.0)),
+            (JitValue::Int(1), JitValue::Float(11.0)),
+            (JitValue::Int(2), JitValue::Float(12.0)),
+        ];
+        let value = jit_compiler
+            .compile_switch(scrutinee, &cases, JitValue::Float(-1.0))
+            .unwrap();
+        jit_compiler.builder.build_return(Some(&value)).unwrap();
+        jit_compiler.verify().unwrap();
+        assert!(jit_compiler.print_ir().contains("switch i64"));
+
+        let pick = unsafe {
+            jit_compiler
+                .get_execution_engine()
+                .get_function::<unsafe extern "C" fn(i64) -> f64>("pick")
+                .unwrap()
+        };
+        unsafe {
+            assert_eq!(pick.call(0), 10.0);
+            assert_eq!(pick.call(1), 11.0);
+            assert_eq!(pick.call(2), 12.0);
+            assert_eq!(pick.call(7), -1.0);
+        }
+    }
+
+    fn compile_src_const<'ctx>(
+        jit_compiler: &JitCompiler<'ctx>,
+        src: &str,
+    ) -> RtlResult<GlobalValue<'ctx>> {
+        let rattle = parse(src)?;
+        let RtlDeclValue::RtlConst(decl) = rattle.decls()[0].value() else {
+            panic!("expected a constant declaration");
+        };
+        jit_compiler.compile_const(decl)
+    }
+
+    #[test]
+    fn test_compile_suffixed_literals() {
+        let context = Context::create();
+        let jit_compiler = JitCompiler::new(&context, "jit_test");
+
+        compile_src_const(&jit_compiler, "const I32 x = 5i32;").unwrap();
+        compile_src_const(&jit_compiler, "const F32 y = 3.0f32;").unwrap();
+        compile_src_const(&jit_compiler, "const Int z = 7i128;").unwrap();
+        let ir = jit_compiler.print_ir();
+        assert!(ir.contains("@x = constant i32 5"), "{}", ir);
+        assert!(ir.contains("@y = constant float 3.0"), "{}", ir);
+        assert!(ir.contains("@z = constant i128 7"), "{}", ir);
+
+        assert!(compile_src_const(&jit_compiler, "const I32 w = 5i64;").is_err());
+
+        // A literal too big for its type fails instead of wrapping
+        compile_src_const(&jit_compiler, "const U8 a = 255u8;").unwrap();
+        compile_src_const(&jit_compiler, "const I8 b = 127;").unwrap();
+        for (src, expected) in [
+            (
+                "const U8 c = 300u8;",
+                "Literal 300 out of range for 8-bit integer",
+            ),
+            (
+                "const I8 d = 128i8;",
+                "Literal 128 out of range for 8-bit integer",
+            ),
+            (
+                "const I32 e = 4294967296;",
+                "Literal 4294967296 out of range for 32-bit integer",
+            ),
+        ] {
+            let err = compile_src_const(&jit_compiler, src).unwrap_err();
+            assert_eq!(err.to_string(), expected, "{}", src);
+        }
+    }
+
+    fn compile_src_fns<'ctx>(jit_compiler: &JitCompiler<'ctx>, src: &str) -> RtlResult<()> {
+        jit_compiler.compile_program(&parse(src)?)
+    }
+
+    #[test]
+    fn test_compile_fn() {
+        let context = Context::create();
+        let jit_compiler = JitCompiler::new(&context, "jit_test");
+
+        compile_src_fns(
+            &jit_compiler,
+            "f Double(Int x) Int { return x * 2; }
+             f Calc(Int x, Int y) Int { var Int mut z = x + y; z = Double(z); return z - 1; }",
+        )
+        .unwrap();
+        assert!(jit_compiler.verify().is_ok(), "{}", jit_compiler.print_ir());
+        assert_eq!(jit_compiler.run_i128_2("Calc", 3, 4).unwrap(), 13);
+
+        // A failed function is removed rather than left half-built
+        assert!(compile_src_fns(&jit_compiler, "f Bad(Int x) Int { var Int y = x; }").is_err());
+        assert!(jit_compiler.module.get_function("Bad").is_none());
+    }
+
+    #[test]
+    fn test_compile_recursive() {
+        let context = Context::create();
+        let jit_compiler = JitCompiler::new(&context, "jit_test");
+
+        compile_src_fns(
+            &jit_compiler,
+            "f Fib(Int n) Int { return n < 2 ? n : Fib(n - 1) + Fib(n - 2); }
+             f IsEven(Int n) Bool { return n == 0 ? true : IsOdd(n - 1); }
+             f IsOdd(Int n) Bool { return n == 0 ? false : IsEven(n - 1); }
+             f Parity(Int n) Int { return IsEven(n) ? 0 : 1; }",
+        )
+        .unwrap();
+        assert!(jit_compiler.verify().is_ok(), "{}", jit_compiler.print_ir());
+        assert_eq!(jit_compiler.call_i64("Fib", &[10]).unwrap(), 55);
+        assert_eq!(jit_compiler.call_i64("Parity", &[10]).unwrap(), 0);
+        assert_eq!(jit_compiler.call_i64("Parity", &[7]).unwrap(), 1);
+        // `IsEven` returns a `Bool`, so it can't be called as `fn(Int) Int`
+        let err = jit_compiler.call_i64("IsEven", &[4]).unwrap_err();
+        assert!(err.to_string().contains("only Int"), "{}", err);
+
+        // A failed body takes the whole program with it, so no compiled
+        // function is left calling one without a body
+        assert!(compile_src_fns(
+            &jit_compiler,
+            "f Ping(Int n) Int { return Pong(n); }
+             f Pong(Int n) Int { var Int m = n; }",
+        )
+        .is_err());
+        assert!(jit_compiler.module.get_function("Ping").is_none());
+        assert!(jit_compiler.module.get_function("Pong").is_none());
+        assert!(jit_compiler.verify().is_ok(), "{}", jit_compiler.print_ir());
+        assert_eq!(jit_compiler.call_i64("Fib", &[10]).unwrap(), 55);
+    }
+
+    #[test]
+    fn test_compile_default_args() {
+        let context = Context::create();
+        let jit_compiler = JitCompiler::new(&context, "jit_test");
+
+        compile_src_fns(
+            &jit_compiler,
+            "f Scale(Int x, Int by = 2, Int plus = 1) Int { return x * by + plus; }
+             f Twice(Int x) Int { return Scale(x); }
+             f Thrice(Int x) Int { return Scale(x, 3); }
+             f Exact(Int x) Int { return Scale(x, 3, 0); }",
+        )
+        .unwrap();
+        assert!(jit_compiler.verify().is_ok(), "{}", jit_compiler.print_ir());
+        assert_eq!(jit_compiler.call_i64("Twice", &[5]).unwrap(), 11);
+        assert_eq!(jit_compiler.call_i64("Thrice", &[5]).unwrap(), 16);
+        assert_eq!(jit_compiler.call_i64("Exact", &[5]).unwrap(), 15);
+
+        // Only parameters with defaults may be left out
+        let err = compile_src_fns(
+            &jit_compiler,
+            "f Pair(Int a, Int b) Int { return a + b; } f One() Int { return Pair(1); }",
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Function 'Pair' takes 2 arguments but 1 were given"
+        );
+    }
+
+    #[test]
+    fn test_compile_unit_fn() {
+        let context = Context::create();
+        let jit_compiler = JitCompiler::new(&context, "jit_test");
+
+        compile_src_fns(
+            &jit_compiler,
+            "f Touch(Int x) { var Int y = x; }
+             f Early(Int x) { return; }
+             f Main() -> Int { Touch(1); Early(2); return 3; }",
+        )
+        .unwrap();
+        assert!(jit_compiler.verify().is_ok(), "{}", jit_compiler.print_ir());
+        let touch = jit_compiler.module.get_function("Touch").unwrap();
+        assert!(touch.get_type().get_return_type().is_none());
+        assert_eq!(jit_compiler.call_i64("Main", &[]).unwrap(), 3);
+
+        // A `Unit` function has no value to return or use
+        assert!(compile_src_fns(&jit_compiler, "f Bad() { return 1; }").is_err());
+        let err =
+            compile_src_fns(&jit_compiler, "f UseIt() -> Int { return Touch(1); }").unwrap_err();
+        assert_eq!(err.to_string(), "Unsupported: void call to 'Touch'");
+    }
+
+    #[test]
+    fn test_compile_call_i64() {
+        let context = Context::create();
+        let jit_compiler = JitCompiler::new(&context, "jit_test");
+
+        compile_src_fns(
+            &jit_compiler,
+            "f AddThree(Int a, Int b, Int c) Int { return a + b + c; }",
+        )
+        .unwrap();
+        assert_eq!(jit_compiler.call_i64("AddThree", &[1, 2, 3]).unwrap(), 6);
+        assert_eq!(jit_compiler.call_i64("AddThree", &[-4, 0, 1]).unwrap(), -3);
+
+        let err = jit_compiler.call_i64("AddThree", &[1, 2]).unwrap_err();
+        assert!(err.to_string().contains("AddThree"), "{}", err);
+        assert!(jit_compiler.call_i64("Missing", &[]).is_err());
+        assert!(jit_compiler
+            .call_i64("AddThree", &[i64::MAX, 1, 0])
+            .is_err());
+    }
+
+    /// The fields recorded on a span, as names and debug-formatted values.
+    #[cfg(feature = "trace")]
+    type Fields = Vec<(String, String)>;
+
+    /// Collects the spans entered while it's the default subscriber, each as
+    /// its name and the fields recorded on it.
+    #[cfg(feature = "trace")]
+    #[derive(Clone, Default)]
+    struct SpanCollector {
+        spans: std::sync::Arc<std::sync::Mutex<Vec<(String, Fields)>>>,
+    }
+
+    #[cfg(feature = "trace")]
+    struct FieldVisitor<'a>(&'a mut Fields);
+
+    #[cfg(feature = "trace")]
+    impl tracing::field::Visit for FieldVisitor<'_> {
+        fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+            self.0.push((field.name().to_string(), value.to_string()));
+        }
+
+        fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+            self.0
+                .push((field.name().to_string(), format!("{:?}", value)));
+        }
+    }
+
+    #[cfg(feature = "trace")]
+    impl tracing::Subscriber for SpanCollector {
+        fn enabled(&self, _: &tracing::Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, attrs: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+            let mut fields = Vec::new();
+            attrs.record(&mut FieldVisitor(&mut fields));
+            let mut spans = self.spans.lock().unwrap();
+            spans.push((attrs.metadata().name().to_string(), fields));
+            tracing::span::Id::from_u64(spans.len() as u64)
+        }
+
+        fn record(&self, id: &tracing::span::Id, values: &tracing::span::Record<'_>) {
+            let mut spans = self.spans.lock().unwrap();
+            values.record(&mut FieldVisitor(&mut spans[id.into_u64() as usize - 1].1));
+        }
+
+        fn record_follows_from(&self, _: &tracing::span::Id, _: &tracing::span::Id) {}
+
+        fn event(&self, _: &tracing::Event<'_>) {}
+
+        fn enter(&self, _: &tracing::span::Id) {}
+
+        fn exit(&self, _: &tracing::span::Id) {}
+    }
+
+    #[cfg(feature = "trace")]
+    #[test]
+    fn test_trace_spans() {
+        let collector = SpanCollector::default();
+        tracing::subscriber::with_default(collector.clone(), || {
+            let context = Context::create();
+            let jit_compiler = JitCompiler::new(&context, "jit_test");
+            compile_src_fns(&jit_compiler, "f Inc(Int x) Int { return x + 1; }").unwrap();
+            assert_eq!(jit_compiler.run_i128_1("Inc", 1).unwrap(), 2);
+        });
+
+        let spans = collector.spans.lock().unwrap();
+        let names: Vec<_> = spans.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["compile_fn", "run_function"]);
+        for (name, fields) in spans.iter() {
+            let function = fields.iter().find(|(field, _)| field == "function");
+            assert_eq!(
+                function.map(|(_, value)| value.as_str()),
+                Some("Inc"),
+                "{}",
+                name
+            );
+            assert!(
+                fields.iter().any(|(field, _)| field == "elapsed_us"),
+                "{}",
+                name
+            );
+        }
+    }
+
+    extern "C" fn host_add(a: i64, b: i64) -> i64 {
+        a + b
+    }
+
+    #[test]
+    fn test_register_extern() {
+        let context = Context::create();
+        let mut jit_compiler = JitCompiler::new(&context, "jit_test");
+        let addr = host_add as *const () as usize;
+        let signature = ExternSig {
+            params: vec![JitKind::I64, JitKind::I64],
+            ret: JitKind::I64,
+        };
+
+        // `host_add` is an `extern "C" fn(i64, i64) -> i64` that lives forever
+        unsafe {
+            jit_compiler
+                .register_extern("add", addr, signature.clone())
+                .unwrap();
+        }
+        compile_src_fns(&jit_compiler, "f Sum() I64 { return add(2, add(3, 4)); }").unwrap();
+        assert!(jit_compiler.verify().is_ok(), "{}", jit_compiler.print_ir());
+        let sum = unsafe {
+            jit_compiler
+                .execution_engine
+                .get_function::<unsafe extern "C" fn() -> i64>("Sum")
+                .unwrap()
+                .call()
+        };
+        assert_eq!(sum, 9);
+
+        let err = unsafe { jit_compiler.register_extern("add", addr, signature) }.unwrap_err();
+        assert_eq!(err.to_string(), "Unsupported: redefining function 'add'");
+    }
+
+    #[test]
+    fn test_compile_debug_info() {
+        let context = Context::create();
+        let mut jit_compiler = JitCompiler::new(&context, "jit_test");
+        let src = "f Square(Int x) Int {\n    return x * x;\n}";
+
+        compile_src_fns(&jit_compiler, src).unwrap();
+        assert!(!jit_compiler.print_ir().contains("!dbg"));
+
+        jit_compiler.reset();
+        jit_compiler.set_debug_info(true);
+        compile_src_fns(&jit_compiler, src).unwrap();
+        let ir = jit_compiler.print_ir();
+        assert!(jit_compiler.verify().is_ok(), "{}", ir);
+        assert!(ir.contains("!dbg"), "{}", ir);
+        assert!(ir.contains("DISubprogram(name: \"Square\""), "{}", ir);
+        // The multiplication points at the return on line 2
+        assert!(ir.contains("DILocation(line: 2, column: 12"), "{}", ir);
+    }
+
+    #[test]
+    fn test_compile_for_target() {
+        let context = Context::create();
+        let jit_compiler = JitCompiler::new(&context, "jit_test");
+        let rattle = parse(
+            "const Int Base = 40;
+             f Add(Int x, Int y) Int { return x + y; }
+             f Answer() Int { return Add(2, 40); }",
+        )
+        .unwrap();
+        let out = std::env::temp_dir().join("rtlc_test_compile_for_target.o");
+
+        jit_compiler
+            .compile_for_target(&rattle, "wasm32-unknown-unknown", &out)
+            .unwrap();
+        let size = std::fs::metadata(&out).unwrap().len();
+        std::fs::remove_file(&out).unwrap();
+        assert!(size > 0);
+        // The program went into its own module, not the JIT's
+        assert!(jit_compiler.module.get_function("Answer").is_none());
+
+        let err = jit_compiler
+            .compile_for_target(&rattle, "nonsense-unknown-none", &out)
+            .unwrap_err();
+        assert!(err.to_string().starts_with("Invalid target:"), "{}", err);
+    }
+
+    #[test]
+    fn test_write_object() {
+        let context = Context::create();
+        let jit_compiler = JitCompiler::new(&context, "jit_test");
+        let out = std::env::temp_dir().join("rtlc_test_write_object.o");
+
+        let err = jit_compiler.write_object(&out).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid module: no functions have been compiled"
+        );
+
+        let rattle = parse("f Add(Int x, Int y) Int { return x + y; }").unwrap();
+        let RtlDeclValue::RtlFn(func) = rattle.decls()[0].value() else {
+            panic!("expected a function");
+        };
+        jit_compiler.compile_fn(func).unwrap();
+        jit_compiler.write_object(&out).unwrap();
+        let size = std::fs::metadata(&out).unwrap().len();
+        std::fs::remove_file(&out).unwrap();
+        assert!(size > 0);
+    }
+
+    #[test]
+    fn test_compile_timings() {
+        let context = Context::create();
+        let jit_compiler = JitCompiler::new(&context, "jit_test");
+        let rattle = parse("f Square(Int x) Int { return x * x; }").unwrap();
+        let RtlDeclValue::RtlFn(func) = rattle.decls()[0].value() else {
+            panic!("expected a function");
+        };
+
+        let (function, timings) = jit_compiler.compile_fn_timed(func).unwrap();
+        assert_eq!(function.count_params(), 1);
+        assert!(timings.ir_gen > Duration::ZERO);
+        assert!(timings.finalize > Duration::ZERO);
+        assert_eq!(jit_compiler.run_i128_1("Square", 9).unwrap(), 81);
+    }
+
+    #[test]
+    fn test_compile_const_array() {
+        let context = Context::create();
+        let jit_compiler = JitCompiler::new(&context, "jit_test");
+
+        let global =
+            compile_src_const(&jit_compiler, "const [Int] PRIMES = [2, 3, 5, 7];").unwrap();
+        assert!(global.is_constant());
+        let BasicValueEnum::ArrayValue(array) = global.get_initializer().unwrap() else {
+            panic!("expected an array initializer");
+        };
+        assert_eq!(array.get_type().len(), 4);
+
+        let ir = jit_compiler.print_ir();
+        assert!(ir.contains("@PRIMES = constant [4 x i128]"), "{}", ir);
+        assert!(jit_compiler.verify().is_ok());
+    }
+}
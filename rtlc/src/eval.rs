@@ -0,0 +1,1402 @@
+use std::{cmp::Ordering, collections::HashMap, error::Error, mem};
+
+use rtl_parser::{
+    Ident, Lit, Rattle, RtlArrayLen, RtlBinOp, RtlConstExpr, RtlDeclValue, RtlEnum, RtlEnumVariant,
+    RtlExpr, RtlFn, RtlStmt, RtlType,
+};
+
+use crate::{
+    interp::Interpreter, JitError, JitKind, JitValue, RtlResult, SpanInfo, MAX_REPEAT_LEN,
+};
+
+/// Options controlling how source values are lowered to jit values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoweringConfig {
+    /// The width of unsuffixed integer literals and of `Int`: 32, 64 or 128.
+    pub default_int_bits: u32,
+    /// The width of unsuffixed float literals and of `Float`: 32 or 64.
+    pub default_float_bits: u32,
+}
+
+impl Default for LoweringConfig {
+    fn default() -> Self {
+        Self {
+            default_int_bits: 128,
+            default_float_bits: 64,
+        }
+    }
+}
+
+impl LoweringConfig {
+    /// The kind that `Int` and unsuffixed integer literals lower to.
+    pub fn int_kind(&self) -> RtlResult<JitKind> {
+        match self.default_int_bits {
+            32 => Ok(JitKind::I32),
+            64 => Ok(JitKind::I64),
+            128 => Ok(JitKind::Int),
+            bits => Err(JitError::Unsupported(format!("{}-bit integer", bits)).into()),
+        }
+    }
+
+    /// The kind that `Float` and unsuffixed float literals lower to.
+    pub fn float_kind(&self) -> RtlResult<JitKind> {
+        match self.default_float_bits {
+            32 => Ok(JitKind::F32),
+            64 => Ok(JitKind::Float),
+            bits => Err(JitError::Unsupported(format!("{}-bit float", bits)).into()),
+        }
+    }
+}
+
+/// Maps scalar type names to the kind of value they hold.
+///
+/// Embedders implement this to add their own names, like a `Money` alias for
+/// `Int`, usually falling back to a [`DefaultTypeResolver`].
+pub trait TypeResolver {
+    /// Returns the kind a type name lowers to, or `None` if it names no
+    /// scalar type. Struct names are looked up separately.
+    fn resolve(&self, name: &str) -> Option<JitKind>;
+}
+
+/// Resolves the built-in scalar types, sizing `Int` and `Float` by a config.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DefaultTypeResolver {
+    pub config: LoweringConfig,
+}
+
+impl TypeResolver for DefaultTypeResolver {
+    fn resolve(&self, name: &str) -> Option<JitKind> {
+        match name {
+            "Int" => self.config.int_kind().ok(),
+            "Float" => self.config.float_kind().ok(),
+            "I32" => Some(JitKind::I32),
+            "I64" => Some(JitKind::I64),
+            "F32" => Some(JitKind::F32),
+            "String" => Some(JitKind::String),
+            "Bool" => Some(JitKind::Bool),
+            "Char" => Some(JitKind::Char),
+            "Bytes" => Some(JitKind::Bytes),
+            "Unit" => Some(JitKind::Unit),
+            _ => None,
+        }
+    }
+}
+
+/// Evaluates an expression at compile time against a program's declarations.
+///
+/// Constants the expression names are evaluated once each; a
+/// [`ConstEvalCtx`] keeps their values across expressions.
+pub fn eval_const(rattle: &Rattle, expr: &RtlExpr, config: &LoweringConfig) -> RtlResult<JitValue> {
+    let types = DefaultTypeResolver { config: *config };
+    ConstEvalCtx::new(rattle, *config, &types).eval(expr)
+}
+
+/// Evaluates a call on the host, without the JIT, when the function is pure
+/// integer arithmetic: a single `return` of `Int` parameters, unsuffixed
+/// integer literals and `+ - * / % & | ^ << >>`, taking and returning `Int`.
+///
+/// Returns `None` for any other function, for arguments that aren't one
+/// `Int` per parameter, and when the arithmetic overflows, divides by zero or
+/// shifts out of range, leaving those calls to compiled code. Compiled code
+/// takes a shift amount modulo the width, as [`JitCompiler::emit_binop`]
+/// describes.
+///
+/// [`JitCompiler::emit_binop`]: crate::JitCompiler::emit_binop
+pub fn try_fold_fn(func: &RtlFn, args: &[JitValue]) -> Option<JitValue> {
+    let is_int = |ty: &RtlType| matches!(ty, RtlType::Named(name) if name == "Int");
+    let [RtlStmt::Return(Some(expr))] = func.body()?.stmts() else {
+        return None;
+    };
+    if !is_int(func.ret()) || args.len() != func.args().len() {
+        return None;
+    }
+    let mut params = HashMap::new();
+    for (param, arg) in func.args().iter().zip(args) {
+        match arg {
+            JitValue::Int(value) if is_int(param.ty()) => {
+                params.insert(param.name().to_string(), *value);
+            }
+            _ => return None,
+        }
+    }
+    fold_int(expr, &params).map(JitValue::Int)
+}
+
+/// Folds integer arithmetic over known parameters, or `None` if the
+/// expression is anything else or the arithmetic fails.
+fn fold_int(expr: &RtlExpr, params: &HashMap<String, i128>) -> Option<i128> {
+    match expr {
+        RtlExpr::Ident(name) => params.get(&name.to_string()).copied(),
+        RtlExpr::Lit(Lit::Int(lit)) if lit.suffix().is_empty() => lit.base10_parse().ok(),
+        RtlExpr::Binary { op, lhs, rhs } => {
+            let (lhs, rhs) = (fold_int(lhs, params)?, fold_int(rhs, params)?);
+            match op {
+                RtlBinOp::Add => lhs.checked_add(rhs),
+                RtlBinOp::Sub => lhs.checked_sub(rhs),
+                RtlBinOp::Mul => lhs.checked_mul(rhs),
+                RtlBinOp::Div => lhs.checked_div(rhs),
+                RtlBinOp::Rem => lhs.checked_rem(rhs),
+                RtlBinOp::BitAnd => Some(lhs & rhs),
+                RtlBinOp::BitOr => Some(lhs | rhs),
+                RtlBinOp::BitXor => Some(lhs ^ rhs),
+                RtlBinOp::Shl => lhs.checked_shl(u32::try_from(rhs).ok()?),
+                RtlBinOp::Shr => lhs.checked_shr(u32::try_from(rhs).ok()?),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Evaluates every top-level constant of a program in order, checking each
+/// against its declared type as named by `types`, and every `static_assert`
+/// where it's declared, failing on the first whose condition is false. Type
+/// aliases are expanded wherever a type is checked, and each is checked for
+/// cycles where it's declared, even if nothing uses it.
+pub fn lower_program(
+    rattle: &Rattle,
+    config: &LoweringConfig,
+    types: &dyn TypeResolver,
+) -> RtlResult<Vec<(String, JitValue)>> {
+    let mut ctx = ConstEvalCtx::new(rattle, *config, types);
+    let mut values = Vec::new();
+    for decl in rattle.decls() {
+        if let RtlDeclValue::RtlConst(c) = decl.value() {
+            crate::trace_span!("lower_const", constant = %c.name());
+            let value = ctx.eval_const_named(c.name())?;
+            check_type(rattle, c.ty(), &value, types)?;
+            values.push((c.name().to_string(), value));
+        }
+        if let RtlDeclValue::RtlTypeAlias(alias) = decl.value() {
+            expand_alias(rattle, &RtlType::Named(alias.name().clone()))?;
+        }
+        if let RtlDeclValue::RtlStaticAssert(assert) = decl.value() {
+            let cond = ctx.eval(assert.cond())?;
+            let holds = cond.as_bool().ok_or_else(|| JitError::TypeMismatch {
+                expected: "Bool".to_string(),
+                found: cond.kind_name().to_string(),
+            })?;
+            if !holds {
+                let start = assert.cond().span().start();
+                return Err(JitError::StaticAssertFailed {
+                    message: assert.msg(),
+                    span: SpanInfo {
+                        line: start.line,
+                        column: start.column,
+                    },
+                }
+                .into());
+            }
+        }
+    }
+    Ok(values)
+}
+
+/// Evaluates constant expressions against a program's declarations, each
+/// constant at most once.
+///
+/// The value of every constant an expression names is cached by the
+/// constant's name, so later expressions naming it reuse the value. A
+/// constant named again while its own value is being evaluated is a
+/// [`CyclicConstant`](JitError::CyclicConstant) error.
+pub struct ConstEvalCtx<'a> {
+    rattle: &'a Rattle,
+    config: LoweringConfig,
+    types: &'a dyn TypeResolver,
+    state: ConstState,
+}
+
+/// The constants a [`ConstEvalCtx`] has evaluated and is evaluating. It moves
+/// between the context and the [`Interpreter`] evaluating a block in a
+/// constant, so both share one cache and see the same cycles.
+#[derive(Debug, Default)]
+pub(crate) struct ConstState {
+    values: HashMap<String, JitValue>,
+    /// The constants being evaluated, outermost first.
+    evaluating: Vec<String>,
+}
+
+impl<'a> ConstEvalCtx<'a> {
+    /// Creates a context with an empty cache, resolving type names by `types`.
+    pub fn new(rattle: &'a Rattle, config: LoweringConfig, types: &'a dyn TypeResolver) -> Self {
+        Self::with_state(rattle, config, types, ConstState::default())
+    }
+
+    /// Creates a context carrying on from `state`.
+    pub(crate) fn with_state(
+        rattle: &'a Rattle,
+        config: LoweringConfig,
+        types: &'a dyn TypeResolver,
+        state: ConstState,
+    ) -> Self {
+        ConstEvalCtx {
+            rattle,
+            config,
+            types,
+            state,
+        }
+    }
+
+    pub(crate) fn into_state(self) -> ConstState {
+        self.state
+    }
+
+    /// Returns the cached value of a constant, if it's been evaluated.
+    pub fn cached(&self, name: &str) -> Option<&JitValue> {
+        self.state.values.get(name)
+    }
+
+    /// Evaluates the constant a name refers to, or returns its cached value.
+    pub fn eval_const_named(&mut self, name: &Ident) -> RtlResult<JitValue> {
+        let key = name.to_string();
+        if let Some(value) = self.state.values.get(&key) {
+            return Ok(value.clone());
+        }
+        if let Some(start) = self.state.evaluating.iter().position(|c| *c == key) {
+            let mut cycle = self.state.evaluating[start..].to_vec();
+            cycle.push(key);
+            return Err(JitError::CyclicConstant(cycle).into());
+        }
+        let decl =
+            find_const(self.rattle, name).ok_or_else(|| JitError::undefined_variable_at(name))?;
+        self.state.evaluating.push(key.clone());
+        let value = self.eval(decl.data());
+        self.state.evaluating.pop();
+        let value = value?;
+        self.state.values.insert(key, value.clone());
+        Ok(value)
+    }
+
+    /// Turns an [`IntegerOverflow`](JitError::IntegerOverflow) in an
+    /// expression of the constant being evaluated into a
+    /// [`ConstantOverflow`](JitError::ConstantOverflow) naming it. An
+    /// expression outside any constant has no name to give.
+    fn name_overflow(&self, err: Box<dyn Error>, expr: &RtlExpr) -> Box<dyn Error> {
+        let Some(name) = self.state.evaluating.last() else {
+            return err;
+        };
+        if !matches!(err.downcast_ref(), Some(JitError::IntegerOverflow)) {
+            return err;
+        }
+        let start = expr.span().start();
+        JitError::ConstantOverflow {
+            name: name.clone(),
+            span: SpanInfo {
+                line: start.line,
+                column: start.column,
+            },
+        }
+        .into()
+    }
+
+    /// Evaluates an expression.
+    pub fn eval(&mut self, expr: &RtlExpr) -> RtlResult<JitValue> {
+        match expr {
+            RtlExpr::Lit(lit) => eval_lit(lit, &self.config),
+            RtlExpr::Ident(name) if find_const(self.rattle, name).is_none() => {
+                match self.rattle.find_variant(&name.to_string()) {
+                    Some((en, variant)) => {
+                        variant_value(self.rattle, en, variant, Vec::new(), self.types)
+                    }
+                    None => Err(JitError::undefined_variable_at(name).into()),
+                }
+            }
+            RtlExpr::Ident(name) => self.eval_const_named(name),
+            RtlExpr::Binary { op, lhs, rhs } => {
+                let lhs = self.eval(lhs)?;
+                if let Some(value) = short_circuit(*op, &lhs) {
+                    return Ok(value);
+                }
+                eval_binop(*op, lhs, self.eval(rhs)?).map_err(|err| self.name_overflow(err, expr))
+            }
+            RtlExpr::Field { base, field } => field_value(&self.eval(base)?, field),
+            RtlExpr::StructLit {
+                name,
+                fields,
+                spread,
+            } => {
+                let values = fields
+                    .iter()
+                    .map(|(field, expr)| Ok((field, self.eval(expr)?)))
+                    .collect::<RtlResult<Vec<_>>>()?;
+                let base = spread.as_deref().map(|base| self.eval(base)).transpose()?;
+                struct_value(self.rattle, name, values, base, self.types)
+            }
+            RtlExpr::Array(exprs) => array_value(
+                exprs
+                    .iter()
+                    .map(|expr| self.eval(expr))
+                    .collect::<RtlResult<Vec<_>>>()?,
+            ),
+            RtlExpr::Repeat { value, count } => array_value(repeat(self.eval(value)?, *count)?),
+            // Blocks declare locals, which only the interpreter keeps track
+            // of. It takes the constants along, so a constant it names is
+            // cached here and a cycle through the block is still caught.
+            RtlExpr::Block(_) => {
+                let mut interp = Interpreter::with_config(self.rattle, self.config);
+                interp.set_const_state(mem::take(&mut self.state));
+                let value = interp.eval(expr);
+                self.state = interp.take_const_state();
+                value
+            }
+            RtlExpr::Index { base, index } => index_value(&self.eval(base)?, &self.eval(index)?),
+            RtlExpr::Slice { base, range } => {
+                let RtlExpr::Range { start, end } = &**range else {
+                    return Err(JitError::Unsupported("slicing by a non-range".into()).into());
+                };
+                let base = self.eval(base)?;
+                let mut bound = |bound: &Option<Box<RtlExpr>>| {
+                    bound.as_deref().map(|expr| self.eval(expr)).transpose()
+                };
+                let (start, end) = (bound(start)?, bound(end)?);
+                slice_value(&base, start.as_ref(), end.as_ref())
+            }
+            RtlExpr::Range { .. } => {
+                Err(JitError::Unsupported("range outside a slice".into()).into())
+            }
+            RtlExpr::Conditional {
+                cond,
+                then,
+                else_branch,
+            } => {
+                let cond = self.eval(cond)?;
+                let chosen = if condition(&cond)? { then } else { else_branch };
+                self.eval(chosen)
+            }
+            RtlExpr::Cast { expr, ty } => {
+                self.eval(expr)?
+                    .cast_to(type_kind(self.rattle, ty, self.types)?)
+            }
+            RtlExpr::Call { name, args } => {
+                let Some((en, variant)) = self.rattle.find_variant(&name.to_string()) else {
+                    return Err(JitError::Unsupported("call in a constant".into()).into());
+                };
+                let fields = args
+                    .iter()
+                    .map(|arg| self.eval(arg))
+                    .collect::<RtlResult<Vec<_>>>()?;
+                variant_value(self.rattle, en, variant, fields, self.types)
+            }
+            RtlExpr::Spread(_) => Err(JitError::Unsupported("spread outside a call".into()).into()),
+            RtlExpr::None(_) => Ok(JitValue::Option(None)),
+            RtlExpr::Some(value) => Ok(JitValue::Option(Some(Box::new(self.eval(value)?)))),
+            RtlExpr::Match { .. } => {
+                Err(JitError::Unsupported("match in a constant".into()).into())
+            }
+            RtlExpr::Loop { .. } => Err(JitError::Unsupported("loop in a constant".into()).into()),
+        }
+    }
+}
+
+pub(crate) fn find_const<'a>(rattle: &'a Rattle, name: &Ident) -> Option<&'a RtlConstExpr> {
+    rattle.decls().iter().find_map(|decl| match decl.value() {
+        RtlDeclValue::RtlConst(c) if c.name() == name => Some(c),
+        _ => None,
+    })
+}
+
+pub(crate) fn eval_lit(lit: &Lit, config: &LoweringConfig) -> RtlResult<JitValue> {
+    match lit {
+        Lit::Int(v) if v.suffix().starts_with('f') => {
+            float_lit(v.base10_digits(), v.suffix(), config)
+        }
+        Lit::Int(v) => int_lit(v.base10_digits(), v.suffix(), config),
+        Lit::Float(v) => float_lit(v.base10_digits(), v.suffix(), config),
+        Lit::Str(v) => Ok(JitValue::String(v.value())),
+        Lit::Bool(v) => Ok(JitValue::Bool(v.value)),
+        Lit::Char(v) => Ok(JitValue::Char(v.value())),
+        Lit::ByteStr(v) => Ok(JitValue::Bytes(v.value())),
+        _ => Err(JitError::Unsupported("literal".into()).into()),
+    }
+}
+
+/// Lowers integer literal digits to the width of their suffix, or the default
+/// width when unsuffixed. Only the widths a [`JitValue`] has are accepted, so
+/// the narrower and unsigned suffixes codegen takes, like `u8`, fail here.
+fn int_lit(digits: &str, suffix: &str, config: &LoweringConfig) -> RtlResult<JitValue> {
+    let bits = match suffix {
+        "" => config.default_int_bits,
+        "i32" => 32,
+        "i64" => 64,
+        "i128" => 128,
+        _ => return Err(JitError::Unsupported(format!("`{}` literal", suffix)).into()),
+    };
+    match bits {
+        32 => Ok(JitValue::I32(digits.parse()?)),
+        64 => Ok(JitValue::I64(digits.parse()?)),
+        128 => Ok(JitValue::Int(digits.parse()?)),
+        _ => Err(JitError::Unsupported(format!("{}-bit integer", bits)).into()),
+    }
+}
+
+/// Lowers float literal digits like [`int_lit`] does integer ones.
+fn float_lit(digits: &str, suffix: &str, config: &LoweringConfig) -> RtlResult<JitValue> {
+    let bits = match suffix {
+        "" => config.default_float_bits,
+        "f32" => 32,
+        "f64" => 64,
+        _ => return Err(JitError::Unsupported(format!("`{}` literal", suffix)).into()),
+    };
+    match bits {
+        32 => Ok(JitValue::F32(digits.parse()?)),
+        64 => Ok(JitValue::Float(digits.parse()?)),
+        _ => Err(JitError::Unsupported(format!("{}-bit float", bits)).into()),
+    }
+}
+
+/// Returns the result of `&&` or `||` when the left operand alone decides it,
+/// in which case the right operand must not be evaluated.
+pub(crate) fn short_circuit(op: RtlBinOp, lhs: &JitValue) -> Option<JitValue> {
+    match (op, lhs) {
+        (RtlBinOp::And, JitValue::Bool(false)) => Some(JitValue::Bool(false)),
+        (RtlBinOp::Or, JitValue::Bool(true)) => Some(JitValue::Bool(true)),
+        _ => None,
+    }
+}
+
+/// Reads the condition of a `?:`, which must be a `Bool`.
+pub(crate) fn condition(value: &JitValue) -> RtlResult<bool> {
+    value.as_bool().copied().ok_or_else(|| {
+        JitError::TypeMismatch {
+            expected: "Bool".to_string(),
+            found: value.kind_name().to_string(),
+        }
+        .into()
+    })
+}
+
+/// Applies a binary operator, reporting type errors instead of panicking like
+/// the operator impls do.
+pub(crate) fn eval_binop(op: RtlBinOp, lhs: JitValue, rhs: JitValue) -> RtlResult<JitValue> {
+    let unsupported = || {
+        JitError::Unsupported(format!(
+            "`{}` on {} and {}",
+            op,
+            lhs.kind_name(),
+            rhs.kind_name()
+        ))
+    };
+    if lhs.kind() != rhs.kind() {
+        return Err(unsupported().into());
+    }
+    match op {
+        RtlBinOp::Eq => return Ok(JitValue::Bool(lhs == rhs)),
+        RtlBinOp::Ne => return Ok(JitValue::Bool(lhs != rhs)),
+        RtlBinOp::And | RtlBinOp::Or => {
+            let (Some(&l), Some(&r)) = (lhs.as_bool(), rhs.as_bool()) else {
+                return Err(unsupported().into());
+            };
+            let value = if op == RtlBinOp::And { l && r } else { l || r };
+            return Ok(JitValue::Bool(value));
+        }
+        _ if !lhs.is_numeric() => return Err(unsupported().into()),
+        RtlBinOp::Lt | RtlBinOp::Le | RtlBinOp::Gt | RtlBinOp::Ge => {
+            let ord = compare(&lhs, &rhs);
+            let value = match op {
+                RtlBinOp::Lt => ord == Some(Ordering::Less),
+                RtlBinOp::Le => matches!(ord, Some(Ordering::Less | Ordering::Equal)),
+                RtlBinOp::Gt => ord == Some(Ordering::Greater),
+                _ => matches!(ord, Some(Ordering::Greater | Ordering::Equal)),
+            };
+            return Ok(JitValue::Bool(value));
+        }
+        RtlBinOp::BitAnd => return lhs.bitand(rhs),
+        RtlBinOp::BitOr => return lhs.bitor(rhs),
+        RtlBinOp::BitXor => return lhs.bitxor(rhs),
+        RtlBinOp::Shl => return lhs.shl(rhs),
+        RtlBinOp::Shr => return lhs.shr(rhs),
+        _ => {}
+    }
+    let zero = matches!(rhs, JitValue::Int(0) | JitValue::I32(0) | JitValue::I64(0))
+        || rhs == JitValue::Float(0.0)
+        || rhs == JitValue::F32(0.0);
+    if matches!(op, RtlBinOp::Div | RtlBinOp::Rem) && zero {
+        return Err(JitError::Unsupported("division by zero".into()).into());
+    }
+    match op {
+        RtlBinOp::Add => lhs.checked_add(rhs),
+        RtlBinOp::Sub => lhs.checked_sub(rhs),
+        RtlBinOp::Mul => lhs.checked_mul(rhs),
+        RtlBinOp::Div => lhs.checked_div(rhs),
+        _ => lhs.checked_rem(rhs),
+    }
+}
+
+/// Orders two numbers of the same kind; `None` for NaN or other kinds.
+fn compare(lhs: &JitValue, rhs: &JitValue) -> Option<Ordering> {
+    match (lhs, rhs) {
+        (JitValue::Int(l), JitValue::Int(r)) => l.partial_cmp(r),
+        (JitValue::I32(l), JitValue::I32(r)) => l.partial_cmp(r),
+        (JitValue::I64(l), JitValue::I64(r)) => l.partial_cmp(r),
+        (JitValue::Float(l), JitValue::Float(r)) => l.partial_cmp(r),
+        (JitValue::F32(l), JitValue::F32(r)) => l.partial_cmp(r),
+        _ => None,
+    }
+}
+
+/// Reads a struct field.
+pub(crate) fn field_value(value: &JitValue, field: &Ident) -> RtlResult<JitValue> {
+    value.field(&field.to_string()).cloned().ok_or_else(|| {
+        JitError::UnknownField {
+            ty: value.kind_name().to_string(),
+            field: field.to_string(),
+        }
+        .into()
+    })
+}
+
+/// Builds an array, checking that every element has the same kind.
+pub(crate) fn array_value(items: Vec<JitValue>) -> RtlResult<JitValue> {
+    // An empty literal has no element to take the kind from
+    let elem = items.first().map_or(JitKind::Int, JitValue::kind);
+    if let Some(item) = items.iter().find(|item| item.kind() != elem) {
+        return Err(JitError::TypeMismatch {
+            expected: elem.name().to_string(),
+            found: item.kind_name().to_string(),
+        }
+        .into());
+    }
+    Ok(JitValue::Array { elem, items })
+}
+
+/// Repeats a value into an array's elements, up to [`MAX_REPEAT_LEN`] of them.
+pub(crate) fn repeat<T: Clone>(value: T, count: usize) -> RtlResult<Vec<T>> {
+    if count > MAX_REPEAT_LEN {
+        return Err(JitError::RepeatTooLong(count).into());
+    }
+    Ok(vec![value; count])
+}
+
+/// Converts an index value to a position, rejecting negative and non-integer indices.
+pub(crate) fn to_index(index: &JitValue) -> RtlResult<usize> {
+    let i = match index {
+        JitValue::Int(v) => usize::try_from(*v).ok(),
+        JitValue::I32(v) => usize::try_from(*v).ok(),
+        JitValue::I64(v) => usize::try_from(*v).ok(),
+        _ => None,
+    };
+    i.ok_or_else(|| JitError::Unsupported(format!("index {}", index)).into())
+}
+
+/// Reads an array element, or a string's character at a character index.
+pub(crate) fn index_value(value: &JitValue, index: &JitValue) -> RtlResult<JitValue> {
+    let i = to_index(index)?;
+    match value {
+        JitValue::String(_) => value.char_at(i),
+        JitValue::Array { items, .. } => items.get(i).cloned().ok_or_else(|| {
+            JitError::IndexOutOfBounds {
+                index: i,
+                len: items.len(),
+            }
+            .into()
+        }),
+        _ => Err(JitError::Unsupported(format!("indexing {}", value.kind_name())).into()),
+    }
+}
+
+/// Copies the elements of an array, or the characters of a string, from
+/// `start` up to `end`, which default to its ends.
+pub(crate) fn slice_value(
+    value: &JitValue,
+    start: Option<&JitValue>,
+    end: Option<&JitValue>,
+) -> RtlResult<JitValue> {
+    if let JitValue::String(s) = value {
+        let start = start.map(to_index).transpose()?.unwrap_or(0);
+        let end = end.map(to_index).transpose()?;
+        return value.substring(start..end.unwrap_or_else(|| s.chars().count()));
+    }
+    let JitValue::Array { elem, items } = value else {
+        return Err(JitError::Unsupported(format!("slicing {}", value.kind_name())).into());
+    };
+    let start = start.map(to_index).transpose()?.unwrap_or(0);
+    let end = end.map(to_index).transpose()?.unwrap_or(items.len());
+    let slice = items.get(start..end).ok_or(JitError::InvalidSlice {
+        start,
+        end,
+        len: items.len(),
+    })?;
+    Ok(JitValue::Array {
+        elem: *elem,
+        items: slice.to_vec(),
+    })
+}
+
+/// Builds a value of an enum variant from its evaluated payload, checking it
+/// against the variant's declared fields.
+pub(crate) fn variant_value(
+    rattle: &Rattle,
+    en: &RtlEnum,
+    variant: &RtlEnumVariant,
+    fields: Vec<JitValue>,
+    types: &dyn TypeResolver,
+) -> RtlResult<JitValue> {
+    if fields.len() != variant.fields().len() {
+        return Err(JitError::ArityMismatch {
+            name: variant.name().to_string(),
+            expected: variant.fields().len(),
+            found: fields.len(),
+        }
+        .into());
+    }
+    for (ty, value) in variant.fields().iter().zip(&fields) {
+        check_type(rattle, ty, value, types)?;
+    }
+    Ok(JitValue::Enum {
+        name: en.name().to_string(),
+        variant: variant.name().to_string(),
+        fields,
+    })
+}
+
+/// Builds a struct from evaluated field values, checking them against the declaration.
+pub(crate) fn struct_value(
+    rattle: &Rattle,
+    name: &Ident,
+    mut fields: Vec<(&Ident, JitValue)>,
+    base: Option<JitValue>,
+    types: &dyn TypeResolver,
+) -> RtlResult<JitValue> {
+    let decl = rattle
+        .find_struct(&name.to_string())
+        .ok_or_else(|| JitError::UndefinedStruct(name.to_string()))?;
+
+    // The `..base` of a literal must be the same struct
+    let mut base_fields = match base {
+        Some(JitValue::Struct {
+            name: base_name,
+            fields,
+        }) if *name == base_name => fields,
+        Some(other) => {
+            let found = match &other {
+                JitValue::Struct { name, .. } => name.clone(),
+                other => other.kind_name().to_string(),
+            };
+            return Err(JitError::TypeMismatch {
+                expected: name.to_string(),
+                found,
+            }
+            .into());
+        }
+        None => Vec::new(),
+    };
+
+    for (field, _) in &fields {
+        if !decl.fields().iter().any(|f| f.name() == *field) {
+            return Err(JitError::UnknownField {
+                ty: name.to_string(),
+                field: field.to_string(),
+            }
+            .into());
+        }
+    }
+
+    // Fields are stored in declaration order, whatever order the literal uses.
+    // Fields the literal sets win over the base's.
+    let mut values = Vec::new();
+    for declared in decl.fields() {
+        let value = match fields
+            .iter()
+            .position(|(field, _)| *field == declared.name())
+        {
+            Some(pos) => fields.swap_remove(pos).1,
+            None => {
+                let pos = base_fields
+                    .iter()
+                    .position(|(field, _)| declared.name() == field)
+                    .ok_or_else(|| JitError::MissingField {
+                        ty: name.to_string(),
+                        field: declared.name().to_string(),
+                    })?;
+                base_fields.swap_remove(pos).1
+            }
+        };
+        check_type(rattle, declared.ty(), &value, types)?;
+        values.push((declared.name().to_string(), value));
+    }
+
+    Ok(JitValue::Struct {
+        name: name.to_string(),
+        fields: values,
+    })
+}
+
+/// Follows a type through the aliases naming it to the type it stands for,
+/// or returns it as is if it names no alias. Only the outermost name is
+/// expanded; an alias used inside an array or option is expanded when that
+/// is checked.
+pub(crate) fn expand_alias<'a>(rattle: &'a Rattle, mut ty: &'a RtlType) -> RtlResult<&'a RtlType> {
+    let mut expanding: Vec<String> = Vec::new();
+    while let RtlType::Named(name) = ty {
+        let Some(alias) = rattle.find_type_alias(&name.to_string()) else {
+            break;
+        };
+        let name = alias.name().to_string();
+        if let Some(start) = expanding.iter().position(|a| *a == name) {
+            let mut cycle = expanding[start..].to_vec();
+            cycle.push(name);
+            return Err(JitError::CyclicTypeAlias(cycle).into());
+        }
+        expanding.push(name);
+        ty = alias.target();
+    }
+    Ok(ty)
+}
+
+/// Returns the kind of value a scalar Rattle type holds.
+pub(crate) fn type_kind(
+    rattle: &Rattle,
+    ty: &RtlType,
+    types: &dyn TypeResolver,
+) -> RtlResult<JitKind> {
+    let name = match expand_alias(rattle, ty)? {
+        RtlType::Named(name) => name,
+        RtlType::Optional(_) => return Ok(JitKind::Option),
+        RtlType::Array(_) | RtlType::FixedArray(..) | RtlType::Path(_) | RtlType::Ref { .. } => {
+            return Err(JitError::Unsupported(format!("type '{}'", ty)).into())
+        }
+    };
+    let name = name.to_string();
+    types
+        .resolve(&name)
+        .ok_or_else(|| JitError::Unsupported(format!("type '{}'", name)).into())
+}
+
+/// Checks that a value inhabits a declared Rattle type.
+pub(crate) fn check_type(
+    rattle: &Rattle,
+    ty: &RtlType,
+    value: &JitValue,
+    types: &dyn TypeResolver,
+) -> RtlResult<()> {
+    let ty = expand_alias(rattle, ty)?;
+    let matches = match (ty, value) {
+        (RtlType::Named(name), JitValue::Struct { name: actual, .. })
+        | (RtlType::Named(name), JitValue::Enum { name: actual, .. }) => name == actual,
+        (RtlType::Named(name), _) => match types.resolve(&name.to_string()) {
+            Some(kind) => value.kind() == kind,
+            None if rattle.find_struct(&name.to_string()).is_some()
+                || rattle.find_enum(&name.to_string()).is_some() =>
+            {
+                false
+            }
+            None => return Err(JitError::Unsupported(format!("type '{}'", name)).into()),
+        },
+        (RtlType::Array(inner), JitValue::Array { items, .. }) => {
+            return items
+                .iter()
+                .try_for_each(|item| check_type(rattle, inner, item, types))
+        }
+        (RtlType::Array(_), _) => false,
+        (RtlType::FixedArray(inner, len), JitValue::Array { items, .. }) => {
+            if matches!(len, RtlArrayLen::Lit(len) if *len != items.len()) {
+                return Err(JitError::TypeMismatch {
+                    expected: ty.to_string(),
+                    found: format!("array of length {}", items.len()),
+                }
+                .into());
+            }
+            return items
+                .iter()
+                .try_for_each(|item| check_type(rattle, inner, item, types));
+        }
+        (RtlType::FixedArray(..), _) => false,
+        (RtlType::Optional(inner), JitValue::Option(value)) => {
+            return match value {
+                Some(value) => check_type(rattle, inner, value, types),
+                None => Ok(()),
+            }
+        }
+        (RtlType::Optional(_), _) => false,
+        // Imported types and references have no values the evaluator can build yet
+        (RtlType::Path(_) | RtlType::Ref { .. }, _) => {
+            return Err(JitError::Unsupported(format!("type '{}'", ty)).into())
+        }
+    };
+    if !matches {
+        return Err(JitError::TypeMismatch {
+            expected: ty.to_string(),
+            found: value.kind_name().to_string(),
+        }
+        .into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rtl_parser::{parse, Span};
+
+    const PERSON: &str = "struct Person { String name, Int age }";
+
+    fn eval_src(src: &str) -> RtlResult<JitValue> {
+        let rattle = parse(&format!("{} {}", PERSON, src))?;
+        let RtlDeclValue::RtlConst(decl) = rattle.decls()[1].value() else {
+            panic!("expected a constant declaration");
+        };
+        eval_const(&rattle, decl.data(), &LoweringConfig::default())
+    }
+
+    #[test]
+    fn test_struct_lit() {
+        let person = eval_src(r#"const Person P = Person { age: 3, name: "x" };"#).unwrap();
+        assert_eq!(person.field("age"), Some(&JitValue::Int(3)));
+        assert_eq!(person.field("name"), Some(&JitValue::String("x".into())));
+        assert_eq!(person.to_string(), r#"Person { name: "x", age: 3 }"#);
+    }
+
+    #[test]
+    fn test_struct_lit_errors() {
+        let err = eval_src(r#"const Person P = Person { name: "x", age: 3, height: 2 };"#);
+        assert!(err.unwrap_err().to_string().contains("no field 'height'"));
+
+        let err = eval_src(r#"const Person P = Person { name: "x", age: "3" };"#);
+        assert!(err.unwrap_err().to_string().contains("expected Int"));
+
+        let err = eval_src(r#"const Person P = Person { name: "x" };"#);
+        assert!(err.unwrap_err().to_string().contains("missing field 'age'"));
+
+        let err = eval_src("const Person P = Robot { id: 1 };");
+        assert!(err.unwrap_err().to_string().contains("Robot"));
+    }
+
+    #[test]
+    fn test_struct_spread() {
+        let eval = |src: &str| {
+            let base =
+                r#"struct Robot { Int id } const Person Base = Person { name: "x", age: 3 };"#;
+            let rattle = parse(&format!("{} {} {}", PERSON, base, src))?;
+            let RtlDeclValue::RtlConst(decl) = rattle.decls()[3].value() else {
+                panic!("expected a constant declaration");
+            };
+            eval_const(&rattle, decl.data(), &LoweringConfig::default())
+        };
+
+        let person = eval("const Person P = Person { ..Base, age: 5 };").unwrap();
+        assert_eq!(person.field("name"), Some(&JitValue::String("x".into())));
+        assert_eq!(person.field("age"), Some(&JitValue::Int(5)));
+        let copy = eval("const Person P = Person { ..Base };").unwrap();
+        assert_eq!(copy.to_string(), r#"Person { name: "x", age: 3 }"#);
+
+        let err = eval("const Robot R = Robot { ..Base };").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Type mismatch: expected Robot, found Person"
+        );
+        let err = eval(r#"const Person P = Person { ..1, name: "y" };"#).unwrap_err();
+        assert_eq!(err.to_string(), "Type mismatch: expected Person, found Int");
+        let err = eval("const Person P = Person { ..Base, age: 1.5 };").unwrap_err();
+        assert!(err.to_string().contains("expected Int"), "{}", err);
+    }
+
+    #[test]
+    fn test_const_cache() {
+        // Each constant doubles the last, naming it twice, so evaluating
+        // them without the cache would take 2^64 steps
+        let mut src = String::from("const Int C0 = 1;");
+        for i in 1..=64 {
+            src.push_str(&format!(" const Int C{} = C{1} + C{1};", i, i - 1));
+        }
+        let rattle = parse(&src).unwrap();
+        let types = DefaultTypeResolver::default();
+        let mut ctx = ConstEvalCtx::new(&rattle, LoweringConfig::default(), &types);
+        let top = Ident::new("C64", Span::call_site());
+        assert_eq!(ctx.eval_const_named(&top).unwrap(), JitValue::Int(1 << 64));
+        assert_eq!(ctx.cached("C10"), Some(&JitValue::Int(1 << 10)));
+        assert!(ctx.cached("C65").is_none());
+
+        let values = lower_program(&rattle, &LoweringConfig::default(), &types).unwrap();
+        assert_eq!(values.len(), 65);
+    }
+
+    #[test]
+    fn test_const_cycle() {
+        let rattle = parse("const Int A = B + 1; const Int B = C; const Int C = A;").unwrap();
+        let err = lower_program(
+            &rattle,
+            &LoweringConfig::default(),
+            &DefaultTypeResolver::default(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Cyclic constant dependency: A -> B -> C -> A"
+        );
+
+        let err = eval_src("const Int X = X;").unwrap_err();
+        assert_eq!(err.to_string(), "Cyclic constant dependency: X -> X");
+
+        // Cycles through blocks, which the interpreter evaluates
+        let err = eval_src("const Int A = { A };").unwrap_err();
+        assert_eq!(err.to_string(), "Cyclic constant dependency: A -> A");
+        let err = eval_src("const Int A = { B }; const Int B = { var Int b = A; b };").unwrap_err();
+        assert_eq!(err.to_string(), "Cyclic constant dependency: B -> A -> B");
+        let value = eval_src("const Int A = { B + B }; const Int B = { 2 };").unwrap();
+        assert_eq!(value, JitValue::Int(4));
+    }
+
+    #[test]
+    fn test_static_assert() {
+        let lower = |src: &str| {
+            let rattle = parse(src).unwrap();
+            let types = DefaultTypeResolver::default();
+            lower_program(&rattle, &LoweringConfig::default(), &types)
+        };
+        let values = lower(
+            r#"const Int Size = 4;
+               static_assert(Size > 0, "size must be positive");
+               static_assert(Size % 2 == 0);"#,
+        );
+        assert_eq!(values.unwrap().len(), 1);
+
+        let err = lower(
+            r#"const Int Size = 0;
+               static_assert(Size > 0, "size must be positive");"#,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Static assertion failed at line 2, column 30: size must be positive"
+        );
+        let err = lower("static_assert(1 > 2);").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Static assertion failed at line 1, column 15"
+        );
+        let err = lower("static_assert(1 + 2);").unwrap_err();
+        assert_eq!(err.to_string(), "Type mismatch: expected Bool, found Int");
+    }
+
+    #[test]
+    fn test_type_alias() {
+        let lower = |src: &str| {
+            let rattle = parse(src).unwrap();
+            let types = DefaultTypeResolver::default();
+            lower_program(&rattle, &LoweringConfig::default(), &types)
+        };
+        let values = lower(
+            "type Id = UserId;
+             type UserId = Int;
+             struct User { Id id, [Id] friends }
+             const User U = User { id: 1, friends: [2, 3] };
+             const Id N = 1.5 as Id;",
+        )
+        .unwrap();
+        assert_eq!(
+            values[0].1,
+            JitValue::Struct {
+                name: "User".to_string(),
+                fields: vec![
+                    ("id".to_string(), JitValue::Int(1)),
+                    (
+                        "friends".to_string(),
+                        JitValue::Array {
+                            elem: JitKind::Int,
+                            items: vec![JitValue::Int(2), JitValue::Int(3)],
+                        }
+                    ),
+                ],
+            }
+        );
+        assert_eq!(values[1].1, JitValue::Int(1));
+
+        let err = lower("type Id = Int; struct User { Id id } const User U = User { id: 1.5 };")
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Type mismatch: expected Int, found Float");
+
+        // A cycle is an error even if no type uses the aliases
+        let err = lower("type A = B; type B = C; type C = A;").unwrap_err();
+        assert_eq!(err.to_string(), "Cyclic type alias: A -> B -> C -> A");
+        let err = lower("type A = A; const A X = 1;").unwrap_err();
+        assert_eq!(err.to_string(), "Cyclic type alias: A -> A");
+    }
+
+    #[test]
+    fn test_lowering_config() {
+        let rattle = parse("const Int X = 7; const Float Y = 1.5; const Int Z = 2i64;").unwrap();
+        let eval = |i: usize, config: &LoweringConfig| {
+            let RtlDeclValue::RtlConst(decl) = rattle.decls()[i].value() else {
+                panic!("expected a constant declaration");
+            };
+            eval_const(&rattle, decl.data(), config).unwrap()
+        };
+
+        let default = LoweringConfig::default();
+        assert_eq!(eval(0, &default), JitValue::Int(7));
+        assert_eq!(eval(1, &default), JitValue::Float(1.5));
+
+        let narrow = LoweringConfig {
+            default_int_bits: 32,
+            default_float_bits: 32,
+        };
+        assert_eq!(eval(0, &narrow), JitValue::I32(7));
+        assert_eq!(eval(1, &narrow), JitValue::F32(1.5));
+        // Suffixes win over the default
+        assert_eq!(eval(2, &narrow), JitValue::I64(2));
+    }
+
+    #[test]
+    fn test_type_resolver() {
+        struct Aliases;
+        impl TypeResolver for Aliases {
+            fn resolve(&self, name: &str) -> Option<JitKind> {
+                match name {
+                    "Money" => Some(JitKind::Int),
+                    other => DefaultTypeResolver::default().resolve(other),
+                }
+            }
+        }
+
+        let rattle = parse(
+            "struct Order { Money total, Int count }
+             const Money Price = 5 as Money * 2;
+             const Order O = Order { total: Price, count: 1 };",
+        )
+        .unwrap();
+        let config = LoweringConfig::default();
+        let values = lower_program(&rattle, &config, &Aliases).unwrap();
+        assert_eq!(values[0], ("Price".to_string(), JitValue::Int(10)));
+        assert_eq!(values[1].1.field("total"), Some(&JitValue::Int(10)));
+
+        let err = lower_program(&rattle, &config, &DefaultTypeResolver::default()).unwrap_err();
+        assert_eq!(err.to_string(), "Unsupported: type 'Money'");
+    }
+
+    #[test]
+    fn test_raw_string() {
+        let path = eval_src(r#"const String P = r"C:\temp";"#).unwrap();
+        assert_eq!(path, JitValue::String(r"C:\temp".to_string()));
+        assert_eq!(path.to_string(), r"C:\temp");
+    }
+
+    #[test]
+    fn test_cast() {
+        assert_eq!(
+            eval_src("const Float X = 5 as Float + 2.0;").unwrap(),
+            JitValue::Float(7.0)
+        );
+        assert_eq!(
+            eval_src("const Int X = 'a' as Int + 1;").unwrap(),
+            JitValue::Int(98)
+        );
+        let err = eval_src(r#"const Int X = "5" as Int;"#).unwrap_err();
+        assert_eq!(err.to_string(), "Cannot cast String to Int");
+    }
+
+    #[test]
+    fn test_slice() {
+        let xs = eval_src("const [Int] XS = [1, 2, 3, 4][1..3];").unwrap();
+        assert_eq!(xs.to_string(), "[2, 3]");
+        let xs = eval_src("const [Int] XS = [1, 2, 3, 4][2..];").unwrap();
+        assert_eq!(xs.to_string(), "[3, 4]");
+
+        let err = eval_src("const [Int] XS = [1, 2][1..5];").unwrap_err();
+        assert_eq!(err.to_string(), "Slice 1..5 out of bounds for length 2");
+        let err = eval_src("const [Int] XS = [1, 2][2..1];").unwrap_err();
+        assert_eq!(err.to_string(), "Slice 2..1 out of bounds for length 2");
+    }
+
+    #[test]
+    fn test_string_index() {
+        let c = eval_src(r#"const Char C = "héllo"[1];"#).unwrap();
+        assert_eq!(c, JitValue::Char('é'));
+        let s = eval_src(r#"const String S = "héllo"[1..4];"#).unwrap();
+        assert_eq!(s, JitValue::String("éll".into()));
+        let s = eval_src(r#"const String S = "héllo"[2..];"#).unwrap();
+        assert_eq!(s, JitValue::String("llo".into()));
+
+        let err = eval_src(r#"const Char C = "héllo"[5];"#).unwrap_err();
+        assert_eq!(err.to_string(), "Index 5 out of bounds for length 5");
+    }
+
+    #[test]
+    fn test_bytes() {
+        let rattle = parse(r#"var Bytes data = b"\x00\x01";"#).unwrap();
+        let RtlDeclValue::RtlVar(var) = rattle.decls()[0].value() else {
+            panic!("expected a variable declaration");
+        };
+        let config = LoweringConfig::default();
+        let data = eval_const(&rattle, var.data(), &config).unwrap();
+        check_type(&rattle, var.ty(), &data, &DefaultTypeResolver { config }).unwrap();
+        assert_eq!(data.as_bytes(), Some(&[0, 1][..]));
+    }
+
+    #[test]
+    fn test_array() {
+        let xs = eval_src("const [Int] XS = [1, 2, 3][1];").unwrap();
+        assert_eq!(xs, JitValue::Int(2));
+
+        let err = eval_src("const [Int] XS = [1, 2.0];").unwrap_err();
+        assert_eq!(err.to_string(), "Type mismatch: expected Int, found Float");
+    }
+
+    #[test]
+    fn test_fixed_array() {
+        let rattle = parse("const [Int; 3] XS = [1, 2, 3]; const [Int; 2] YS = [0; 3];").unwrap();
+        let types = DefaultTypeResolver::default();
+        let check = |i: usize| {
+            let RtlDeclValue::RtlConst(c) = rattle.decls()[i].value() else {
+                panic!("expected a constant declaration");
+            };
+            let value = eval_const(&rattle, c.data(), &LoweringConfig::default())?;
+            check_type(&rattle, c.ty(), &value, &types)
+        };
+        check(0).unwrap();
+        assert_eq!(
+            check(1).unwrap_err().to_string(),
+            "Type mismatch: expected [Int; 2], found array of length 3"
+        );
+    }
+
+    #[test]
+    fn test_optional() {
+        let none = eval_src("const Int? X = none;").unwrap();
+        assert_eq!(none, JitValue::Option(None));
+        assert_eq!(none.to_string(), "none");
+
+        let some = eval_src("const Int? X = some(2 + 3);").unwrap();
+        assert_eq!(some, JitValue::Option(Some(Box::new(JitValue::Int(5)))));
+        assert_eq!(some.to_string(), "some(5)");
+
+        let rattle = parse(r#"const Int? X = some("five"); const Int Y = none;"#).unwrap();
+        let types = DefaultTypeResolver::default();
+        for (decl, expected) in rattle.decls().iter().zip(["Int", "Int"]) {
+            let RtlDeclValue::RtlConst(c) = decl.value() else {
+                panic!("expected a constant declaration");
+            };
+            let value = eval_const(&rattle, c.data(), &LoweringConfig::default()).unwrap();
+            let err = check_type(&rattle, c.ty(), &value, &types).unwrap_err();
+            assert!(err
+                .to_string()
+                .starts_with(&format!("Type mismatch: expected {}", expected)));
+        }
+    }
+
+    #[test]
+    fn test_bool() {
+        assert_eq!(
+            eval_src("const Bool Ready = true;").unwrap(),
+            JitValue::Bool(true)
+        );
+        assert_eq!(
+            eval_src("const Bool Done = false || true && false;").unwrap(),
+            JitValue::Bool(false)
+        );
+    }
+
+    #[test]
+    fn test_signed_literals() {
+        let min = eval_src("const Int Min = -170141183460469231731687303715884105728;");
+        assert_eq!(min.unwrap(), JitValue::Int(i128::MIN));
+        let max = eval_src("const Int Max = +170141183460469231731687303715884105727;");
+        assert_eq!(max.unwrap(), JitValue::Int(i128::MAX));
+        let x = eval_src("const Int X = -170141183460469231731687303715884105727;");
+        assert_eq!(x.unwrap(), JitValue::Int(-i128::MAX));
+        let x = eval_src("const I32 X = -2147483648i32;");
+        assert_eq!(x.unwrap(), JitValue::I32(i32::MIN));
+        assert!(eval_src("const Int X = -170141183460469231731687303715884105729;").is_err());
+        assert!(eval_src("const I32 X = -2147483649i32;").is_err());
+        let x = eval_src("const Int X = 5i128;");
+        assert_eq!(x.unwrap(), JitValue::Int(5));
+        // Values have no narrower or unsigned integers to lower these to
+        for suffix in ["i8", "i16", "u8", "u16", "u32", "u64", "u128"] {
+            let err = eval_src(&format!("const Int X = 1{};", suffix)).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                format!("Unsupported: `{}` literal", suffix)
+            );
+        }
+
+        let x = eval_src("const Float X = -1.5;").unwrap();
+        assert_eq!(x, JitValue::Float(-1.5));
+        let x = eval_src("const Float X = -1.7976931348623157e308;").unwrap();
+        assert_eq!(x, JitValue::Float(f64::MIN));
+        let x = eval_src("const F32 X = +3.5f32;").unwrap();
+        assert_eq!(x, JitValue::F32(3.5));
+        let JitValue::Float(zero) = eval_src("const Float X = -0.0;").unwrap() else {
+            panic!("expected a Float");
+        };
+        assert!(zero == 0.0 && zero.is_sign_negative());
+    }
+
+    #[test]
+    fn test_exponent_literals() {
+        let c = eval_src("const Float c = 3.0e8;").unwrap();
+        assert_eq!(c, JitValue::Float(300000000.0));
+        let eps = eval_src("const Float eps = 2E-3;").unwrap();
+        assert_eq!(eps, JitValue::Float(0.002));
+        let small = eval_src("const F32 x = 1.5e-3f32;").unwrap();
+        assert_eq!(small, JitValue::F32(0.0015));
+    }
+
+    #[test]
+    fn test_bitwise() {
+        let x = eval_src("const Int X = 0b1100 & 0b1010;").unwrap();
+        assert_eq!(x, JitValue::Int(8));
+        let x = eval_src("const Int X = 0b1100 | 0b1010 ^ 1;").unwrap();
+        assert_eq!(x, JitValue::Int(0b1111));
+        let x = eval_src("const Int X = 1 << 4;").unwrap();
+        assert_eq!(x, JitValue::Int(16));
+        let x = eval_src("const I32 X = -16i32 >> 2i32;").unwrap();
+        assert_eq!(x, JitValue::I32(-4));
+
+        let err = eval_src("const Int X = 1 << 200;").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Shift by 200 out of range for 128-bit integer"
+        );
+        assert!(eval_src("const I32 X = 1i32 << 32i32;").is_err());
+        assert!(eval_src("const Int X = 1 >> -1;").is_err());
+        assert!(eval_src("const Float X = 1.0 & 2.0;").is_err());
+    }
+
+    #[test]
+    fn test_overflow() {
+        let err =
+            eval_src("const Int X = 170141183460469231731687303715884105727 + 1;").unwrap_err();
+        assert_eq!(err.to_string(), "integer overflow");
+        let err = eval_src("const I32 X = 65536i32 * 65536i32;").unwrap_err();
+        assert_eq!(err.to_string(), "integer overflow");
+        // The quotient of the minimum and -1 doesn't fit, and the remainder
+        // overflows with it
+        for src in [
+            "const I32 X = -2147483648i32 / -1i32;",
+            "const I32 X = -2147483648i32 % -1i32;",
+            "const I64 X = -9223372036854775808i64 / -1i64;",
+        ] {
+            let err = eval_src(src).unwrap_err();
+            assert_eq!(err.to_string(), "integer overflow", "{}", src);
+        }
+        assert_eq!(
+            eval_src("const Int X = -7 / 2;").unwrap(),
+            JitValue::Int(-3)
+        );
+        assert_eq!(
+            eval_src("const Int X = -7 % 2;").unwrap(),
+            JitValue::Int(-1)
+        );
+    }
+
+    #[test]
+    fn test_constant_overflow() {
+        let lower = |src: &str| {
+            let rattle = parse(src).unwrap();
+            let types = DefaultTypeResolver::default();
+            lower_program(&rattle, &LoweringConfig::default(), &types)
+        };
+        // 2^126 - 1 doubles to just under `Int`'s maximum, and 2^126 to just over
+        let values = lower("const Int X = 85070591730234615865843651857942052863 * 2;").unwrap();
+        assert_eq!(values[0].1, JitValue::Int(i128::MAX - 1));
+        let err = lower("const Int X = 85070591730234615865843651857942052864 * 2;").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Constant overflow in 'X' at line 1, column 15"
+        );
+        let err = lower("const Int Y = 4;\nconst Int X = Y * (4611686018427387904i64 * 2i64);")
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Constant overflow in 'X' at line 2, column 20"
+        );
+
+        // Dividing the minimum by -1 overflows like any other arithmetic
+        let err =
+            lower("const Int X = -170141183460469231731687303715884105728 / -1;").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Constant overflow in 'X' at line 1, column 15"
+        );
+        let err =
+            lower("const Int X = -170141183460469231731687303715884105728 % -1;").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Constant overflow in 'X' at line 1, column 15"
+        );
+
+        // The constant the overflow is in is named, not the one using it
+        let err = lower(
+            "const Int Y = X + 1;\nconst Int X = -85070591730234615865843651857942052864 * 3;",
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Constant overflow in 'X' at line 2, column 15"
+        );
+    }
+
+    #[test]
+    fn test_conditional() {
+        // The branch that isn't chosen would fail if it were evaluated
+        let x = eval_src("const Int X = 1 < 2 ? 10 : 1 / 0;").unwrap();
+        assert_eq!(x, JitValue::Int(10));
+        let x = eval_src("const Int X = 2 < 1 ? 1 / 0 : 20;").unwrap();
+        assert_eq!(x, JitValue::Int(20));
+
+        let err = eval_src("const Int X = 1 ? 10 : 20;").unwrap_err();
+        assert_eq!(err.to_string(), "Type mismatch: expected Bool, found Int");
+    }
+
+    #[test]
+    fn test_block() {
+        let value = eval_src("const Int X = { var Int a = 2; a + 1 };").unwrap();
+        assert_eq!(value, JitValue::Int(3));
+        let unit = eval_src("const Unit X = { var Int a = 2; a + 1; };").unwrap();
+        assert_eq!(unit, JitValue::Unit);
+    }
+
+    #[test]
+    fn test_repeat() {
+        let xs = eval_src("const [Int] XS = [1; 3];").unwrap();
+        assert_eq!(
+            xs,
+            JitValue::Array {
+                elem: JitKind::Int,
+                items: vec![JitValue::Int(1); 3],
+            }
+        );
+        let empty = eval_src("const [Int] XS = [0; 0];").unwrap();
+        assert_eq!(
+            empty,
+            JitValue::Array {
+                elem: JitKind::Int,
+                items: Vec::new(),
+            }
+        );
+        assert!(eval_src("const [Int] XS = [1; -2];").is_err());
+        // A count too big to allocate fails instead of aborting
+        let err = eval_src("const [Int] XS = [0; 99999999999999];").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Repeat array of 99999999999999 elements is longer than 1048576"
+        );
+        let err = eval_src("const [Int] XS = { [0; 99999999999999] };").unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<JitError>(),
+            Some(JitError::RepeatTooLong(99999999999999))
+        ));
+    }
+    #[test]
+    fn test_try_fold_fn() {
+        let rattle = parse(
+            "f Sq(Int x) Int { return x*x; }
+             f Poly(Int x, Int y) Int { x * 3 - y / 2 + 1 }
+             f Half(Int x) Float { return x / 2; }
+             f Loud(Int x) Int { print(x); return x; }",
+        )
+        .unwrap();
+        let fold = |name: &str, args: &[JitValue]| try_fold_fn(rattle.find_fn(name)?, args);
+
+        assert_eq!(fold("Sq", &[JitValue::Int(4)]), Some(JitValue::Int(16)));
+        assert_eq!(
+            fold("Poly", &[JitValue::Int(5), JitValue::Int(7)]),
+            Some(JitValue::Int(13))
+        );
+        // Left to the JIT: other kinds, wrong arity, overflow, side effects
+        assert_eq!(fold("Sq", &[JitValue::I64(4)]), None);
+        assert_eq!(fold("Sq", &[]), None);
+        assert_eq!(fold("Sq", &[JitValue::Int(i128::MAX)]), None);
+        assert_eq!(fold("Half", &[JitValue::Int(4)]), None);
+        assert_eq!(fold("Loud", &[JitValue::Int(4)]), None);
+    }
+}
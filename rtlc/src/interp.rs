@@ -0,0 +1,1369 @@
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    fmt, mem,
+};
+
+use rtl_parser::{
+    Ident, Rattle, RtlBody, RtlElse, RtlExpr, RtlFn, RtlIf, RtlIfLet, RtlMatchArm, RtlPat, RtlStmt,
+};
+
+use crate::{
+    eval::{self, ConstEvalCtx, ConstState, DefaultTypeResolver, LoweringConfig},
+    JitError, JitMeta, JitValue, RtlResult,
+};
+
+/// A native function callable from interpreted code.
+pub type NativeFn<'a> = Box<dyn FnMut(&[JitValue]) -> RtlResult<JitValue> + 'a>;
+
+/// How many times an interpreted `loop` may run before it's taken to never
+/// end, unless set with [`Interpreter::set_max_loop_iterations`].
+pub const DEFAULT_MAX_LOOP_ITERATIONS: usize = 1_000_000;
+
+/// How many calls deep interpreted code may go before it's taken to recurse
+/// forever, unless set with [`Interpreter::set_max_call_depth`]. Each call
+/// takes tens of kilobytes of the thread's stack in a debug build, more when
+/// it goes through blocks or `match` arms, so this stays within the main
+/// thread's 8 MiB one.
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 64;
+
+/// Interprets one function of a program with already evaluated arguments,
+/// like [`Interpreter::call`] on a fresh interpreter.
+pub fn interpret_fn(rattle: &Rattle, name: &str, args: &[JitValue]) -> RtlResult<JitValue> {
+    Interpreter::new(rattle).call(name, args.to_vec())
+}
+
+/// A local variable of an interpreted call.
+#[derive(Clone)]
+struct Local {
+    value: JitValue,
+    meta: JitMeta,
+}
+
+/// A `break` on its way out to its loop. It travels as an error, so every
+/// expression and body it leaves unwinds as it would for one, running its
+/// `defer`s; the loop it's for takes it back out.
+#[derive(Debug)]
+struct Break {
+    label: Option<String>,
+    value: JitValue,
+}
+
+impl Break {
+    fn outside_loop(self) -> JitError {
+        JitError::BreakOutsideLoop(self.label)
+    }
+}
+
+impl fmt::Display for Break {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        JitError::BreakOutsideLoop(self.label.clone()).fmt(f)
+    }
+}
+
+impl Error for Break {}
+
+/// A tree-walking interpreter over a parsed program.
+pub struct Interpreter<'a> {
+    rattle: &'a Rattle,
+    config: LoweringConfig,
+    natives: HashMap<String, NativeFn<'a>>,
+    /// The locals of each active call, innermost last.
+    frames: Vec<HashMap<String, Local>>,
+    max_loop_iterations: usize,
+    max_call_depth: usize,
+    /// How many calls deep the interpreter is. Blocks, `match` arms and loop
+    /// iterations push frames too, so this isn't `frames.len()`.
+    depth: usize,
+    /// The constants evaluated so far, and those being evaluated.
+    consts: ConstState,
+}
+
+impl<'a> Interpreter<'a> {
+    /// Creates an interpreter that lowers literals with the default config.
+    pub fn new(rattle: &'a Rattle) -> Self {
+        Self::with_config(rattle, LoweringConfig::default())
+    }
+
+    pub fn with_config(rattle: &'a Rattle, config: LoweringConfig) -> Self {
+        Self {
+            rattle,
+            config,
+            natives: HashMap::new(),
+            frames: Vec::new(),
+            max_loop_iterations: DEFAULT_MAX_LOOP_ITERATIONS,
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            depth: 0,
+            consts: ConstState::default(),
+        }
+    }
+
+    /// Carries on from a constant evaluator's state, for a block inside a
+    /// constant.
+    pub(crate) fn set_const_state(&mut self, state: ConstState) {
+        self.consts = state;
+    }
+
+    pub(crate) fn take_const_state(&mut self) -> ConstState {
+        mem::take(&mut self.consts)
+    }
+
+    /// Sets how many times a `loop` may run before it fails with
+    /// [`JitError::LoopLimit`], so a loop that never breaks can't hang the
+    /// interpreter.
+    pub fn set_max_loop_iterations(&mut self, max: usize) {
+        self.max_loop_iterations = max;
+    }
+
+    /// Sets how many calls deep interpreted code may go before it fails with
+    /// [`JitError::CallDepthLimit`], so unbounded recursion can't overflow
+    /// the interpreter's stack. Deeper recursion needs a thread with a bigger
+    /// stack to run on.
+    pub fn set_max_call_depth(&mut self, max: usize) {
+        self.max_call_depth = max;
+    }
+
+    /// Registers a native function, which shadows Rattle functions of the same name.
+    pub fn register_native(
+        &mut self,
+        name: &str,
+        func: impl FnMut(&[JitValue]) -> RtlResult<JitValue> + 'a,
+    ) {
+        self.natives.insert(name.to_string(), Box::new(func));
+    }
+
+    /// Calls a function with already evaluated arguments, one per parameter.
+    pub fn call(&mut self, name: &str, args: Vec<JitValue>) -> RtlResult<JitValue> {
+        if let Some(native) = self.natives.get_mut(name) {
+            return native(&args);
+        }
+        let func = self.find_fn(name)?;
+        self.call_fn(func, args)
+    }
+
+    /// Calls a function that needn't be declared at the top level, like a
+    /// `gen` block's method, with one argument per parameter. Calls in its
+    /// body still resolve against the interpreter's program.
+    pub fn call_fn(&mut self, func: &'a RtlFn, args: Vec<JitValue>) -> RtlResult<JitValue> {
+        if args.len() != func.args().len() {
+            return Err(JitError::ArityMismatch {
+                name: func.name().to_string(),
+                expected: func.args().len(),
+                found: args.len(),
+            }
+            .into());
+        }
+        self.invoke(func, args)
+    }
+
+    /// Evaluates an expression in the innermost call's scope.
+    pub fn eval(&mut self, expr: &RtlExpr) -> RtlResult<JitValue> {
+        match expr {
+            RtlExpr::Lit(lit) => eval::eval_lit(lit, &self.config),
+            RtlExpr::Ident(name) => {
+                if let Some(local) = self.local(name) {
+                    return Ok(local.value.clone());
+                }
+                if eval::find_const(self.rattle, name).is_some() {
+                    let types = self.types();
+                    let state = mem::take(&mut self.consts);
+                    let mut ctx = ConstEvalCtx::with_state(self.rattle, self.config, &types, state);
+                    let value = ctx.eval_const_named(name);
+                    self.consts = ctx.into_state();
+                    return value;
+                }
+                let (en, variant) = self
+                    .rattle
+                    .find_variant(&name.to_string())
+                    .ok_or_else(|| JitError::undefined_variable_at(name))?;
+                eval::variant_value(self.rattle, en, variant, Vec::new(), &self.types())
+            }
+            RtlExpr::Binary { op, lhs, rhs } => {
+                let lhs = self.eval(lhs)?;
+                // The right operand may have side effects, so it's only
+                // evaluated when the left one doesn't decide the result
+                if let Some(value) = eval::short_circuit(*op, &lhs) {
+                    return Ok(value);
+                }
+                let rhs = self.eval(rhs)?;
+                eval::eval_binop(*op, lhs, rhs)
+            }
+            RtlExpr::Field { base, field } => eval::field_value(&self.eval(base)?, field),
+            RtlExpr::Index { base, index } => {
+                let base = self.eval(base)?;
+                eval::index_value(&base, &self.eval(index)?)
+            }
+            RtlExpr::Slice { base, range } => {
+                let RtlExpr::Range { start, end } = &**range else {
+                    return Err(JitError::Unsupported("slicing by a non-range".into()).into());
+                };
+                let base = self.eval(base)?;
+                let start = start.as_deref().map(|e| self.eval(e)).transpose()?;
+                let end = end.as_deref().map(|e| self.eval(e)).transpose()?;
+                eval::slice_value(&base, start.as_ref(), end.as_ref())
+            }
+            RtlExpr::Range { .. } => {
+                Err(JitError::Unsupported("range outside a slice".into()).into())
+            }
+            RtlExpr::Conditional {
+                cond,
+                then,
+                else_branch,
+            } => {
+                let cond = self.eval(cond)?;
+                let chosen = if eval::condition(&cond)? {
+                    then
+                } else {
+                    else_branch
+                };
+                self.eval(chosen)
+            }
+            RtlExpr::Array(exprs) => {
+                let items = exprs
+                    .iter()
+                    .map(|expr| self.eval(expr))
+                    .collect::<RtlResult<Vec<_>>>()?;
+                eval::array_value(items)
+            }
+            RtlExpr::Block(block) => self.eval_block(block),
+            RtlExpr::Repeat { value, count } => {
+                eval::array_value(eval::repeat(self.eval(value)?, *count)?)
+            }
+            RtlExpr::StructLit {
+                name,
+                fields,
+                spread,
+            } => {
+                let values = fields
+                    .iter()
+                    .map(|(field, expr)| Ok((field, self.eval(expr)?)))
+                    .collect::<RtlResult<Vec<_>>>()?;
+                let base = spread.as_deref().map(|base| self.eval(base)).transpose()?;
+                eval::struct_value(self.rattle, name, values, base, &self.types())
+            }
+            RtlExpr::Cast { expr, ty } => {
+                let kind = eval::type_kind(self.rattle, ty, &self.types())?;
+                self.eval(expr)?.cast_to(kind)
+            }
+            RtlExpr::Call { name, args } => {
+                let name_str = name.to_string();
+                if self.natives.contains_key(&name_str) {
+                    let args = self.eval_args(args)?;
+                    return self.call(&name_str, args);
+                }
+                if self.rattle.find_fn(&name_str).is_none() {
+                    if let Some((en, variant)) = self.rattle.find_variant(&name_str) {
+                        let fields = self.eval_args(args)?;
+                        return eval::variant_value(
+                            self.rattle,
+                            en,
+                            variant,
+                            fields,
+                            &self.types(),
+                        );
+                    }
+                }
+                let func = self.find_fn(&name_str)?;
+                if !args.iter().any(|arg| matches!(arg, RtlExpr::Spread(_))) {
+                    let args = func
+                        .call_args(args)?
+                        .into_iter()
+                        .map(|arg| self.eval(arg))
+                        .collect::<RtlResult<Vec<_>>>()?;
+                    return self.invoke(func, args);
+                }
+                // How many arguments a spread gives is only known once it's evaluated
+                let mut values = self.eval_args(args)?;
+                for param in func.args().iter().skip(values.len()) {
+                    let Some(default) = param.default() else {
+                        break;
+                    };
+                    values.push(self.eval(default)?);
+                }
+                if values.len() != func.args().len() {
+                    return Err(JitError::ArityMismatch {
+                        name: name_str,
+                        expected: func.args().len(),
+                        found: values.len(),
+                    }
+                    .into());
+                }
+                self.invoke(func, values)
+            }
+            RtlExpr::None(_) => Ok(JitValue::Option(None)),
+            RtlExpr::Some(value) => Ok(JitValue::Option(Some(Box::new(self.eval(value)?)))),
+            RtlExpr::Spread(_) => Err(JitError::Unsupported("spread outside a call".into()).into()),
+            RtlExpr::Match { scrutinee, arms } => {
+                let value = self.eval(scrutinee)?;
+                for arm in arms {
+                    if let Some(result) = self.eval_arm(arm, &value)? {
+                        return Ok(result);
+                    }
+                }
+                Err(JitError::NoMatchingArm(value.to_string()).into())
+            }
+            RtlExpr::Loop { label, body } => self.eval_loop(label.as_ref(), body),
+        }
+    }
+
+    /// Runs a loop's body until a `break` for it, yielding the value it breaks
+    /// with, or `Unit`. Each run of the body is a block of its own.
+    fn eval_loop(&mut self, label: Option<&Ident>, body: &RtlBody) -> RtlResult<JitValue> {
+        if has_return(body.stmts()) {
+            return Err(JitError::Unsupported("`return` inside a loop".into()).into());
+        }
+        for _ in 0..self.max_loop_iterations {
+            let err = match self.eval_block(body) {
+                Ok(_) => continue,
+                Err(err) => err,
+            };
+            match err.downcast::<Break>() {
+                // An unlabelled `break` is for the innermost loop
+                Ok(brk) if brk.label.is_none() || brk.label == label.map(Ident::to_string) => {
+                    return Ok(brk.value)
+                }
+                Ok(brk) => return Err(brk),
+                Err(err) => return Err(err),
+            }
+        }
+        Err(JitError::LoopLimit(self.max_loop_iterations).into())
+    }
+
+    /// Runs a block expression in a scope of its own, yielding its trailing
+    /// expression or `Unit`.
+    fn eval_block(&mut self, block: &RtlBody) -> RtlResult<JitValue> {
+        if has_return(block.stmts()) {
+            return Err(JitError::Unsupported("`return` inside a block expression".into()).into());
+        }
+        self.exec_scoped(block, Vec::new())
+            .map(|value| value.unwrap_or(JitValue::Unit))
+    }
+
+    /// Runs a body in a scope of its own, with `bound` declared in it first.
+    /// Locals the body declares or binds are dropped after it, but its
+    /// assignments to the enclosing locals are kept.
+    fn exec_scoped(
+        &mut self,
+        body: &RtlBody,
+        bound: Vec<(&Ident, JitValue)>,
+    ) -> RtlResult<Option<JitValue>> {
+        let frame = self.frames.last().cloned().unwrap_or_default();
+        self.frames.push(frame);
+        let mut declared: HashSet<String> =
+            bound.iter().map(|(name, _)| name.to_string()).collect();
+        for (name, value) in bound {
+            self.declare(name, value, JitMeta::new(false));
+        }
+        let result = self.exec(body);
+        let inner = self.frames.pop().unwrap_or_default();
+
+        declared.extend(body.stmts().iter().filter_map(|stmt| match stmt {
+            RtlStmt::Var(var) => Some(var.name().to_string()),
+            RtlStmt::Const(c) => Some(c.name().to_string()),
+            _ => None,
+        }));
+        if let Some(outer) = self.frames.last_mut() {
+            for (name, local) in inner {
+                if declared.contains(&name) {
+                    continue;
+                }
+                if let Some(outer_local) = outer.get_mut(&name) {
+                    outer_local.value = local.value;
+                }
+            }
+        }
+        result
+    }
+
+    /// Evaluates call arguments, expanding each spread array into its elements.
+    fn eval_args(&mut self, args: &[RtlExpr]) -> RtlResult<Vec<JitValue>> {
+        let mut values = Vec::new();
+        for arg in args {
+            let RtlExpr::Spread(inner) = arg else {
+                values.push(self.eval(arg)?);
+                continue;
+            };
+            match self.eval(inner)? {
+                JitValue::Array { items, .. } => values.extend(items),
+                other => {
+                    return Err(JitError::TypeMismatch {
+                        expected: "Array".to_string(),
+                        found: other.kind_name().to_string(),
+                    }
+                    .into())
+                }
+            }
+        }
+        Ok(values)
+    }
+
+    /// Evaluates an arm's body if its pattern and guard accept the value.
+    ///
+    /// The arm's bindings live in a copy of the current frame, so they shadow
+    /// locals only until the arm is done.
+    fn eval_arm(&mut self, arm: &RtlMatchArm, value: &JitValue) -> RtlResult<Option<JitValue>> {
+        let Some(bound) = self.match_pat(arm.pat(), value)? else {
+            return Ok(None);
+        };
+        let frame = self.frames.last().cloned().unwrap_or_default();
+        self.frames.push(frame);
+        let result = self.eval_arm_body(arm, bound);
+        self.frames.pop();
+        result
+    }
+
+    fn eval_arm_body(
+        &mut self,
+        arm: &RtlMatchArm,
+        bound: Vec<(&Ident, JitValue)>,
+    ) -> RtlResult<Option<JitValue>> {
+        for (name, value) in bound {
+            self.declare(name, value, JitMeta::new(false));
+        }
+        for (name, expr) in arm.bindings() {
+            let value = self.eval(expr)?;
+            self.declare(name, value, JitMeta::new(false));
+        }
+        if let Some(guard) = arm.guard() {
+            let guard = self.eval(guard)?;
+            let accepted = guard.as_bool().ok_or_else(|| JitError::TypeMismatch {
+                expected: "Bool".to_string(),
+                found: guard.kind_name().to_string(),
+            })?;
+            if !accepted {
+                return Ok(None);
+            }
+        }
+        self.eval(arm.body()).map(Some)
+    }
+
+    /// Returns the names a pattern binds, or `None` if it rejects the value.
+    fn match_pat<'p>(
+        &self,
+        pat: &'p RtlPat,
+        value: &JitValue,
+    ) -> RtlResult<Option<Vec<(&'p Ident, JitValue)>>> {
+        match pat {
+            RtlPat::Wild => Ok(Some(Vec::new())),
+            RtlPat::Lit(lit) => {
+                let accepted = eval::eval_lit(lit, &self.config)? == *value;
+                Ok(accepted.then(Vec::new))
+            }
+            // A name an enum declares as a variant matches that variant
+            RtlPat::Binding(name) if self.rattle.find_variant(&name.to_string()).is_some() => {
+                self.match_variant(name, &[], value)
+            }
+            RtlPat::Binding(name) => Ok(Some(vec![(name, value.clone())])),
+            RtlPat::Struct { name, fields } => match value {
+                JitValue::Struct { name: actual, .. } if name == actual => fields
+                    .iter()
+                    .map(|field| {
+                        let v = value.field(&field.to_string()).ok_or_else(|| {
+                            JitError::UnknownField {
+                                ty: actual.clone(),
+                                field: field.to_string(),
+                            }
+                        })?;
+                        Ok((field, v.clone()))
+                    })
+                    .collect::<RtlResult<Vec<_>>>()
+                    .map(Some),
+                _ => Ok(None),
+            },
+            RtlPat::Variant { name, fields } if name == "some" => match (&fields[..], value) {
+                ([inner], JitValue::Option(Some(v))) => self.match_pat(inner, v),
+                ([_], JitValue::Option(None)) => Ok(None),
+                _ => Err(JitError::TypeMismatch {
+                    expected: "Option".to_string(),
+                    found: value.kind_name().to_string(),
+                }
+                .into()),
+            },
+            RtlPat::Variant { name, fields } => self.match_variant(name, fields, value),
+        }
+    }
+
+    /// Matches a value against an enum variant and patterns for its payload.
+    fn match_variant<'p>(
+        &self,
+        name: &'p Ident,
+        fields: &'p [RtlPat],
+        value: &JitValue,
+    ) -> RtlResult<Option<Vec<(&'p Ident, JitValue)>>> {
+        let (en, variant) = self
+            .rattle
+            .find_variant(&name.to_string())
+            .ok_or_else(|| JitError::UndefinedVariant(name.to_string()))?;
+        if fields.len() != variant.fields().len() {
+            return Err(JitError::ArityMismatch {
+                name: name.to_string(),
+                expected: variant.fields().len(),
+                found: fields.len(),
+            }
+            .into());
+        }
+        let JitValue::Enum {
+            name: actual,
+            variant: actual_variant,
+            fields: values,
+        } = value
+        else {
+            return Err(JitError::TypeMismatch {
+                expected: en.name().to_string(),
+                found: value.kind_name().to_string(),
+            }
+            .into());
+        };
+        if en.name() != actual || variant.name() != actual_variant {
+            return Ok(None);
+        }
+        let mut bound = Vec::new();
+        for (pat, value) in fields.iter().zip(values) {
+            let Some(more) = self.match_pat(pat, value)? else {
+                return Ok(None);
+            };
+            bound.extend(more);
+        }
+        Ok(Some(bound))
+    }
+
+    fn find_fn(&self, name: &str) -> RtlResult<&'a RtlFn> {
+        self.rattle
+            .find_fn(name)
+            .ok_or_else(|| JitError::UndefinedFunction(name.to_string()).into())
+    }
+
+    /// Runs a function's body in a new frame holding its arguments.
+    fn invoke(&mut self, func: &'a RtlFn, args: Vec<JitValue>) -> RtlResult<JitValue> {
+        let body = func.body().ok_or_else(|| {
+            JitError::Unsupported(format!("calling bodiless function '{}'", func.name()))
+        })?;
+        if self.depth >= self.max_call_depth {
+            return Err(JitError::CallDepthLimit(self.max_call_depth).into());
+        }
+        let frame = func
+            .args()
+            .iter()
+            .zip(args)
+            .map(|(arg, value)| {
+                let local = Local {
+                    value,
+                    meta: JitMeta::new(false),
+                };
+                (arg.name().to_string(), local)
+            })
+            .collect();
+
+        self.frames.push(frame);
+        self.depth += 1;
+        let result = self.exec(body);
+        self.depth -= 1;
+        self.frames.pop();
+        // A loop in the caller can't be broken out of from here
+        let result = result.map_err(|err| match err.downcast::<Break>() {
+            Ok(brk) => brk.outside_loop().into(),
+            Err(err) => err,
+        });
+        match result? {
+            // A `Unit` function may end without a `return`, or with a bare one
+            Some(JitValue::Unit) | None if func.ret().is_unit() => Ok(JitValue::Unit),
+            Some(JitValue::Unit) | None => Err(JitError::Unsupported(format!(
+                "function '{}' returning no value",
+                func.name()
+            ))
+            .into()),
+            Some(value) => Ok(value),
+        }
+    }
+
+    /// Runs a body's statements in order, stopping at the first `return`, or
+    /// else evaluates its trailing value. Then runs its deferred expressions,
+    /// the last deferred first, even when a statement failed.
+    fn exec(&mut self, body: &RtlBody) -> RtlResult<Option<JitValue>> {
+        let mut deferred = Vec::new();
+        let result = match self.exec_stmts(body.stmts(), &mut deferred) {
+            Ok(None) => body.value().map(|value| self.eval(value)).transpose(),
+            result => result,
+        };
+        for expr in deferred.into_iter().rev() {
+            let ran = self.eval(expr);
+            // The body's own error is the one worth reporting
+            if result.is_ok() {
+                ran?;
+            }
+        }
+        result
+    }
+
+    fn exec_stmts<'s>(
+        &mut self,
+        stmts: &'s [RtlStmt],
+        deferred: &mut Vec<&'s RtlExpr>,
+    ) -> RtlResult<Option<JitValue>> {
+        for stmt in stmts {
+            match stmt {
+                RtlStmt::Var(var) => {
+                    let value = self.eval(var.data())?;
+                    eval::check_type(self.rattle, var.ty(), &value, &self.types())?;
+                    self.declare(var.name(), value, JitMeta::new(var.is_mut()));
+                }
+                RtlStmt::Const(c) => {
+                    let value = self.eval(c.data())?;
+                    eval::check_type(self.rattle, c.ty(), &value, &self.types())?;
+                    self.declare(c.name(), value, JitMeta::constant());
+                }
+                RtlStmt::Assign { target, value } => {
+                    let value = self.eval(value)?;
+                    self.assign(target, value)?;
+                }
+                RtlStmt::Return(Some(expr)) => return self.eval(expr).map(Some),
+                // A `return` in an `if` or `while` has to stop the body
+                // around it too, so it yields a value either way
+                RtlStmt::Return(None) => return Ok(Some(JitValue::Unit)),
+                RtlStmt::Expr(expr) => {
+                    self.eval(expr)?;
+                }
+                RtlStmt::Fn(func) => {
+                    return Err(JitError::Unsupported(format!(
+                        "interpreting nested function '{}'",
+                        func.name()
+                    ))
+                    .into())
+                }
+                RtlStmt::IfLet(if_let) => {
+                    if let Some(value) = self.exec_if_let(if_let)? {
+                        return Ok(Some(value));
+                    }
+                }
+                RtlStmt::If(if_) => {
+                    if let Some(value) = self.exec_if(if_)? {
+                        return Ok(Some(value));
+                    }
+                }
+                RtlStmt::While { cond, body } => {
+                    if let Some(value) = self.exec_while(cond, body)? {
+                        return Ok(Some(value));
+                    }
+                }
+                RtlStmt::Defer(expr) => deferred.push(expr),
+                RtlStmt::Break { label, value } => {
+                    let value = match value {
+                        Some(value) => self.eval(value)?,
+                        None => JitValue::Unit,
+                    };
+                    return Err(Box::new(Break {
+                        label: label.as_ref().map(Ident::to_string),
+                        value,
+                    }));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Runs an `if let`'s block with the names its pattern binds, or else its
+    /// `else` branch. Yields the value of a `return` in either.
+    fn exec_if_let(&mut self, if_let: &RtlIfLet) -> RtlResult<Option<JitValue>> {
+        let value = self.eval(if_let.scrutinee())?;
+        match self.match_pat(if_let.pat(), &value)? {
+            Some(bound) => self.exec_scoped(if_let.then(), bound),
+            None => self.exec_else(if_let.else_branch()),
+        }
+    }
+
+    /// Runs an `if`'s block when its condition holds, or else its `else`
+    /// branch. Yields the value of a `return` in either.
+    fn exec_if(&mut self, if_: &RtlIf) -> RtlResult<Option<JitValue>> {
+        let cond = self.eval(if_.cond())?;
+        if eval::condition(&cond)? {
+            self.exec_scoped(if_.then(), Vec::new())
+        } else {
+            self.exec_else(if_.else_branch())
+        }
+    }
+
+    fn exec_else(&mut self, branch: Option<&RtlElse>) -> RtlResult<Option<JitValue>> {
+        match branch {
+            Some(RtlElse::IfLet(next)) => self.exec_if_let(next),
+            Some(RtlElse::If(next)) => self.exec_if(next),
+            Some(RtlElse::Block(block)) => self.exec_scoped(block, Vec::new()),
+            None => Ok(None),
+        }
+    }
+
+    /// Runs a `while`'s body, each run a scope of its own, until its
+    /// condition fails or a `break` for it. Yields the value of a `return`
+    /// in the body, which ends the call.
+    fn exec_while(&mut self, cond: &RtlExpr, body: &RtlBody) -> RtlResult<Option<JitValue>> {
+        for _ in 0..self.max_loop_iterations {
+            let holds = self.eval(cond)?;
+            if !eval::condition(&holds)? {
+                return Ok(None);
+            }
+            let err = match self.exec_scoped(body, Vec::new()) {
+                Ok(None) => continue,
+                Ok(returned) => return Ok(returned),
+                Err(err) => err,
+            };
+            match err.downcast::<Break>() {
+                // A `while` has no label, and evaluates to nothing
+                Ok(brk) if brk.label.is_none() && brk.value == JitValue::Unit => return Ok(None),
+                Ok(brk) if brk.label.is_none() => {
+                    return Err(JitError::Unsupported(
+                        "`break` with a value out of a `while`".into(),
+                    )
+                    .into())
+                }
+                Ok(brk) => return Err(brk),
+                Err(err) => return Err(err),
+            }
+        }
+        Err(JitError::LoopLimit(self.max_loop_iterations).into())
+    }
+
+    fn types(&self) -> DefaultTypeResolver {
+        DefaultTypeResolver {
+            config: self.config,
+        }
+    }
+
+    fn local(&self, name: &Ident) -> Option<&Local> {
+        self.frames.last()?.get(&name.to_string())
+    }
+
+    fn declare(&mut self, name: &Ident, value: JitValue, meta: JitMeta) {
+        if let Some(frame) = self.frames.last_mut() {
+            frame.insert(name.to_string(), Local { value, meta });
+        }
+    }
+
+    /// Stores a value into a variable, or an element or field of one.
+    fn assign(&mut self, target: &RtlExpr, value: JitValue) -> RtlResult<()> {
+        let (name, place) = match target {
+            RtlExpr::Ident(name) => (name, Place::Whole),
+            RtlExpr::Index { base, index } => match &**base {
+                RtlExpr::Ident(name) => (name, Place::Index(self.eval(index)?)),
+                _ => return Err(JitError::Unsupported("nested assignment target".into()).into()),
+            },
+            RtlExpr::Field { base, field } => match &**base {
+                RtlExpr::Ident(name) => (name, Place::Field(field)),
+                _ => return Err(JitError::Unsupported("nested assignment target".into()).into()),
+            },
+            _ => return Err(JitError::Unsupported("assignment target".into()).into()),
+        };
+
+        let Some(local) = self
+            .frames
+            .last_mut()
+            .and_then(|frame| frame.get_mut(&name.to_string()))
+        else {
+            // A top-level constant isn't a local, but is just as unassignable
+            if eval::find_const(self.rattle, name).is_some() {
+                return Err(JitError::ConstAssign(name.to_string()).into());
+            }
+            return Err(JitError::undefined_variable_at(name).into());
+        };
+        local.meta.check_assign(&name.to_string())?;
+        match place {
+            Place::Whole if local.value.kind() != value.kind() => Err(JitError::TypeMismatch {
+                expected: local.value.kind_name().to_string(),
+                found: value.kind_name().to_string(),
+            }
+            .into()),
+            Place::Whole => {
+                local.value = value;
+                Ok(())
+            }
+            Place::Index(index) => local.value.set_index(eval::to_index(&index)?, value),
+            Place::Field(field) => local.value.set_field(&field.to_string(), value),
+        }
+    }
+}
+
+/// Whether statements hold a `return`, directly or in the body of an `if`,
+/// `if let` or `while` among them. A `return` in a block or loop expression
+/// is that expression's to reject.
+fn has_return(stmts: &[RtlStmt]) -> bool {
+    stmts.iter().any(|stmt| match stmt {
+        RtlStmt::Return(_) => true,
+        RtlStmt::IfLet(if_let) => {
+            has_return(if_let.then().stmts()) || else_has_return(if_let.else_branch())
+        }
+        RtlStmt::If(if_) => has_return(if_.then().stmts()) || else_has_return(if_.else_branch()),
+        RtlStmt::While { body, .. } => has_return(body.stmts()),
+        _ => false,
+    })
+}
+
+fn else_has_return(branch: Option<&RtlElse>) -> bool {
+    match branch {
+        Some(RtlElse::IfLet(next)) => {
+            has_return(next.then().stmts()) || else_has_return(next.else_branch())
+        }
+        Some(RtlElse::If(next)) => {
+            has_return(next.then().stmts()) || else_has_return(next.else_branch())
+        }
+        Some(RtlElse::Block(block)) => has_return(block.stmts()),
+        None => false,
+    }
+}
+
+/// The part of a variable an assignment stores into.
+enum Place<'e> {
+    Whole,
+    Index(JitValue),
+    Field(&'e Ident),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::JitKind;
+    use rtl_parser::parse;
+    use std::cell::Cell;
+
+    #[test]
+    fn test_short_circuit() {
+        let rattle = parse(
+            "f And() Bool { return false && SideEffect(); }
+             f Or() Bool { return true || SideEffect(); }
+             f Both() Bool { return true && SideEffect(); }
+             f Pick(Bool b) Bool { return b ? true : SideEffect(); }",
+        )
+        .unwrap();
+        let calls = Cell::new(0);
+        let mut interp = Interpreter::new(&rattle);
+        interp.register_native("SideEffect", |_| {
+            calls.set(calls.get() + 1);
+            Ok(JitValue::Bool(true))
+        });
+
+        assert_eq!(interp.call("And", vec![]).unwrap(), JitValue::Bool(false));
+        assert_eq!(interp.call("Or", vec![]).unwrap(), JitValue::Bool(true));
+        assert_eq!(calls.get(), 0);
+
+        assert_eq!(interp.call("Both", vec![]).unwrap(), JitValue::Bool(true));
+        assert_eq!(calls.get(), 1);
+
+        let pick = |interp: &mut Interpreter, b| interp.call("Pick", vec![JitValue::Bool(b)]);
+        assert_eq!(pick(&mut interp, true).unwrap(), JitValue::Bool(true));
+        assert_eq!(calls.get(), 1);
+        assert_eq!(pick(&mut interp, false).unwrap(), JitValue::Bool(true));
+        assert_eq!(calls.get(), 2);
+    }
+
+    #[test]
+    fn test_interpret_fn() {
+        let rattle = parse(
+            "struct Point { Int x, Int y }
+             f Add(Int x, Int y) Int { return x + y; }
+             f Sign(Int x) Int { return match x { 0 => 0, n where n > 0 => 1, _ => 0 - 1 }; }
+             f Quadrant(Point p) Int {
+                 return match p {
+                     Point { x, y } where let s = x * y, s > 0 && x > 0 => 1,
+                     Point { x, y } where x < 0 && y > 0 => 2,
+                     _ => 0,
+                 };
+             }
+             f Only(Int x) Int { return match x { 1 => 1 }; }",
+        )
+        .unwrap();
+        let run = |name, args: &[JitValue]| interpret_fn(&rattle, name, args).unwrap();
+        let point = |x, y| JitValue::Struct {
+            name: "Point".to_string(),
+            fields: vec![
+                ("x".to_string(), JitValue::Int(x)),
+                ("y".to_string(), JitValue::Int(y)),
+            ],
+        };
+
+        assert_eq!(
+            run("Add", &[JitValue::Int(3), JitValue::Int(4)]),
+            JitValue::Int(7)
+        );
+        assert_eq!(run("Sign", &[JitValue::Int(0)]), JitValue::Int(0));
+        assert_eq!(run("Sign", &[JitValue::Int(5)]), JitValue::Int(1));
+        assert_eq!(run("Sign", &[JitValue::Int(-5)]), JitValue::Int(-1));
+        assert_eq!(run("Quadrant", &[point(2, 3)]), JitValue::Int(1));
+        assert_eq!(run("Quadrant", &[point(-2, 3)]), JitValue::Int(2));
+        assert_eq!(run("Quadrant", &[point(-2, -3)]), JitValue::Int(0));
+
+        let err = interpret_fn(&rattle, "Only", &[JitValue::Int(2)]).unwrap_err();
+        assert_eq!(err.to_string(), "No match arm matches 2");
+    }
+
+    #[test]
+    fn test_enum_match() {
+        let rattle = parse(
+            "enum Shape { Circle(Float), Rect(Float, Float), Empty }
+             f Area(Shape s) Float {
+                 return match s { Circle(r) => 3.0 * r * r, Rect(w, h) => w * h, Empty => 0.0 };
+             }
+             f Unit() Float { return Area(Circle(2.0)) + Area(Empty); }
+             f Small(Shape s) Bool { return match s { Rect(1.0, h) => h < 1.0, _ => false }; }
+             f Inner(Int? x) Int { return match x { some(v) => v, _ => 0 }; }
+             const Shape Square = Rect(2.0, 2.0);",
+        )
+        .unwrap();
+        let run = |name, args: &[JitValue]| interpret_fn(&rattle, name, args).unwrap();
+        let circle = JitValue::Enum {
+            name: "Shape".to_string(),
+            variant: "Circle".to_string(),
+            fields: vec![JitValue::Float(2.0)],
+        };
+        assert_eq!(circle.to_string(), "Circle(2)");
+
+        assert_eq!(run("Area", &[circle]), JitValue::Float(12.0));
+        assert_eq!(run("Unit", &[]), JitValue::Float(12.0));
+        let square = eval::eval_const(
+            &rattle,
+            eval::find_const(
+                &rattle,
+                &Ident::new("Square", rtl_parser::Span::call_site()),
+            )
+            .unwrap()
+            .data(),
+            &LoweringConfig::default(),
+        )
+        .unwrap();
+        assert_eq!(square.to_string(), "Rect(2, 2)");
+        assert_eq!(
+            run("Area", std::slice::from_ref(&square)),
+            JitValue::Float(4.0)
+        );
+        assert_eq!(run("Small", &[square]), JitValue::Bool(false));
+        let thin = JitValue::Enum {
+            name: "Shape".to_string(),
+            variant: "Rect".to_string(),
+            fields: vec![JitValue::Float(1.0), JitValue::Float(0.5)],
+        };
+        assert_eq!(run("Small", &[thin]), JitValue::Bool(true));
+        let some = JitValue::Option(Some(Box::new(JitValue::Int(4))));
+        assert_eq!(run("Inner", &[some]), JitValue::Int(4));
+        assert_eq!(run("Inner", &[JitValue::Option(None)]), JitValue::Int(0));
+
+        let err = interpret_fn(&rattle, "Area", &[JitValue::Int(1)]).unwrap_err();
+        assert_eq!(err.to_string(), "Type mismatch: expected Shape, found Int");
+    }
+
+    #[test]
+    fn test_calls_and_locals() {
+        let rattle = parse(
+            "f Add(Int x, Int y = 10) Int { var Int mut z = x + y; z = z * 2; return z; }
+             f Main() Int { var [Int] mut xs = [1, 2]; xs[1] = Add(1); return xs[1]; }
+             f Frozen(Int x) Int { x = 1; return x; }
+             const Int Limit = 3;
+             f Raise() Int { Limit = 4; return Limit; }
+             f Local() Int { const Int y = 1; y += 1; return y; }",
+        )
+        .unwrap();
+        let mut interp = Interpreter::new(&rattle);
+
+        assert_eq!(interp.call("Main", vec![]).unwrap(), JitValue::Int(22));
+        let err = interp.call("Frozen", vec![JitValue::Int(0)]).unwrap_err();
+        assert_eq!(err.to_string(), "Cannot assign to immutable variable 'x'");
+        let err = interp.call("Raise", vec![]).unwrap_err();
+        assert_eq!(err.to_string(), "Cannot assign to constant 'Limit'");
+        let err = interp.call("Local", vec![]).unwrap_err();
+        assert_eq!(err.to_string(), "Cannot assign to constant 'y'");
+        let err = interp.call("Add", vec![]).unwrap_err();
+        assert!(err.to_string().contains("takes 2 arguments"), "{}", err);
+    }
+
+    #[test]
+    fn test_call_fn() {
+        let rattle = parse(
+            "f Fact(Int n) Int { return n <= 1 ? 1 : n * Fact(n - 1); }
+             f Twice(Int n) Int { return n * 2; }",
+        )
+        .unwrap();
+        let fact = rattle.find_fn("Fact").unwrap();
+        let mut interp = Interpreter::new(&rattle);
+        assert_eq!(
+            interp.call_fn(fact, vec![JitValue::Int(10)]).unwrap(),
+            JitValue::Int(3628800)
+        );
+        assert_eq!(
+            interp.call("Fact", vec![JitValue::Int(0)]).unwrap(),
+            JitValue::Int(1)
+        );
+
+        // A function from another program still calls into this one
+        let other = parse("f Quad(Int n) Int { return Twice(Twice(n)); }").unwrap();
+        let quad = other.find_fn("Quad").unwrap();
+        assert_eq!(
+            Interpreter::new(&rattle)
+                .call_fn(quad, vec![JitValue::Int(3)])
+                .unwrap(),
+            JitValue::Int(12)
+        );
+        let err = interp.call_fn(fact, vec![]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Function 'Fact' takes 1 arguments but 0 were given"
+        );
+    }
+
+    #[test]
+    fn test_unit_fn() {
+        let rattle = parse(
+            "f Touch(Int x) { var Int y = x; }
+             f Main() -> Int { Touch(1); return 2; }",
+        )
+        .unwrap();
+        assert_eq!(
+            interpret_fn(&rattle, "Touch", &[JitValue::Int(1)]).unwrap(),
+            JitValue::Unit
+        );
+        assert_eq!(
+            interpret_fn(&rattle, "Main", &[]).unwrap(),
+            JitValue::Int(2)
+        );
+    }
+
+    #[test]
+    fn test_loop() {
+        let rattle = parse(
+            "f Answer() Int { return loop { break 42; }; }
+             f Count() Int {
+                 var Int mut i = 0;
+                 var Int r = loop {
+                     i = i + 1;
+                     i == 5 ? { break i * 10; } : {};
+                 };
+                 return r + i;
+             }
+             f Nested() Int { return 'outer: loop { loop { break 'outer 7; } }; }
+             f Inner() Int { break; }
+             f Outer() Int { return loop { Inner(); }; }
+             f Lost() Int { return loop { break 'nowhere 1; }; }
+             f Spin() Int { return loop {}; }",
+        )
+        .unwrap();
+        let mut interp = Interpreter::new(&rattle);
+
+        assert_eq!(interp.call("Answer", vec![]).unwrap(), JitValue::Int(42));
+        assert_eq!(interp.call("Count", vec![]).unwrap(), JitValue::Int(55));
+        assert_eq!(interp.call("Nested", vec![]).unwrap(), JitValue::Int(7));
+        // A loop can't be broken out of from a function it calls
+        let err = interp.call("Outer", vec![]).unwrap_err();
+        assert_eq!(err.to_string(), "`break` outside a loop");
+        let err = interp.call("Lost", vec![]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "No loop labelled 'nowhere' to break out of"
+        );
+
+        interp.set_max_loop_iterations(100);
+        let err = interp.call("Spin", vec![]).unwrap_err();
+        assert_eq!(err.to_string(), "Loop ran 100 iterations without a `break`");
+    }
+
+    #[test]
+    fn test_if() {
+        let rattle = parse(
+            "f Sign(Int x) Int {
+                 if x > 0 { return 1; } else if x == 0 { return 0; } else { return -1; }
+             }
+             f Clamp(Int x) Int {
+                 var Int mut y = x;
+                 if y > 10 { var Int over = y - 10; y = 10; }
+                 return y;
+             }
+             f Log(Bool b) { if b { return; } Fail(); }
+             f Pick(Int? v) Int {
+                 if let some(x) = v { return x; } else if let none = v { return -1; }
+                 return 0;
+             }
+             f Shadow(Int? v) Int {
+                 var Int x = 1;
+                 var Int mut y = 0;
+                 if let some(x) = v { y = x; }
+                 return x + y;
+             }
+             f NotBool(Int x) Int { if x { return 1; } return 0; }
+             f InLoop(Bool b) Int { return loop { if b { return 1; } break 2; }; }",
+        )
+        .unwrap();
+        let run = |name, args: &[JitValue]| interpret_fn(&rattle, name, args);
+
+        assert_eq!(run("Sign", &[JitValue::Int(7)]).unwrap(), JitValue::Int(1));
+        assert_eq!(run("Sign", &[JitValue::Int(0)]).unwrap(), JitValue::Int(0));
+        assert_eq!(
+            run("Sign", &[JitValue::Int(-7)]).unwrap(),
+            JitValue::Int(-1)
+        );
+        // The assignment to `y` outlives the block, but not its own local
+        assert_eq!(
+            run("Clamp", &[JitValue::Int(12)]).unwrap(),
+            JitValue::Int(10)
+        );
+        assert_eq!(run("Clamp", &[JitValue::Int(3)]).unwrap(), JitValue::Int(3));
+        // A bare `return` in the block ends the call, never reaching `Fail`
+        assert_eq!(run("Log", &[JitValue::Bool(true)]).unwrap(), JitValue::Unit);
+
+        let some = JitValue::Option(Some(Box::new(JitValue::Int(4))));
+        assert_eq!(
+            run("Pick", std::slice::from_ref(&some)).unwrap(),
+            JitValue::Int(4)
+        );
+        assert_eq!(
+            run("Pick", &[JitValue::Option(None)]).unwrap(),
+            JitValue::Int(-1)
+        );
+        // The pattern's `x` shadows the local only inside the block
+        assert_eq!(run("Shadow", &[some]).unwrap(), JitValue::Int(5));
+
+        let err = run("NotBool", &[JitValue::Int(1)]).unwrap_err();
+        assert_eq!(err.to_string(), "Type mismatch: expected Bool, found Int");
+        let err = run("InLoop", &[JitValue::Bool(true)]).unwrap_err();
+        assert_eq!(err.to_string(), "Unsupported: `return` inside a loop");
+    }
+
+    #[test]
+    fn test_while() {
+        let rattle = parse(
+            "f Sum(Int n) Int {
+                 var Int mut i = 0;
+                 var Int mut total = 0;
+                 while i < n { i += 1; total += i; }
+                 return total;
+             }
+             f Find([Int] xs, Int n, Int x) Int {
+                 var Int mut i = 0;
+                 while i < n { if xs[i] == x { return i; } i += 1; }
+                 return -1;
+             }
+             f FirstOver(Int n) Int {
+                 var Int mut i = 0;
+                 while true { if i * i > n { break; } i += 1; }
+                 return i;
+             }
+             f Outer() Int { return 'o: loop { while true { break 'o 3; } }; }
+             f Valued() Int { while true { break 1; } return 0; }
+             f Spin() Int { while true { } return 0; }",
+        )
+        .unwrap();
+        let mut interp = Interpreter::new(&rattle);
+
+        assert_eq!(
+            interp.call("Sum", vec![JitValue::Int(10)]).unwrap(),
+            JitValue::Int(55)
+        );
+        assert_eq!(
+            interp.call("Sum", vec![JitValue::Int(0)]).unwrap(),
+            JitValue::Int(0)
+        );
+        let xs = eval::array_value(vec![JitValue::Int(5), JitValue::Int(8)]).unwrap();
+        assert_eq!(
+            interp
+                .call("Find", vec![xs.clone(), JitValue::Int(2), JitValue::Int(8)])
+                .unwrap(),
+            JitValue::Int(1)
+        );
+        assert_eq!(
+            interp
+                .call("Find", vec![xs, JitValue::Int(2), JitValue::Int(9)])
+                .unwrap(),
+            JitValue::Int(-1)
+        );
+        assert_eq!(
+            interp.call("FirstOver", vec![JitValue::Int(10)]).unwrap(),
+            JitValue::Int(4)
+        );
+        // A labelled `break` goes through the `while` to its loop
+        assert_eq!(interp.call("Outer", vec![]).unwrap(), JitValue::Int(3));
+        let err = interp.call("Valued", vec![]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Unsupported: `break` with a value out of a `while`"
+        );
+
+        interp.set_max_loop_iterations(100);
+        let err = interp.call("Spin", vec![]).unwrap_err();
+        assert_eq!(err.to_string(), "Loop ran 100 iterations without a `break`");
+    }
+
+    #[test]
+    fn test_call_depth_limit() {
+        let rattle = parse(
+            "f R() Int { return R(); }
+             f Down(Int n) Int { return n == 0 ? 0 : Down(n - 1); }",
+        )
+        .unwrap();
+        let mut interp = Interpreter::new(&rattle);
+
+        let err = interp.call("R", vec![]).unwrap_err();
+        assert_eq!(err.to_string(), "Calls went 64 deep without returning");
+        assert_eq!(
+            interp.call("Down", vec![JitValue::Int(63)]).unwrap(),
+            JitValue::Int(0)
+        );
+
+        interp.set_max_call_depth(10);
+        let err = interp.call("Down", vec![JitValue::Int(10)]).unwrap_err();
+        assert_eq!(err.to_string(), "Calls went 10 deep without returning");
+
+        // The `match` arm and block each call goes through don't count as
+        // calls. They take more stack than a test thread has in a debug
+        // build, so this runs on one with the main thread's 8 MiB.
+        let sum = std::thread::Builder::new()
+            .stack_size(8 << 20)
+            .spawn(|| {
+                let src =
+                    "f Sum(Int n) Int { return match n { 0 => 0, _ => n + { Sum(n - 1) } }; }";
+                let rattle = parse(src).unwrap();
+                let sum = |n| interpret_fn(&rattle, "Sum", &[JitValue::Int(n)]);
+                (sum(63).unwrap(), sum(64).unwrap_err().to_string())
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+        assert_eq!(sum.0, JitValue::Int(2016));
+        assert_eq!(sum.1, "Calls went 64 deep without returning");
+    }
+
+    #[test]
+    fn test_block() {
+        let rattle = parse(
+            "f Main() Int {
+                 var Int mut x = 1;
+                 var Int y = { var Int x = 10; x + 1 };
+                 { x = x + y; };
+                 return x;
+             }
+             f Returns() Int { var Int y = { return 1; }; return y; }",
+        )
+        .unwrap();
+
+        assert_eq!(
+            interpret_fn(&rattle, "Main", &[]).unwrap(),
+            JitValue::Int(12)
+        );
+        let err = interpret_fn(&rattle, "Returns", &[]).unwrap_err();
+        assert!(
+            err.to_string().contains("`return` inside a block"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_defer() {
+        let rattle = parse(
+            "f Main() Int {
+                 defer Log(1);
+                 defer Log(2);
+                 var Int x = Log(0);
+                 var Int y = { defer Log(3); Log(4) };
+                 return x + y;
+             }",
+        )
+        .unwrap();
+        let log = std::cell::RefCell::new(Vec::new());
+        let mut interp = Interpreter::new(&rattle);
+        interp.register_native("Log", |args| {
+            log.borrow_mut().push(args[0].clone());
+            Ok(args[0].clone())
+        });
+
+        assert_eq!(interp.call("Main", vec![]).unwrap(), JitValue::Int(4));
+        let order: Vec<_> = log.borrow().iter().map(JitValue::to_string).collect();
+        assert_eq!(order, ["0", "4", "3", "2", "1"]);
+    }
+
+    #[test]
+    fn test_spread() {
+        let rattle = parse(
+            "f Sum3(Int a, Int b, Int c = 100) Int { return a + b + c; }
+             f Pair() Int { var [Int] xs = [1, 2]; return Sum3(xs...); }
+             f Triple() Int { var [Int] xs = [1, 2]; return Sum3(xs..., 3); }
+             f TooMany() Int { return Sum3([1, 2, 3, 4]...); }
+             f NotArray() Int { return Sum3(1...); }",
+        )
+        .unwrap();
+
+        assert_eq!(
+            interpret_fn(&rattle, "Pair", &[]).unwrap(),
+            JitValue::Int(103)
+        );
+        assert_eq!(
+            interpret_fn(&rattle, "Triple", &[]).unwrap(),
+            JitValue::Int(6)
+        );
+        let err = interpret_fn(&rattle, "TooMany", &[]).unwrap_err();
+        assert!(err.to_string().contains("takes 3 arguments"), "{}", err);
+        let err = interpret_fn(&rattle, "NotArray", &[]).unwrap_err();
+        assert_eq!(err.to_string(), "Type mismatch: expected Array, found Int");
+    }
+
+    #[test]
+    fn test_iter() {
+        let rattle = parse(
+            "f Xs() -> [Int] { return [1, 2, 3]; }
+             f Word() -> String { return \"hi\"; }
+             f Total() Int { var [Int] xs = [1, 2, 3]; return Sum(xs); }",
+        )
+        .unwrap();
+
+        let xs = interpret_fn(&rattle, "Xs", &[]).unwrap();
+        let sum = xs
+            .iter()
+            .unwrap()
+            .fold(JitValue::Int(0), |sum, x| sum + x.into_owned());
+        assert_eq!(sum, JitValue::Int(6));
+        // Rattle has no `for` loop yet, so interpreted code iterates through
+        // a native built on `iter`
+        let mut interp = Interpreter::new(&rattle);
+        interp.register_native("Sum", |args| {
+            Ok(args[0]
+                .iter()?
+                .fold(JitValue::Int(0), |sum, x| sum + x.into_owned()))
+        });
+        assert_eq!(interp.call("Total", vec![]).unwrap(), JitValue::Int(6));
+
+        let word = interpret_fn(&rattle, "Word", &[]).unwrap();
+        let chars: Vec<_> = word.iter().unwrap().map(|c| c.into_owned()).collect();
+        assert_eq!(chars, [JitValue::Char('h'), JitValue::Char('i')]);
+        let err = JitValue::Int(1).iter().err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Type mismatch: expected Array or String, found Int"
+        );
+    }
+
+    #[test]
+    fn test_slice() {
+        let rattle = parse("f Mid([Int] xs, Int n) [Int] { return xs[1..n]; }").unwrap();
+        let xs = JitValue::Array {
+            elem: JitKind::Int,
+            items: (1..=4).map(JitValue::Int).collect(),
+        };
+
+        let mid = interpret_fn(&rattle, "Mid", &[xs.clone(), JitValue::Int(3)]).unwrap();
+        assert_eq!(mid.to_string(), "[2, 3]");
+        let err = interpret_fn(&rattle, "Mid", &[xs, JitValue::Int(9)]).unwrap_err();
+        assert_eq!(err.to_string(), "Slice 1..9 out of bounds for length 4");
+    }
+
+    #[test]
+    fn test_undefined_variable_span() {
+        let rattle = parse("f F() Int {\n    var Int a = 1;\n    return a + x;\n}").unwrap();
+        let err = Interpreter::new(&rattle).call("F", vec![]).unwrap_err();
+        let err = err.downcast_ref::<JitError>().unwrap();
+        let JitError::UndefinedVariable(name, Some(span)) = err else {
+            panic!("expected an undefined variable, got {:?}", err);
+        };
+        assert_eq!(name, "x");
+        assert_eq!((span.line, span.column), (3, 15));
+        assert_eq!(
+            err.to_string(),
+            "Variable 'x' not found at line 3, column 16"
+        );
+
+        let rattle = parse("f G() Int { y = 2; return 0; }").unwrap();
+        let err = Interpreter::new(&rattle).call("G", vec![]).unwrap_err();
+        let err = err.downcast_ref::<JitError>().unwrap();
+        assert!(matches!(err, JitError::UndefinedVariable(name, _) if name == "y"));
+    }
+}
@@ -1,38 +1,363 @@
 #![deny(warnings, nonstandard_style)]
 #![allow(dead_code)]
 
+mod codegen;
+mod eval;
+mod interp;
+
 use inkwell::{
     builder::Builder,
     context::Context,
     execution_engine::{ExecutionEngine, JitFunction},
     module::Module,
     targets::{InitializationConfig, Target},
-    types::BasicTypeEnum,
+    types::{AnyType, BasicMetadataTypeEnum, BasicType, BasicTypeEnum},
     AddressSpace, OptimizationLevel,
 };
+use rtl_parser::{FnvHasher, Ident, RtlExpr, SpanInfo};
 pub(crate) use std::error::Error;
 pub(crate) use std::{
+    borrow::Cow,
+    cell::RefCell,
     cmp::PartialEq,
     collections::HashMap,
-    ops::{Add, Div, Mul, Rem, Sub},
+    fmt,
+    hash::Hasher,
+    ops::{Add, Div, Mul, Range, Rem, Sub},
 };
 
 /// A custom result type for the Jit compiler.
 pub type RtlResult<T> = std::result::Result<T, Box<dyn Error>>;
 
+/// The most arguments [`JitCompiler::call_i64`] can pass to a function.
+pub const MAX_CALL_ARGS: usize = 6;
+
+/// The most elements a repeat array like `[0; 8]` may have, so a huge count
+/// fails instead of taking all the memory there is.
+pub const MAX_REPEAT_LEN: usize = 1 << 20;
+
+/// Errors reported by the Jit compiler.
+#[derive(Debug, Clone, PartialEq)]
+pub enum JitError {
+    /// A variable was looked up without being declared, with where it was
+    /// named when it was named in source.
+    UndefinedVariable(String, Option<SpanInfo>),
+    /// The module failed LLVM verification.
+    InvalidModule(String),
+    /// A construct has no lowering yet.
+    Unsupported(String),
+    /// A function was looked up without being compiled.
+    UndefinedFunction(String),
+    /// A function was called with the wrong number of arguments.
+    ArityMismatch {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+    /// A struct literal names a struct that was never declared.
+    UndefinedStruct(String),
+    /// A pattern names an enum variant that no enum declares.
+    UndefinedVariant(String),
+    /// A struct literal sets a field the struct doesn't declare.
+    UnknownField { ty: String, field: String },
+    /// A struct literal leaves out a declared field.
+    MissingField { ty: String, field: String },
+    /// A value doesn't match the type it was declared with.
+    TypeMismatch { expected: String, found: String },
+    /// A value was cast to a kind it has no conversion to.
+    InvalidCast { from: String, to: String },
+    /// An immutable variable was assigned to.
+    ImmutableVariable(String),
+    /// A constant was assigned to.
+    ConstAssign(String),
+    /// An array or string was indexed past its end.
+    IndexOutOfBounds { index: usize, len: usize },
+    /// A target triple is unknown to LLVM or can't generate code.
+    InvalidTarget(String),
+    /// No arm of a match accepted the value.
+    NoMatchingArm(String),
+    /// An array or string was sliced past its end, or with a start after the end.
+    InvalidSlice {
+        start: usize,
+        end: usize,
+        len: usize,
+    },
+    /// Integer arithmetic overflowed its kind's range.
+    IntegerOverflow,
+    /// A format specification couldn't be parsed.
+    InvalidFormat(String),
+    /// An integer was shifted by a negative amount or by its width or more.
+    InvalidShift { amount: i128, bits: u32 },
+    /// An integer constant doesn't fit the width of its type.
+    LiteralOutOfRange { value: i128, bits: u32 },
+    /// Constants were defined in terms of each other, named in the order
+    /// they depend on each other, ending with the first again.
+    CyclicConstant(Vec<String>),
+    /// Type aliases were defined in terms of each other, named in the order
+    /// they expand, ending with the first again.
+    CyclicTypeAlias(Vec<String>),
+    /// A `static_assert` condition was false, with its message if it has one
+    /// and where the condition is.
+    StaticAssertFailed {
+        message: Option<String>,
+        span: SpanInfo,
+    },
+    /// Integer arithmetic overflowed while evaluating a constant, with where
+    /// the overflowing expression is.
+    ConstantOverflow { name: String, span: SpanInfo },
+    /// A `break` ran outside any loop, or outside every loop with its label.
+    BreakOutsideLoop(Option<String>),
+    /// An interpreted loop ran its maximum number of iterations without a
+    /// `break`.
+    LoopLimit(usize),
+    /// Interpreted calls nested their maximum depth, like a function that
+    /// always calls itself.
+    CallDepthLimit(usize),
+    /// A repeat array had more than [`MAX_REPEAT_LEN`] elements.
+    RepeatTooLong(usize),
+}
+
+impl fmt::Display for JitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JitError::UndefinedVariable(name, None) => write!(f, "Variable '{}' not found", name),
+            // Columns count from 0 in spans, but from 1 for people
+            JitError::StaticAssertFailed { message, span } => {
+                write!(
+                    f,
+                    "Static assertion failed at line {}, column {}",
+                    span.line,
+                    span.column + 1
+                )?;
+                match message {
+                    Some(message) => write!(f, ": {}", message),
+                    None => Ok(()),
+                }
+            }
+            JitError::UndefinedVariable(name, Some(span)) => write!(
+                f,
+                "Variable '{}' not found at line {}, column {}",
+                name,
+                span.line,
+                span.column + 1
+            ),
+            JitError::InvalidModule(msg) => write!(f, "Invalid module: {}", msg),
+            JitError::Unsupported(what) => write!(f, "Unsupported: {}", what),
+            JitError::UndefinedFunction(name) => write!(f, "Function '{}' not found", name),
+            JitError::ArityMismatch {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Function '{}' takes {} arguments but {} were given",
+                name, expected, found
+            ),
+            JitError::UndefinedStruct(name) => write!(f, "Struct '{}' not found", name),
+            JitError::UndefinedVariant(name) => write!(f, "Enum variant '{}' not found", name),
+            JitError::UnknownField { ty, field } => {
+                write!(f, "Struct '{}' has no field '{}'", ty, field)
+            }
+            JitError::MissingField { ty, field } => {
+                write!(f, "Struct '{}' is missing field '{}'", ty, field)
+            }
+            JitError::TypeMismatch { expected, found } => {
+                write!(f, "Type mismatch: expected {}, found {}", expected, found)
+            }
+            JitError::InvalidCast { from, to } => write!(f, "Cannot cast {} to {}", from, to),
+            JitError::ImmutableVariable(name) => {
+                write!(f, "Cannot assign to immutable variable '{}'", name)
+            }
+            JitError::ConstAssign(name) => write!(f, "Cannot assign to constant '{}'", name),
+            JitError::IndexOutOfBounds { index, len } => {
+                write!(f, "Index {} out of bounds for length {}", index, len)
+            }
+            JitError::InvalidTarget(msg) => write!(f, "Invalid target: {}", msg),
+            JitError::NoMatchingArm(value) => write!(f, "No match arm matches {}", value),
+            JitError::InvalidSlice { start, end, len } => {
+                write!(
+                    f,
+                    "Slice {}..{} out of bounds for length {}",
+                    start, end, len
+                )
+            }
+            JitError::IntegerOverflow => write!(f, "integer overflow"),
+            JitError::InvalidFormat(spec) => {
+                write!(f, "Invalid format specification '{}'", spec)
+            }
+            JitError::LiteralOutOfRange { value, bits } => {
+                write!(f, "Literal {} out of range for {}-bit integer", value, bits)
+            }
+            JitError::InvalidShift { amount, bits } => {
+                write!(
+                    f,
+                    "Shift by {} out of range for {}-bit integer",
+                    amount, bits
+                )
+            }
+            JitError::CyclicConstant(cycle) => {
+                write!(f, "Cyclic constant dependency: {}", cycle.join(" -> "))
+            }
+            JitError::ConstantOverflow { name, span } => write!(
+                f,
+                "Constant overflow in '{}' at line {}, column {}",
+                name,
+                span.line,
+                span.column + 1
+            ),
+            JitError::CyclicTypeAlias(cycle) => {
+                write!(f, "Cyclic type alias: {}", cycle.join(" -> "))
+            }
+            JitError::BreakOutsideLoop(None) => write!(f, "`break` outside a loop"),
+            JitError::BreakOutsideLoop(Some(label)) => {
+                write!(f, "No loop labelled '{}' to break out of", label)
+            }
+            JitError::LoopLimit(limit) => {
+                write!(f, "Loop ran {} iterations without a `break`", limit)
+            }
+            JitError::CallDepthLimit(limit) => {
+                write!(f, "Calls went {} deep without returning", limit)
+            }
+            JitError::RepeatTooLong(count) => write!(
+                f,
+                "Repeat array of {} elements is longer than {}",
+                count, MAX_REPEAT_LEN
+            ),
+        }
+    }
+}
+
+impl Error for JitError {}
+
+impl JitError {
+    /// An [`UndefinedVariable`] for a name in source, with where it is.
+    ///
+    /// [`UndefinedVariable`]: JitError::UndefinedVariable
+    pub fn undefined_variable_at(name: &Ident) -> Self {
+        let start = name.span().start();
+        let span = SpanInfo {
+            line: start.line,
+            column: start.column,
+        };
+        JitError::UndefinedVariable(name.to_string(), Some(span))
+    }
+}
+
 /// An enum to represent different types of values in the Jit compiler.
 #[derive(Debug, Clone)]
 pub enum JitValue {
     Int(i128),
     String(String),
     Float(f64), // Add more types as needed
+    Struct {
+        name: String,
+        fields: Vec<(String, JitValue)>,
+    },
+    Array {
+        elem: JitKind,
+        items: Vec<JitValue>,
+    },
+    I32(i32),
+    I64(i64),
+    F32(f32),
+    Bool(bool),
+    Char(char),
+    /// The value of a block ending in `;`.
+    Unit,
+    /// A value of an optional type: `none` or `some(value)`.
+    Option(Option<Box<JitValue>>),
+    /// Raw bytes, which unlike a `String` need not be valid UTF-8.
+    Bytes(Vec<u8>),
+    /// A value of an enum: one of its variants, with the variant's payload.
+    Enum {
+        name: String,
+        variant: String,
+        fields: Vec<JitValue>,
+    },
+}
+
+/// A stable tag for each [`JitValue`] variant.
+///
+/// The discriminants are emitted into bytecode streams and FFI type tags, so
+/// existing values must never change; new kinds get the next free number.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum JitKind {
+    Int = 0,
+    Float = 1,
+    String = 2,
+    Struct = 3,
+    Array = 4,
+    I32 = 5,
+    I64 = 6,
+    F32 = 7,
+    Bool = 8,
+    Char = 9,
+    Unit = 10,
+    Option = 11,
+    Bytes = 12,
+    Enum = 13,
+}
+
+impl JitKind {
+    /// Returns the Rattle-facing name of the kind.
+    pub fn name(self) -> &'static str {
+        match self {
+            JitKind::Int => "Int",
+            JitKind::Float => "Float",
+            JitKind::String => "String",
+            JitKind::Struct => "Struct",
+            JitKind::Array => "Array",
+            JitKind::I32 => "I32",
+            JitKind::I64 => "I64",
+            JitKind::F32 => "F32",
+            JitKind::Bool => "Bool",
+            JitKind::Char => "Char",
+            JitKind::Unit => "Unit",
+            JitKind::Option => "Option",
+            JitKind::Bytes => "Bytes",
+            JitKind::Enum => "Enum",
+        }
+    }
 }
 
 pub fn jit_to_llvm<'ctx>(ctx: &'ctx Context, ty: &JitValue) -> BasicTypeEnum<'ctx> {
     match ty {
         JitValue::Int(_) => ctx.i128_type().into(),
         JitValue::Float(_) => ctx.f64_type().into(),
+        JitValue::I32(_) => ctx.i32_type().into(),
+        JitValue::I64(_) => ctx.i64_type().into(),
+        JitValue::F32(_) => ctx.f32_type().into(),
+        JitValue::Bool(_) => ctx.bool_type().into(),
+        JitValue::Char(_) => ctx.i32_type().into(),
+        JitValue::Unit => ctx.struct_type(&[], false).into(),
+        // A presence flag followed by the payload, a placeholder byte for `none`
+        JitValue::Option(value) => {
+            let payload = value
+                .as_deref()
+                .map_or_else(|| ctx.i8_type().into(), |value| jit_to_llvm(ctx, value));
+            ctx.struct_type(&[ctx.bool_type().into(), payload], false)
+                .into()
+        }
         JitValue::String(_) => ctx.ptr_type(AddressSpace::default()).into(),
+        JitValue::Bytes(v) => ctx.i8_type().array_type(v.len() as u32).into(),
+        JitValue::Struct { fields, .. } => {
+            let field_types: Vec<_> = fields.iter().map(|(_, v)| jit_to_llvm(ctx, v)).collect();
+            ctx.struct_type(&field_types, false).into()
+        }
+        JitValue::Array { items, .. } => {
+            let elem = items
+                .first()
+                .map_or_else(|| ctx.i8_type().into(), |item| jit_to_llvm(ctx, item));
+            elem.array_type(items.len() as u32).into()
+        }
+        // The variant's index followed by its payload
+        JitValue::Enum { fields, .. } => {
+            let field_types: Vec<_> = std::iter::once(ctx.i32_type().into())
+                .chain(fields.iter().map(|v| jit_to_llvm(ctx, v)))
+                .collect();
+            ctx.struct_type(&field_types, false).into()
+        }
     }
 }
 
@@ -55,6 +380,31 @@ impl From<i128> for JitValue {
 }
 
 impl JitValue {
+    /// Returns the stable kind tag of the jit value.
+    pub fn kind(&self) -> JitKind {
+        match self {
+            JitValue::Int(_) => JitKind::Int,
+            JitValue::Float(_) => JitKind::Float,
+            JitValue::String(_) => JitKind::String,
+            JitValue::Struct { .. } => JitKind::Struct,
+            JitValue::Array { .. } => JitKind::Array,
+            JitValue::I32(_) => JitKind::I32,
+            JitValue::I64(_) => JitKind::I64,
+            JitValue::F32(_) => JitKind::F32,
+            JitValue::Bool(_) => JitKind::Bool,
+            JitValue::Char(_) => JitKind::Char,
+            JitValue::Unit => JitKind::Unit,
+            JitValue::Option(_) => JitKind::Option,
+            JitValue::Bytes(_) => JitKind::Bytes,
+            JitValue::Enum { .. } => JitKind::Enum,
+        }
+    }
+
+    /// Returns the name of the jit value's kind, for uniform error reporting.
+    pub fn kind_name(&self) -> &'static str {
+        self.kind().name()
+    }
+
     /// Returns `true` if the jit value is [`Int`].
     ///
     /// [`Int`]: JitValue::Int
@@ -103,6 +453,30 @@ impl JitValue {
         }
     }
 
+    /// Returns `true` if the jit value is [`Bytes`].
+    ///
+    /// [`Bytes`]: JitValue::Bytes
+    #[must_use]
+    pub fn is_bytes(&self) -> bool {
+        matches!(self, Self::Bytes(..))
+    }
+
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        if let Self::Bytes(v) = self {
+            Some(v)
+        } else {
+            None
+        }
+    }
+
+    pub fn try_into_bytes(self) -> Result<Vec<u8>, Self> {
+        if let Self::Bytes(v) = self {
+            Ok(v)
+        } else {
+            Err(self)
+        }
+    }
+
     /// Returns `true` if the jit value is [`Float`].
     ///
     /// [`Float`]: JitValue::Float
@@ -126,6 +500,586 @@ impl JitValue {
             Err(self)
         }
     }
+
+    /// Returns `true` if the jit value is [`Bool`].
+    ///
+    /// [`Bool`]: JitValue::Bool
+    #[must_use]
+    pub fn is_bool(&self) -> bool {
+        matches!(self, Self::Bool(..))
+    }
+
+    pub fn as_bool(&self) -> Option<&bool> {
+        if let Self::Bool(v) = self {
+            Some(v)
+        } else {
+            None
+        }
+    }
+
+    /// Adds two numbers of the same kind, failing on integer overflow.
+    pub fn checked_add(self, rhs: Self) -> RtlResult<JitValue> {
+        self.checked_arith(rhs, "addition", i128::checked_add, |l, r| l + r)
+    }
+
+    /// Subtracts two numbers of the same kind, failing on integer overflow.
+    pub fn checked_sub(self, rhs: Self) -> RtlResult<JitValue> {
+        self.checked_arith(rhs, "subtraction", i128::checked_sub, |l, r| l - r)
+    }
+
+    /// Multiplies two numbers of the same kind, failing on integer overflow.
+    pub fn checked_mul(self, rhs: Self) -> RtlResult<JitValue> {
+        self.checked_arith(rhs, "multiplication", i128::checked_mul, |l, r| l * r)
+    }
+
+    /// Divides two numbers of the same kind, failing on integer overflow,
+    /// like the minimum integer divided by -1, or division by zero.
+    pub fn checked_div(self, rhs: Self) -> RtlResult<JitValue> {
+        self.checked_arith(rhs, "division", i128::checked_div, |l, r| l / r)
+    }
+
+    /// The remainder of two numbers of the same kind. It fails wherever
+    /// [`JitValue::checked_div`] does, so the minimum integer `% -1`
+    /// overflows for every kind, as it does in compiled code.
+    pub fn checked_rem(self, rhs: Self) -> RtlResult<JitValue> {
+        let overflows = match (&self, &rhs) {
+            (JitValue::Int(l), JitValue::Int(-1)) => *l == i128::MIN,
+            (JitValue::I64(l), JitValue::I64(-1)) => *l == i64::MIN,
+            (JitValue::I32(l), JitValue::I32(-1)) => *l == i32::MIN,
+            _ => false,
+        };
+        if overflows {
+            return Err(JitError::IntegerOverflow.into());
+        }
+        self.checked_arith(rhs, "remainder", i128::checked_rem, |l, r| l % r)
+    }
+
+    /// Applies an arithmetic operator to two numbers of the same kind. The
+    /// narrower integers are widened to `i128`, which holds any sum, product
+    /// or quotient of theirs exactly, then range checked; floats can't
+    /// overflow.
+    fn checked_arith(
+        self,
+        rhs: Self,
+        op: &str,
+        int: fn(i128, i128) -> Option<i128>,
+        float: fn(f64, f64) -> f64,
+    ) -> RtlResult<JitValue> {
+        let value = match (self, rhs) {
+            (JitValue::Int(l), JitValue::Int(r)) => int(l, r).map(JitValue::Int),
+            (JitValue::I64(l), JitValue::I64(r)) => int(l.into(), r.into())
+                .and_then(|v| i64::try_from(v).ok())
+                .map(JitValue::I64),
+            (JitValue::I32(l), JitValue::I32(r)) => int(l.into(), r.into())
+                .and_then(|v| i32::try_from(v).ok())
+                .map(JitValue::I32),
+            (JitValue::Float(l), JitValue::Float(r)) => Some(JitValue::Float(float(l, r))),
+            (JitValue::F32(l), JitValue::F32(r)) => {
+                Some(JitValue::F32(float(l.into(), r.into()) as f32))
+            }
+            (l, r) => {
+                return Err(JitError::Unsupported(format!(
+                    "{} of {} and {}",
+                    op,
+                    l.kind_name(),
+                    r.kind_name()
+                ))
+                .into())
+            }
+        };
+        value.ok_or_else(|| JitError::IntegerOverflow.into())
+    }
+
+    /// Returns `true` if the jit value is a number of any width.
+    #[must_use]
+    pub fn is_numeric(&self) -> bool {
+        matches!(
+            self,
+            Self::Int(..) | Self::Float(..) | Self::I32(..) | Self::I64(..) | Self::F32(..)
+        )
+    }
+
+    /// Returns `true` if the jit value is [`Struct`].
+    ///
+    /// [`Struct`]: JitValue::Struct
+    #[must_use]
+    pub fn is_struct(&self) -> bool {
+        matches!(self, Self::Struct { .. })
+    }
+
+    /// Returns the value of a struct field, or `None` for a missing field or non-struct.
+    pub fn field(&self, name: &str) -> Option<&JitValue> {
+        if let Self::Struct { fields, .. } = self {
+            fields.iter().find(|(n, _)| n == name).map(|(_, v)| v)
+        } else {
+            None
+        }
+    }
+
+    /// Replaces a struct field, checking that it exists and keeps its kind.
+    pub fn set_field(&mut self, name: &str, v: JitValue) -> RtlResult<()> {
+        let Self::Struct { name: ty, fields } = self else {
+            return Err(JitError::Unsupported(format!("field of {}", self.kind_name())).into());
+        };
+        let (_, slot) =
+            fields
+                .iter_mut()
+                .find(|(n, _)| n == name)
+                .ok_or_else(|| JitError::UnknownField {
+                    ty: ty.clone(),
+                    field: name.to_string(),
+                })?;
+        if slot.kind() != v.kind() {
+            return Err(JitError::TypeMismatch {
+                expected: slot.kind_name().to_string(),
+                found: v.kind_name().to_string(),
+            }
+            .into());
+        }
+        *slot = v;
+        Ok(())
+    }
+
+    /// Converts the jit value to another kind, as an `as` cast does.
+    ///
+    /// - Integers convert between widths by wrapping to the target's low bits,
+    ///   sign-extending when widening.
+    /// - Integers convert to floats by rounding to the nearest representable value,
+    ///   and floats convert between widths the same way.
+    /// - Floats convert to integers by truncating toward zero, saturating at the
+    ///   target's bounds, with NaN becoming 0.
+    /// - Integers convert to `Char` only when they are a Unicode scalar value,
+    ///   and `Char` converts to its code point.
+    /// - Integers convert to `Bool` as `value != 0`, and `Bool` converts to 0 or 1.
+    ///
+    /// Casting to the value's own kind is a copy; every other cast, like
+    /// `String` to `Int` or `Char` to `Float`, is an error.
+    pub fn cast_to(&self, target: JitKind) -> RtlResult<JitValue> {
+        let is_int = matches!(target, JitKind::Int | JitKind::I32 | JitKind::I64);
+        let value = match self {
+            _ if self.kind() == target => Some(self.clone()),
+            JitValue::Int(v) => cast_int(*v, target),
+            JitValue::I32(v) => cast_int(i128::from(*v), target),
+            JitValue::I64(v) => cast_int(i128::from(*v), target),
+            JitValue::Float(v) => cast_float(*v, target),
+            JitValue::F32(v) => cast_float(f64::from(*v), target),
+            JitValue::Char(c) if is_int => cast_int(u32::from(*c).into(), target),
+            JitValue::Bool(b) if is_int => cast_int(i128::from(*b), target),
+            _ => None,
+        };
+        value.ok_or_else(|| {
+            JitError::InvalidCast {
+                from: self.kind_name().to_string(),
+                to: target.name().to_string(),
+            }
+            .into()
+        })
+    }
+
+    /// Returns `true` if the jit value is [`Array`].
+    ///
+    /// [`Array`]: JitValue::Array
+    #[must_use]
+    pub fn is_array(&self) -> bool {
+        matches!(self, Self::Array { .. })
+    }
+
+    /// Returns an array element, or `None` for an out-of-bounds index or non-array.
+    pub fn index(&self, i: usize) -> Option<&JitValue> {
+        if let Self::Array { items, .. } = self {
+            items.get(i)
+        } else {
+            None
+        }
+    }
+
+    /// Replaces an array element, checking the index and the element's kind.
+    pub fn set_index(&mut self, i: usize, v: JitValue) -> RtlResult<()> {
+        let Self::Array { elem, items } = self else {
+            return Err(JitError::Unsupported(format!("indexing {}", self.kind_name())).into());
+        };
+        if v.kind() != *elem {
+            return Err(JitError::TypeMismatch {
+                expected: elem.name().to_string(),
+                found: v.kind_name().to_string(),
+            }
+            .into());
+        }
+        let len = items.len();
+        let slot = items
+            .get_mut(i)
+            .ok_or(JitError::IndexOutOfBounds { index: i, len })?;
+        *slot = v;
+        Ok(())
+    }
+
+    /// Returns the character of a string at a character index, as a `Char`.
+    ///
+    /// Indices count characters, not bytes: `"héllo"` has length 5 and its
+    /// `l` is at index 2.
+    pub fn char_at(&self, i: usize) -> RtlResult<JitValue> {
+        let Self::String(s) = self else {
+            return Err(JitError::Unsupported(format!("indexing {}", self.kind_name())).into());
+        };
+        s.chars().nth(i).map(JitValue::Char).ok_or_else(|| {
+            JitError::IndexOutOfBounds {
+                index: i,
+                len: s.chars().count(),
+            }
+            .into()
+        })
+    }
+
+    /// Copies the characters of a string from `range.start` up to `range.end`.
+    ///
+    /// Like [`char_at`](JitValue::char_at), the range counts characters, so
+    /// it never splits a multi-byte character.
+    pub fn substring(&self, range: Range<usize>) -> RtlResult<JitValue> {
+        let Self::String(s) = self else {
+            return Err(JitError::Unsupported(format!("slicing {}", self.kind_name())).into());
+        };
+        // The byte offset of every character boundary, including the end
+        let bounds: Vec<usize> = s.char_indices().map(|(i, _)| i).chain([s.len()]).collect();
+        match (bounds.get(range.start), bounds.get(range.end)) {
+            (Some(&start), Some(&end)) if start <= end => {
+                Ok(JitValue::String(s[start..end].into()))
+            }
+            _ => Err(JitError::InvalidSlice {
+                start: range.start,
+                end: range.end,
+                len: bounds.len() - 1,
+            }
+            .into()),
+        }
+    }
+
+    /// Returns a hash of the value's contents that's the same across runs and
+    /// platforms, for keying caches and memo tables on values.
+    ///
+    /// Floats hash by bit pattern, except that `0.0` and `-0.0` hash alike, as
+    /// do all NaNs. The kind is part of the hash, so `Int(1)` and `I32(1)` differ.
+    pub fn content_hash(&self) -> u64 {
+        let mut hasher = FnvHasher::default();
+        self.hash_contents(&mut hasher);
+        hasher.finish()
+    }
+
+    fn hash_contents(&self, hasher: &mut FnvHasher) {
+        hasher.write(&[self.kind() as u8]);
+        match self {
+            JitValue::Int(v) => hasher.write(&v.to_le_bytes()),
+            JitValue::I32(v) => hasher.write(&v.to_le_bytes()),
+            JitValue::I64(v) => hasher.write(&v.to_le_bytes()),
+            JitValue::Float(v) => {
+                let bits = match v {
+                    v if *v == 0.0 => 0,
+                    v if v.is_nan() => f64::NAN.to_bits(),
+                    v => v.to_bits(),
+                };
+                hasher.write(&bits.to_le_bytes())
+            }
+            JitValue::F32(v) => {
+                let bits = match v {
+                    v if *v == 0.0 => 0,
+                    v if v.is_nan() => f32::NAN.to_bits(),
+                    v => v.to_bits(),
+                };
+                hasher.write(&bits.to_le_bytes())
+            }
+            JitValue::Bool(v) => hasher.write(&[*v as u8]),
+            JitValue::Char(v) => hasher.write(&(*v as u32).to_le_bytes()),
+            JitValue::Unit => {}
+            JitValue::Option(value) => match value {
+                Some(value) => {
+                    hasher.write(&[1]);
+                    value.hash_contents(hasher);
+                }
+                None => hasher.write(&[0]),
+            },
+            JitValue::String(v) => hash_str(hasher, v),
+            JitValue::Bytes(v) => {
+                hasher.write_usize(v.len());
+                hasher.write(v);
+            }
+            JitValue::Struct { name, fields } => {
+                hash_str(hasher, name);
+                hasher.write_usize(fields.len());
+                for (field, value) in fields {
+                    hash_str(hasher, field);
+                    value.hash_contents(hasher);
+                }
+            }
+            JitValue::Array { elem, items } => {
+                hasher.write(&[*elem as u8]);
+                hasher.write_usize(items.len());
+                for item in items {
+                    item.hash_contents(hasher);
+                }
+            }
+            JitValue::Enum {
+                name,
+                variant,
+                fields,
+            } => {
+                hash_str(hasher, name);
+                hash_str(hasher, variant);
+                hasher.write_usize(fields.len());
+                for field in fields {
+                    field.hash_contents(hasher);
+                }
+            }
+        }
+    }
+}
+
+/// Hashes a string for [`JitValue::content_hash`], prefixed by its length so
+/// adjacent strings can't run together.
+fn hash_str(hasher: &mut FnvHasher, s: &str) {
+    hasher.write_usize(s.len());
+    hasher.write(s.as_bytes());
+}
+
+/// Converts an integer for [`JitValue::cast_to`].
+fn cast_int(v: i128, target: JitKind) -> Option<JitValue> {
+    match target {
+        JitKind::Int => Some(JitValue::Int(v)),
+        JitKind::I32 => Some(JitValue::I32(v as i32)),
+        JitKind::I64 => Some(JitValue::I64(v as i64)),
+        JitKind::Float => Some(JitValue::Float(v as f64)),
+        JitKind::F32 => Some(JitValue::F32(v as f32)),
+        JitKind::Char => u32::try_from(v)
+            .ok()
+            .and_then(char::from_u32)
+            .map(JitValue::Char),
+        JitKind::Bool => Some(JitValue::Bool(v != 0)),
+        _ => None,
+    }
+}
+
+/// Converts a float for [`JitValue::cast_to`]; Rust's `as` already truncates
+/// and saturates the way Rattle's casts are documented to.
+fn cast_float(v: f64, target: JitKind) -> Option<JitValue> {
+    match target {
+        JitKind::Int => Some(JitValue::Int(v as i128)),
+        JitKind::I32 => Some(JitValue::I32(v as i32)),
+        JitKind::I64 => Some(JitValue::I64(v as i64)),
+        JitKind::Float => Some(JitValue::Float(v)),
+        JitKind::F32 => Some(JitValue::F32(v as f32)),
+        _ => None,
+    }
+}
+
+impl fmt::Display for JitValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JitValue::Int(v) => write!(f, "{}", v),
+            JitValue::String(v) => write!(f, "{}", v),
+            JitValue::Float(v) => write!(f, "{}", v),
+            JitValue::I32(v) => write!(f, "{}", v),
+            JitValue::I64(v) => write!(f, "{}", v),
+            JitValue::F32(v) => write!(f, "{}", v),
+            JitValue::Bool(v) => write!(f, "{}", v),
+            JitValue::Char(v) => write!(f, "{}", v),
+            JitValue::Unit => write!(f, "()"),
+            JitValue::Option(Some(value)) => match &**value {
+                JitValue::String(v) => write!(f, "some({:?})", v),
+                value => write!(f, "some({})", value),
+            },
+            JitValue::Option(None) => write!(f, "none"),
+            // As a byte-string literal, escaping all but printable ASCII
+            JitValue::Bytes(v) => write!(f, "b\"{}\"", v.escape_ascii()),
+            JitValue::Struct { name, fields } => {
+                write!(f, "{} {{", name)?;
+                for (i, (field, value)) in fields.iter().enumerate() {
+                    let sep = if i == 0 { " " } else { ", " };
+                    match value {
+                        JitValue::String(v) => write!(f, "{}{}: {:?}", sep, field, v)?,
+                        _ => write!(f, "{}{}: {}", sep, field, value)?,
+                    }
+                }
+                write!(f, " }}")
+            }
+            JitValue::Array { items, .. } => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    match item {
+                        JitValue::String(v) => write!(f, "{:?}", v)?,
+                        _ => write!(f, "{}", item)?,
+                    }
+                }
+                write!(f, "]")
+            }
+            // Like the expression building it, as `Circle(2.5)` or `Empty`
+            JitValue::Enum {
+                variant, fields, ..
+            } => {
+                write!(f, "{}", variant)?;
+                if fields.is_empty() {
+                    return Ok(());
+                }
+                write!(f, "(")?;
+                for (i, field) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    match field {
+                        JitValue::String(v) => write!(f, "{:?}", v)?,
+                        _ => write!(f, "{}", field)?,
+                    }
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+/// Formats a value with a `format!`-style specification like `"{:.2}"`,
+/// `"{:>8}"` or `"{:+#010x}"`, the way Rust would format the matching value.
+/// Precision and `e`/`E` apply to floats (precision also truncates strings),
+/// and `x`/`X`/`o`/`b` to integers; anything else is formatted as by `Display`.
+pub fn format_value(value: &JitValue, spec: &str) -> RtlResult<String> {
+    let invalid = || JitError::InvalidFormat(spec.to_string());
+    let inner = spec
+        .strip_prefix('{')
+        .and_then(|s| s.strip_suffix('}'))
+        .ok_or_else(invalid)?;
+    let mut rest = match inner.strip_prefix(':') {
+        Some(rest) => rest,
+        None if inner.is_empty() => "",
+        None => return Err(invalid().into()),
+    };
+
+    let mut fill = ' ';
+    let mut align = None;
+    let mut chars = rest.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), Some(a @ ('<' | '^' | '>'))) => {
+            fill = c;
+            align = Some(a);
+            rest = &rest[c.len_utf8() + 1..];
+        }
+        (Some(a @ ('<' | '^' | '>')), _) => {
+            align = Some(a);
+            rest = &rest[1..];
+        }
+        _ => {}
+    }
+    let plus = strip_flag(&mut rest, '+');
+    let alternate = strip_flag(&mut rest, '#');
+    let zero = strip_flag(&mut rest, '0');
+    let width = take_count(&mut rest).unwrap_or(0);
+    let precision = match strip_flag(&mut rest, '.') {
+        true => Some(take_count(&mut rest).ok_or_else(invalid)?),
+        false => None,
+    };
+
+    let mismatch = |expected: &str| JitError::TypeMismatch {
+        expected: expected.to_string(),
+        found: value.kind_name().to_string(),
+    };
+    let mut body = match (rest, value) {
+        ("" | "e" | "E", JitValue::Float(v)) => format_float(*v, rest, precision),
+        ("" | "e" | "E", JitValue::F32(v)) => format_float(*v, rest, precision),
+        ("e" | "E", _) => return Err(mismatch("Float").into()),
+        ("x" | "X" | "o" | "b", JitValue::Int(v)) => format_radix(*v, rest, alternate),
+        ("x" | "X" | "o" | "b", JitValue::I32(v)) => format_radix(*v, rest, alternate),
+        ("x" | "X" | "o" | "b", JitValue::I64(v)) => format_radix(*v, rest, alternate),
+        ("x" | "X" | "o" | "b", _) => return Err(mismatch("Int").into()),
+        ("", JitValue::String(v)) => match precision {
+            Some(precision) => v.chars().take(precision).collect(),
+            None => v.clone(),
+        },
+        ("", value) => value.to_string(),
+        _ => return Err(invalid().into()),
+    };
+
+    let numeric = matches!(
+        value,
+        JitValue::Int(_)
+            | JitValue::I32(_)
+            | JitValue::I64(_)
+            | JitValue::Float(_)
+            | JitValue::F32(_)
+    );
+    if numeric && plus && !body.starts_with('-') {
+        body.insert(0, '+');
+    }
+    let len = body.chars().count();
+    if len >= width {
+        return Ok(body);
+    }
+    let pad = width - len;
+    // Zero padding goes after any sign and radix prefix, and ignores alignment
+    if numeric && zero {
+        let sign = if body.starts_with(['+', '-']) { 1 } else { 0 };
+        let prefix = if alternate && !rest.is_empty() {
+            sign + 2
+        } else {
+            sign
+        };
+        body.insert_str(prefix, &"0".repeat(pad));
+        return Ok(body);
+    }
+    let fill = |n: usize| fill.to_string().repeat(n);
+    Ok(match align.unwrap_or(if numeric { '>' } else { '<' }) {
+        '<' => body + &fill(pad),
+        '^' => fill(pad / 2) + &body + &fill(pad - pad / 2),
+        _ => fill(pad) + &body,
+    })
+}
+
+/// Consumes `flag` from the front of a format specification.
+fn strip_flag(rest: &mut &str, flag: char) -> bool {
+    match rest.strip_prefix(flag) {
+        Some(stripped) => {
+            *rest = stripped;
+            true
+        }
+        None => false,
+    }
+}
+
+/// Consumes a width or precision from the front of a format specification.
+fn take_count(rest: &mut &str) -> Option<usize> {
+    let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let count = rest[..digits].parse().ok()?;
+    *rest = &rest[digits..];
+    Some(count)
+}
+
+/// Formats a float for [`format_value`] as `{}`, `{:e}` or `{:E}`.
+fn format_float<T>(v: T, ty: &str, precision: Option<usize>) -> String
+where
+    T: fmt::Display + fmt::LowerExp + fmt::UpperExp,
+{
+    match (ty, precision) {
+        ("e", Some(p)) => format!("{:.*e}", p, v),
+        ("e", None) => format!("{:e}", v),
+        ("E", Some(p)) => format!("{:.*E}", p, v),
+        ("E", None) => format!("{:E}", v),
+        (_, Some(p)) => format!("{:.*}", p, v),
+        (_, None) => v.to_string(),
+    }
+}
+
+/// Formats an integer for [`format_value`] in hex, octal or binary.
+fn format_radix<T>(v: T, ty: &str, alternate: bool) -> String
+where
+    T: fmt::LowerHex + fmt::UpperHex + fmt::Octal + fmt::Binary,
+{
+    match (ty, alternate) {
+        ("x", false) => format!("{:x}", v),
+        ("x", true) => format!("{:#x}", v),
+        ("X", false) => format!("{:X}", v),
+        ("X", true) => format!("{:#X}", v),
+        ("o", false) => format!("{:o}", v),
+        ("o", true) => format!("{:#o}", v),
+        (_, false) => format!("{:b}", v),
+        (_, true) => format!("{:#b}", v),
+    }
 }
 
 impl PartialEq for JitValue {
@@ -134,116 +1088,311 @@ impl PartialEq for JitValue {
             (JitValue::Int(l), JitValue::Int(r)) => l == r,
             (JitValue::Float(l), JitValue::Float(r)) => l == r,
             (JitValue::String(l), JitValue::String(r)) => l == r,
+            (
+                JitValue::Struct {
+                    name: ln,
+                    fields: lf,
+                },
+                JitValue::Struct {
+                    name: rn,
+                    fields: rf,
+                },
+            ) => ln == rn && lf == rf,
+            (JitValue::Array { items: l, .. }, JitValue::Array { items: r, .. }) => l == r,
+            (JitValue::I32(l), JitValue::I32(r)) => l == r,
+            (JitValue::I64(l), JitValue::I64(r)) => l == r,
+            (JitValue::F32(l), JitValue::F32(r)) => l == r,
+            (JitValue::Bool(l), JitValue::Bool(r)) => l == r,
+            (JitValue::Char(l), JitValue::Char(r)) => l == r,
+            (JitValue::Unit, JitValue::Unit) => true,
+            (JitValue::Option(l), JitValue::Option(r)) => l == r,
+            (JitValue::Bytes(l), JitValue::Bytes(r)) => l == r,
+            (
+                JitValue::Enum {
+                    name: ln,
+                    variant: lv,
+                    fields: lf,
+                },
+                JitValue::Enum {
+                    name: rn,
+                    variant: rv,
+                    fields: rf,
+                },
+            ) => ln == rn && lv == rv && lf == rf,
             _ => false,
         }
     }
 }
 
+impl JitValue {
+    /// Iterates the elements of an array, or the characters of a string as
+    /// [`Char`]s, failing for any other kind. Array elements are borrowed,
+    /// while characters are built as they are yielded.
+    ///
+    /// [`Char`]: JitValue::Char
+    pub fn iter(&self) -> RtlResult<impl Iterator<Item = Cow<'_, JitValue>>> {
+        match self {
+            JitValue::Array { items, .. } => Ok(JitIter::Items(items.iter())),
+            JitValue::String(s) => Ok(JitIter::Chars(s.chars())),
+            other => Err(JitError::TypeMismatch {
+                expected: "Array or String".to_string(),
+                found: other.kind_name().to_string(),
+            }
+            .into()),
+        }
+    }
+}
+
+/// The iterator behind [`JitValue::iter`].
+enum JitIter<'a> {
+    Items(std::slice::Iter<'a, JitValue>),
+    Chars(std::str::Chars<'a>),
+}
+
+impl<'a> Iterator for JitIter<'a> {
+    type Item = Cow<'a, JitValue>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            JitIter::Items(items) => items.next().map(Cow::Borrowed),
+            JitIter::Chars(chars) => chars.next().map(|c| Cow::Owned(JitValue::Char(c))),
+        }
+    }
+}
+
+impl JitValue {
+    /// Compares like `==`, except that floats of the same kind are equal when
+    /// they differ by at most `epsilon`, including inside structs, arrays and
+    /// options. Other values must be exactly equal.
+    pub fn approx_eq(&self, other: &JitValue, epsilon: f64) -> bool {
+        match (self, other) {
+            // Equal infinities differ by NaN, so they're compared exactly first
+            (JitValue::Float(l), JitValue::Float(r)) => l == r || (l - r).abs() <= epsilon,
+            (JitValue::F32(l), JitValue::F32(r)) => {
+                l == r || (f64::from(*l) - f64::from(*r)).abs() <= epsilon
+            }
+            (
+                JitValue::Struct {
+                    name: ln,
+                    fields: lf,
+                },
+                JitValue::Struct {
+                    name: rn,
+                    fields: rf,
+                },
+            ) => {
+                ln == rn
+                    && lf.len() == rf.len()
+                    && lf
+                        .iter()
+                        .zip(rf)
+                        .all(|((ln, l), (rn, r))| ln == rn && l.approx_eq(r, epsilon))
+            }
+            (JitValue::Array { items: l, .. }, JitValue::Array { items: r, .. }) => {
+                l.len() == r.len() && l.iter().zip(r).all(|(l, r)| l.approx_eq(r, epsilon))
+            }
+            (JitValue::Option(Some(l)), JitValue::Option(Some(r))) => l.approx_eq(r, epsilon),
+            (
+                JitValue::Enum {
+                    name: ln,
+                    variant: lv,
+                    fields: lf,
+                },
+                JitValue::Enum {
+                    name: rn,
+                    variant: rv,
+                    fields: rf,
+                },
+            ) => {
+                ln == rn
+                    && lv == rv
+                    && lf.len() == rf.len()
+                    && lf.iter().zip(rf).all(|(l, r)| l.approx_eq(r, epsilon))
+            }
+            _ => self == other,
+        }
+    }
+}
+
+/// The bitwise operators, returning errors where the `std::ops` traits
+/// would have to panic.
+#[allow(clippy::should_implement_trait)]
+impl JitValue {
+    /// Bitwise and of two integers of the same kind.
+    pub fn bitand(self, rhs: Self) -> RtlResult<JitValue> {
+        self.bitwise(rhs, "bitwise and", |l, r| l & r)
+    }
+
+    /// Bitwise or of two integers of the same kind.
+    pub fn bitor(self, rhs: Self) -> RtlResult<JitValue> {
+        self.bitwise(rhs, "bitwise or", |l, r| l | r)
+    }
+
+    /// Bitwise exclusive or of two integers of the same kind.
+    pub fn bitxor(self, rhs: Self) -> RtlResult<JitValue> {
+        self.bitwise(rhs, "bitwise xor", |l, r| l ^ r)
+    }
+
+    /// Shifts an integer left by another of the same kind, failing if the
+    /// amount is negative or at least the integer's width.
+    pub fn shl(self, rhs: Self) -> RtlResult<JitValue> {
+        self.shift(rhs, "left shift", |l, n| l << n)
+    }
+
+    /// Shifts an integer right, keeping its sign; fails like [`JitValue::shl`].
+    pub fn shr(self, rhs: Self) -> RtlResult<JitValue> {
+        self.shift(rhs, "right shift", |l, n| l >> n)
+    }
+
+    /// Applies a bitwise operator to two integers of the same kind. The
+    /// narrower integers are sign-extended to `i128` and truncated back, which
+    /// `&`, `|` and `^` commute with.
+    fn bitwise(self, rhs: Self, op: &str, int: fn(i128, i128) -> i128) -> RtlResult<JitValue> {
+        match (self, rhs) {
+            (JitValue::Int(l), JitValue::Int(r)) => Ok(JitValue::Int(int(l, r))),
+            (JitValue::I64(l), JitValue::I64(r)) => {
+                Ok(JitValue::I64(int(l.into(), r.into()) as i64))
+            }
+            (JitValue::I32(l), JitValue::I32(r)) => {
+                Ok(JitValue::I32(int(l.into(), r.into()) as i32))
+            }
+            (l, r) => Err(JitError::Unsupported(format!(
+                "{} of {} and {}",
+                op,
+                l.kind_name(),
+                r.kind_name()
+            ))
+            .into()),
+        }
+    }
+
+    /// Applies a shift to two integers of the same kind, checking the amount
+    /// against the width of the kind.
+    fn shift(self, rhs: Self, op: &str, int: fn(i128, u32) -> i128) -> RtlResult<JitValue> {
+        let (value, amount, bits) = match (&self, &rhs) {
+            (JitValue::Int(l), JitValue::Int(r)) => (*l, *r, 128),
+            (JitValue::I64(l), JitValue::I64(r)) => ((*l).into(), (*r).into(), 64),
+            (JitValue::I32(l), JitValue::I32(r)) => ((*l).into(), (*r).into(), 32),
+            (l, r) => {
+                return Err(JitError::Unsupported(format!(
+                    "{} of {} and {}",
+                    op,
+                    l.kind_name(),
+                    r.kind_name()
+                ))
+                .into())
+            }
+        };
+        let n = match u32::try_from(amount) {
+            Ok(n) if n < bits => n,
+            _ => return Err(JitError::InvalidShift { amount, bits }.into()),
+        };
+        // Shifting the widened value then truncating matches shifting in the
+        // narrower kind, bits shifted out at the top included
+        Ok(match self {
+            JitValue::I64(_) => JitValue::I64(int(value, n) as i64),
+            JitValue::I32(_) => JitValue::I32(int(value, n) as i32),
+            _ => JitValue::Int(int(value, n)),
+        })
+    }
+}
+
 /// Implementing addition for JitValue.
+///
+/// Panics on integer overflow, in debug and release builds alike, and on
+/// values of different kinds; [`JitValue::checked_add`] returns an error instead.
 impl Add for JitValue {
     type Output = JitValue;
 
     fn add(self, rhs: Self) -> Self::Output {
-        match (self, rhs) {
-            (JitValue::Int(left), JitValue::Int(right)) => JitValue::Int(left + right),
-            (JitValue::Float(left), JitValue::Float(right)) => JitValue::Float(left + right),
-            _ => panic!("Unsupported operation: addition with non-matching types"),
-        }
+        self.checked_add(rhs)
+            .unwrap_or_else(|err| panic!("{}", err))
     }
 }
 
 /// Implementing subtraction for JitValue.
+///
+/// Panics like [`Add`]; [`JitValue::checked_sub`] returns an error instead.
 impl Sub for JitValue {
     type Output = JitValue;
 
     fn sub(self, rhs: Self) -> Self::Output {
-        match (self, rhs) {
-            (JitValue::Int(left), JitValue::Int(right)) => JitValue::Int(left - right),
-            (JitValue::Float(left), JitValue::Float(right)) => JitValue::Float(left - right),
-            _ => panic!("Unsupported operation: subtraction with non-matching types"),
-        }
+        self.checked_sub(rhs)
+            .unwrap_or_else(|err| panic!("{}", err))
     }
 }
 
 /// Implementing multiplication for JitValue.
+///
+/// Panics like [`Add`]; [`JitValue::checked_mul`] returns an error instead.
 impl Mul for JitValue {
     type Output = JitValue;
 
     fn mul(self, rhs: Self) -> Self::Output {
-        match (self, rhs) {
-            (JitValue::Int(left), JitValue::Int(right)) => JitValue::Int(left * right),
-            (JitValue::Float(left), JitValue::Float(right)) => JitValue::Float(left * right),
-            _ => panic!("Unsupported operation: multiplication with non-matching types"),
-        }
+        self.checked_mul(rhs)
+            .unwrap_or_else(|err| panic!("{}", err))
     }
 }
 
 /// Implementing division for JitValue.
+///
+/// Panics like [`Add`], and on integer division by zero;
+/// [`JitValue::checked_div`] returns an error instead.
 impl Div for JitValue {
     type Output = JitValue;
 
     fn div(self, rhs: Self) -> Self::Output {
-        match (self, rhs) {
-            (JitValue::Int(left), JitValue::Int(right)) => {
-                match right == 0 {
-                    true => {
-                        panic!("Division by zero");
-                    }
-                    false => (),
-                }
-                JitValue::Int(left / right)
-            }
-            (JitValue::Float(left), JitValue::Float(right)) => {
-                match right == 0f64 {
-                    true => {
-                        panic!("Division by zero");
-                    }
-                    false => (),
-                }
-                JitValue::Float(left / right)
-            }
-            _ => panic!("Unsupported operation: division with non-matching types"),
-        }
+        self.checked_div(rhs)
+            .unwrap_or_else(|err| panic!("{}", err))
     }
 }
 
 /// Implementing modulus for JitValue.
+///
+/// Panics like [`Div`]; [`JitValue::checked_rem`] returns an error instead.
 impl Rem for JitValue {
     type Output = JitValue;
 
     fn rem(self, rhs: Self) -> Self::Output {
-        match (self, rhs) {
-            (JitValue::Int(left), JitValue::Int(right)) => {
-                match right == 0 {
-                    true => {
-                        panic!("Division by zero");
-                    }
-                    false => (),
-                }
-                JitValue::Int(left % right)
-            }
-            (JitValue::Float(left), JitValue::Float(right)) => {
-                match right == 0f64 {
-                    true => {
-                        panic!("Division by zero");
-                    }
-                    false => (),
-                }
-                JitValue::Float(left % right)
-            }
-            _ => panic!("Unsupported operation: modulus with non-matching types"),
-        }
+        self.checked_rem(rhs)
+            .unwrap_or_else(|err| panic!("{}", err))
     }
 }
 
 /// Metadata for Jit variables.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct JitMeta {
     is_mut: bool,
+    /// Whether it was declared as a constant, which is never mutable but is
+    /// reported apart from immutable variables.
+    is_const: bool,
 }
 
 impl JitMeta {
     pub fn new(is_mut: bool) -> Self {
-        Self { is_mut }
+        Self {
+            is_mut,
+            is_const: false,
+        }
+    }
+
+    /// Metadata for a constant.
+    pub fn constant() -> Self {
+        Self {
+            is_mut: false,
+            is_const: true,
+        }
+    }
+
+    /// Checks that the variable named `name` can be assigned to.
+    pub fn check_assign(&self, name: &str) -> Result<(), JitError> {
+        if self.is_const {
+            Err(JitError::ConstAssign(name.to_string()))
+        } else if !self.is_mut {
+            Err(JitError::ImmutableVariable(name.to_string()))
+        } else {
+            Ok(())
+        }
     }
 }
 
@@ -255,6 +1404,14 @@ struct JitCompiler<'ctx> {
     builder: Builder<'ctx>,
     var_types: HashMap<&'static str, (JitMeta, JitValue)>,
     should_execute: bool,
+    /// Whether compiled functions carry debug locations.
+    debug_info: bool,
+    /// The tolerance [`switch`](JitCompiler::switch) matches floats with, if
+    /// any.
+    float_epsilon: Option<f64>,
+    /// The default of each parameter of the Rattle functions declared so
+    /// far, by function name, so calls can leave trailing arguments out.
+    defaults: RefCell<HashMap<String, Vec<Option<RtlExpr>>>>,
 }
 
 impl<'ctx> JitCompiler<'ctx> {
@@ -276,35 +1433,66 @@ impl<'ctx> JitCompiler<'ctx> {
             builder,
             var_types: HashMap::new(),
             should_execute: true, // Start with execution enabled
+            debug_info: false,
+            float_epsilon: None,
+            defaults: RefCell::new(HashMap::new()),
         }
     }
 
+    /// Sets whether [`compile_fn`](JitCompiler::compile_fn) attaches debug
+    /// locations, so the IR maps each instruction back to its source line.
+    pub fn set_debug_info(&mut self, enabled: bool) {
+        self.debug_info = enabled;
+    }
+
+    /// Sets the tolerance [`switch`](JitCompiler::switch) matches float cases
+    /// with, using [`JitValue::approx_eq`]. `None`, the default, matches them
+    /// exactly.
+    pub fn set_float_epsilon(&mut self, epsilon: Option<f64>) {
+        self.float_epsilon = epsilon;
+    }
+
+    /// Discards every variable and all compiled code, starting over with an
+    /// empty module of the same name.
+    pub fn reset(&mut self) {
+        let name = self.module.get_name().to_string_lossy().into_owned();
+        let module = self.context.create_module(&name);
+        let execution_engine = module
+            .create_jit_execution_engine(OptimizationLevel::Aggressive)
+            .expect("Failed to create Jit execution engine");
+
+        // The old module is owned by the old engine; inkwell keeps the engine
+        // alive until both handles are dropped, so the order here is safe.
+        self.execution_engine = execution_engine;
+        self.module = module;
+        self.builder = self.context.create_builder();
+        self.var_types.clear();
+    }
+
     /// Declares a constant variable.
     pub fn decl_const(&mut self, name: &'static str, value: JitValue) {
-        self.var_types
-            .insert(name, (JitMeta { is_mut: false }, value));
+        self.var_types.insert(name, (JitMeta::constant(), value));
     }
 
     /// Declares an immutable variable.
     pub fn decl_var(&mut self, name: &'static str, value: JitValue) {
-        self.var_types
-            .insert(name, (JitMeta { is_mut: false }, value));
+        self.var_types.insert(name, (JitMeta::new(false), value));
     }
 
     /// Declares a mutable variable.
     pub fn decl_var_mut(&mut self, name: &'static str, value: JitValue) {
-        self.var_types
-            .insert(name, (JitMeta { is_mut: true }, value));
+        self.var_types.insert(name, (JitMeta::new(true), value));
     }
 
     /// Assigns a new value to a variable.
+    ///
+    /// Panics if the variable is undeclared, immutable or a constant.
     pub fn assign_var(&mut self, name: &'static str, value: JitValue) {
         if let Some((m, entry)) = self.var_types.get_mut(name) {
-            if m.is_mut {
-                *entry = value;
-            } else {
-                panic!("Variable '{}' is immutable!", name);
+            if let Err(err) = m.check_assign(name) {
+                panic!("{}", err);
             }
+            *entry = value;
         } else {
             panic!("Variable '{}' not found", name);
         }
@@ -316,10 +1504,26 @@ impl<'ctx> JitCompiler<'ctx> {
     }
 
     /// Gets a cloned value of a variable.
-    pub fn get_auto(&self, name: &'static str) -> RtlResult<JitValue> {
-        self.get(name)?
-            .ok_or_else(|| format!("Variable '{}' not found", name).into())
-            .cloned()
+    pub fn get_auto(&self, name: &'static str) -> Result<JitValue, JitError> {
+        self.var_types
+            .get(name)
+            .map(|s| s.1.clone())
+            .ok_or_else(|| JitError::UndefinedVariable(name.to_string(), None))
+    }
+
+    /// Returns `true` if a variable of this name is declared.
+    pub fn contains_var(&self, name: &str) -> bool {
+        self.var_types.contains_key(name)
+    }
+
+    /// Undeclares a variable, returning its value if it was declared.
+    pub fn remove_var(&mut self, name: &str) -> Option<JitValue> {
+        self.var_types.remove(name).map(|(_, value)| value)
+    }
+
+    /// Returns the names of all declared variables, in no particular order.
+    pub fn var_names(&self) -> impl Iterator<Item = &str> {
+        self.var_types.keys().copied()
     }
 
     /// Implements a switch-case-like structure.
@@ -331,38 +1535,229 @@ impl<'ctx> JitCompiler<'ctx> {
     ) -> RtlResult<JitValue> {
         let actual = self.get_auto(name)?;
         for case in cases {
-            if actual == case.0 {
+            let matched = match self.float_epsilon {
+                Some(epsilon) => actual.approx_eq(&case.0, epsilon),
+                None => actual == case.0,
+            };
+            if matched {
                 return Ok(case.1);
             }
         }
         Ok(default)
     }
 
-    /// Runs a Jit-compiled function.
+    /// Runs a Jit-compiled function, traced under the function's `name`.
+    #[cfg_attr(not(feature = "trace"), allow(unused_variables))]
     pub fn run_function(
         &self,
+        name: &str,
         jit_fn: JitFunction<unsafe extern "C" fn() -> i32>,
     ) -> RtlResult<i32> {
+        crate::trace_span!("run_function", function = name);
         Ok(unsafe { jit_fn.call() })
     }
 
+    /// Checks that a compiled function exists, takes `arity` parameters and
+    /// takes and returns only `Int`s, so that calling it through an
+    /// `extern "C" fn(i128, ...) -> i128` is sound.
+    fn check_int_signature(&self, name: &str, arity: usize) -> Result<(), JitError> {
+        let function = self
+            .module
+            .get_function(name)
+            .ok_or_else(|| JitError::UndefinedFunction(name.to_string()))?;
+        let expected = function.count_params() as usize;
+        if expected != arity {
+            return Err(JitError::ArityMismatch {
+                name: name.to_string(),
+                expected,
+                found: arity,
+            });
+        }
+        let fn_type = function.get_type();
+        let params_int = fn_type.get_param_types().into_iter().all(
+            |ty| matches!(ty, BasicMetadataTypeEnum::IntType(int) if int.get_bit_width() == 128),
+        );
+        let returns_int = matches!(
+            fn_type.get_return_type(),
+            Some(BasicTypeEnum::IntType(int)) if int.get_bit_width() == 128
+        );
+        if !params_int || !returns_int {
+            return Err(JitError::TypeMismatch {
+                expected: format!("'{}' to take and return only Int", name),
+                found: fn_type.print_to_string().to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Runs a Jit-compiled `fn(Int) Int` by name, failing if the function
+    /// has another signature.
+    pub fn run_i128_1(&self, name: &str, arg: i128) -> RtlResult<i128> {
+        crate::trace_span!("run_function", function = name);
+        self.check_int_signature(name, 1)?;
+        let jit_fn = unsafe {
+            self.execution_engine
+                .get_function::<unsafe extern "C" fn(i128) -> i128>(name)?
+        };
+        Ok(unsafe { jit_fn.call(arg) })
+    }
+
+    /// Runs a Jit-compiled `fn(Int, Int) Int` by name, failing if the
+    /// function has another signature.
+    pub fn run_i128_2(&self, name: &str, a: i128, b: i128) -> RtlResult<i128> {
+        crate::trace_span!("run_function", function = name);
+        self.check_int_signature(name, 2)?;
+        let jit_fn = unsafe {
+            self.execution_engine
+                .get_function::<unsafe extern "C" fn(i128, i128) -> i128>(name)?
+        };
+        Ok(unsafe { jit_fn.call(a, b) })
+    }
+
+    /// Runs a Jit-compiled function taking and returning only `Int`s by
+    /// name, with up to [`MAX_CALL_ARGS`] arguments. `Int` is 128 bits wide,
+    /// so the arguments are widened and the result must fit in an `i64`.
+    /// Fails without calling it if the function has any other signature.
+    pub fn call_i64(&self, name: &str, args: &[i64]) -> RtlResult<i64> {
+        crate::trace_span!("run_function", function = name);
+        self.check_int_signature(name, args.len())?;
+        let args: Vec<i128> = args.iter().map(|&arg| arg as i128).collect();
+        let result = unsafe { self.call_i128(name, &args)? };
+        i64::try_from(result).map_err(|_| JitError::IntegerOverflow.into())
+    }
+
+    /// Calls a compiled function as an `extern "C" fn(i128, ...) -> i128`.
+    ///
+    /// # Safety
+    ///
+    /// The function must take `args.len()` `Int` parameters and return an
+    /// `Int`.
+    unsafe fn call_i128(&self, name: &str, args: &[i128]) -> RtlResult<i128> {
+        type I = i128;
+        let engine = &self.execution_engine;
+        Ok(match *args {
+            [] => engine
+                .get_function::<unsafe extern "C" fn() -> I>(name)?
+                .call(),
+            [a] => engine
+                .get_function::<unsafe extern "C" fn(I) -> I>(name)?
+                .call(a),
+            [a, b] => engine
+                .get_function::<unsafe extern "C" fn(I, I) -> I>(name)?
+                .call(a, b),
+            [a, b, c] => engine
+                .get_function::<unsafe extern "C" fn(I, I, I) -> I>(name)?
+                .call(a, b, c),
+            [a, b, c, d] => engine
+                .get_function::<unsafe extern "C" fn(I, I, I, I) -> I>(name)?
+                .call(a, b, c, d),
+            [a, b, c, d, e] => engine
+                .get_function::<unsafe extern "C" fn(I, I, I, I, I) -> I>(name)?
+                .call(a, b, c, d, e),
+            [a, b, c, d, e, f] => engine
+                .get_function::<unsafe extern "C" fn(I, I, I, I, I, I) -> I>(name)?
+                .call(a, b, c, d, e, f),
+            _ => {
+                return Err(JitError::Unsupported(format!(
+                    "calling a function with {} arguments, more than {}",
+                    args.len(),
+                    MAX_CALL_ARGS
+                ))
+                .into())
+            }
+        })
+    }
+
     /// Gets the execution engine.
     pub fn get_execution_engine(&self) -> &ExecutionEngine<'ctx> {
         &self.execution_engine
     }
+
+    /// Verifies the module, returning the LLVM verifier message on failure.
+    pub fn verify(&self) -> RtlResult<()> {
+        self.module
+            .verify()
+            .map_err(|msg| JitError::InvalidModule(msg.to_string()).into())
+    }
 }
 
-/// A macro to convert Jit values to strings.
+/// Enters a `tracing` span with the given name and fields until the end of
+/// the enclosing block, recording how long it took as `elapsed_us`. Without
+/// the `trace` feature it expands to nothing.
+#[macro_export]
+macro_rules! trace_span {
+    ($name:literal $(, $($fields:tt)+)?) => {
+        #[cfg(feature = "trace")]
+        let _span = $crate::TimedSpan::enter(tracing::info_span!(
+            $name,
+            $($($fields)+,)?
+            elapsed_us = tracing::field::Empty
+        ));
+    };
+}
+
+/// A span entered by [`trace_span!`], recording its `elapsed_us` field when
+/// dropped.
+#[cfg(feature = "trace")]
+pub struct TimedSpan {
+    span: tracing::span::EnteredSpan,
+    start: std::time::Instant,
+}
+
+#[cfg(feature = "trace")]
+impl TimedSpan {
+    pub fn enter(span: tracing::Span) -> Self {
+        TimedSpan {
+            span: span.entered(),
+            start: std::time::Instant::now(),
+        }
+    }
+}
+
+#[cfg(feature = "trace")]
+impl Drop for TimedSpan {
+    fn drop(&mut self) {
+        let elapsed = self.start.elapsed().as_micros() as u64;
+        self.span.record("elapsed_us", elapsed);
+    }
+}
+
+/// A macro to convert Jit values to strings, yielding a `Result<String, JitError>`.
+#[macro_export]
+macro_rules! try_typed {
+    ($jit_compiler:expr, $name:expr) => {{
+        $jit_compiler.get_auto($name).map(|value| match value {
+            JitValue::Int(int_value) => int_value.to_string(),
+            JitValue::String(string_value) => string_value.clone(),
+            JitValue::Float(float_value) => float_value.to_string(),
+            value => value.to_string(),
+            // Add more cases for other types as needed
+        })
+    }};
+}
+
+/// A macro to convert Jit values to strings, panicking if the variable is missing.
 #[macro_export]
 macro_rules! typed {
     ($jit_compiler:expr, $name:expr) => {{
-        match $jit_compiler.get_auto($name) {
-            Ok(value) => match value {
-                JitValue::Int(int_value) => int_value.to_string(),
-                JitValue::String(string_value) => string_value.clone(),
-                JitValue::Float(float_value) => float_value.to_string(),
-                // Add more cases for other types as needed
-            },
+        match try_typed!($jit_compiler, $name) {
+            Ok(value) => value,
+            Err(err) => panic!("Error: {}", err),
+        }
+    }};
+}
+
+/// Like `typed!`, but formatting the value with a spec like `"{:.2}"`; see
+/// [`format_value`].
+#[macro_export]
+macro_rules! typed_fmt {
+    ($jit_compiler:expr, $name:expr, $spec:expr) => {{
+        match $jit_compiler
+            .get_auto($name)
+            .map_err(Into::into)
+            .and_then(|value| format_value(&value, $spec))
+        {
+            Ok(value) => value,
             Err(err) => panic!("Error: {}", err),
         }
     }};
@@ -563,6 +1958,38 @@ mod tests {
         assert_eq!(result, JitValue::String("Two".to_string()));
     }
 
+    #[test]
+    fn test_approx_eq() {
+        let sum = JitValue::Float(0.1) + JitValue::Float(0.2);
+        assert_ne!(sum, JitValue::Float(0.3));
+        assert!(sum.approx_eq(&JitValue::Float(0.3), 1e-9));
+        assert!(!sum.approx_eq(&JitValue::Float(0.31), 1e-9));
+        assert!(!JitValue::Float(0.3).approx_eq(&JitValue::F32(0.3), 1.0));
+        let inf = JitValue::Float(f64::INFINITY);
+        assert!(inf.approx_eq(&inf, 1e-9));
+        assert!(!inf.approx_eq(&JitValue::Float(f64::NEG_INFINITY), 1e-9));
+        assert!(JitValue::F32(f32::INFINITY).approx_eq(&JitValue::F32(f32::INFINITY), 1e-9));
+        assert!(!JitValue::Float(f64::NAN).approx_eq(&JitValue::Float(f64::NAN), 1e-9));
+
+        let array = |items| JitValue::Array {
+            elem: JitKind::Float,
+            items,
+        };
+        assert!(array(vec![sum.clone()]).approx_eq(&array(vec![JitValue::Float(0.3)]), 1e-9));
+        assert!(!array(vec![sum.clone()]).approx_eq(&array(vec![]), 1e-9));
+
+        // Only with an epsilon set does `switch` match the float case
+        let context = Context::create();
+        let mut jit_compiler = JitCompiler::new(&context, "jit_test");
+        jit_compiler.decl_var("sum", sum);
+        let cases = vec![(JitValue::Float(0.3), JitValue::Int(1))];
+        let result = jit_compiler.switch("sum", cases.clone(), JitValue::Int(0));
+        assert_eq!(result.unwrap(), JitValue::Int(0));
+        jit_compiler.set_float_epsilon(Some(1e-9));
+        let result = jit_compiler.switch("sum", cases, JitValue::Int(0));
+        assert_eq!(result.unwrap(), JitValue::Int(1));
+    }
+
     #[test]
     fn test_typed_macro() {
         let context = Context::create();
@@ -576,4 +2003,446 @@ mod tests {
         assert_eq!(typed!(jit_compiler, "test_float"), "3.14");
         assert_eq!(typed!(jit_compiler, "test_string"), "hello");
     }
+
+    #[test]
+    fn test_bytes() {
+        let context = Context::create();
+        let mut jit_compiler = JitCompiler::new(&context, "jit_test");
+        let bytes = JitValue::Bytes(vec![0, 1, b'a', b'"', 0xff]);
+        assert_eq!(bytes.kind() as u8, 12);
+        assert_eq!(bytes.as_bytes(), Some(&[0, 1, b'a', b'"', 0xff][..]));
+        assert_ne!(bytes, JitValue::String("\0\u{1}a\"".into()));
+
+        jit_compiler.decl_var("data", bytes);
+        assert_eq!(typed!(jit_compiler, "data"), r#"b"\x00\x01a\"\xff""#);
+    }
+
+    #[test]
+    fn test_format_value() {
+        assert_eq!(
+            format_value(&JitValue::Float(3.14159), "{:.2}").unwrap(),
+            "3.14"
+        );
+        assert_eq!(format_value(&JitValue::Float(3.0), "{}").unwrap(), "3");
+        assert_eq!(
+            format_value(&JitValue::F32(1500.0), "{:.1e}").unwrap(),
+            "1.5e3"
+        );
+        assert_eq!(
+            format_value(&JitValue::Int(-42), "{:>6}").unwrap(),
+            "   -42"
+        );
+        assert_eq!(
+            format_value(&JitValue::Int(-42), "{:06}").unwrap(),
+            "-00042"
+        );
+        assert_eq!(format_value(&JitValue::Int(7), "{:+}").unwrap(), "+7");
+        assert_eq!(
+            format_value(&JitValue::I32(255), "{:#06x}").unwrap(),
+            "0x00ff"
+        );
+        assert_eq!(
+            format_value(&JitValue::I32(-1), "{:X}").unwrap(),
+            "FFFFFFFF"
+        );
+        assert_eq!(format_value(&JitValue::Int(5), "{:b}").unwrap(), "101");
+        assert_eq!(
+            format_value(&JitValue::String("hello".into()), "{:*^9.3}").unwrap(),
+            "***hel***"
+        );
+        assert_eq!(
+            format_value(&JitValue::Bool(true), "{:<6}").unwrap(),
+            "true  "
+        );
+
+        let err = format_value(&JitValue::Float(1.0), "{:x}").unwrap_err();
+        assert_eq!(err.to_string(), "Type mismatch: expected Int, found Float");
+        for spec in ["{:.}", "{:q}", ":.2", "{.2}"] {
+            let err = format_value(&JitValue::Int(1), spec).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                format!("Invalid format specification '{}'", spec)
+            );
+        }
+
+        let context = Context::create();
+        let mut jit_compiler = JitCompiler::new(&context, "jit_test");
+        jit_compiler.decl_var("price", JitValue::Float(19.5));
+        assert_eq!(typed_fmt!(jit_compiler, "price", "{:.2}"), "19.50");
+    }
+
+    #[test]
+    fn test_try_typed_macro() {
+        let context = Context::create();
+        let mut jit_compiler = JitCompiler::new(&context, "jit_test");
+
+        jit_compiler.decl_var("test_int", JitValue::Int(42));
+
+        assert_eq!(try_typed!(jit_compiler, "test_int"), Ok("42".to_string()));
+        assert_eq!(
+            try_typed!(jit_compiler, "missing"),
+            Err(JitError::UndefinedVariable("missing".to_string(), None))
+        );
+    }
+
+    #[test]
+    fn test_undefined_variable() {
+        let context = Context::create();
+        let jit_compiler = JitCompiler::new(&context, "jit_test");
+
+        match jit_compiler.get_auto("x") {
+            Err(JitError::UndefinedVariable(name, None)) => assert_eq!(name, "x"),
+            other => panic!("expected an undefined variable, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_verify() {
+        let context = Context::create();
+        let jit_compiler = JitCompiler::new(&context, "jit_test");
+
+        let fn_type = context.i32_type().fn_type(&[], false);
+        let function = jit_compiler.module.add_function("valid", fn_type, None);
+        let entry = context.append_basic_block(function, "entry");
+        jit_compiler.builder.position_at_end(entry);
+        jit_compiler
+            .builder
+            .build_return(Some(&context.i32_type().const_int(0, false)))
+            .unwrap();
+
+        assert!(jit_compiler.verify().is_ok());
+
+        // A basic block without a terminator is rejected by the verifier.
+        let function = jit_compiler.module.add_function("invalid", fn_type, None);
+        context.append_basic_block(function, "entry");
+
+        let err = jit_compiler.verify().unwrap_err().to_string();
+        assert!(err.contains("does not have terminator"), "{}", err);
+    }
+
+    #[test]
+    fn test_kind() {
+        let values = [
+            (JitValue::Int(1), JitKind::Int, 0u8, "Int"),
+            (JitValue::Float(1.0), JitKind::Float, 1u8, "Float"),
+            (
+                JitValue::String("s".to_string()),
+                JitKind::String,
+                2u8,
+                "String",
+            ),
+        ];
+        for (value, kind, tag, name) in values {
+            assert_eq!(value.kind(), kind);
+            assert_eq!(value.kind() as u8, tag);
+            assert_eq!(value.kind_name(), name);
+        }
+    }
+
+    #[test]
+    fn test_cast() {
+        let cast = |v: JitValue, kind| v.cast_to(kind).unwrap();
+        assert_eq!(cast(JitValue::Int(5), JitKind::Float), JitValue::Float(5.0));
+        assert_eq!(cast(JitValue::Float(2.9), JitKind::Int), JitValue::Int(2));
+        assert_eq!(cast(JitValue::Float(-2.9), JitKind::Int), JitValue::Int(-2));
+        assert_eq!(
+            cast(JitValue::Float(1e40), JitKind::I32),
+            JitValue::I32(i32::MAX)
+        );
+        assert_eq!(
+            cast(JitValue::Float(f64::NAN), JitKind::I64),
+            JitValue::I64(0)
+        );
+        assert_eq!(
+            cast(JitValue::Int(1 << 32 | 7), JitKind::I32),
+            JitValue::I32(7)
+        );
+        assert_eq!(cast(JitValue::I32(-1), JitKind::Int), JitValue::Int(-1));
+
+        assert_eq!(cast(JitValue::Char('a'), JitKind::Int), JitValue::Int(97));
+        assert_eq!(cast(JitValue::Int(97), JitKind::Char), JitValue::Char('a'));
+        assert_eq!(cast(JitValue::Bool(true), JitKind::I32), JitValue::I32(1));
+        assert_eq!(cast(JitValue::Int(2), JitKind::Bool), JitValue::Bool(true));
+
+        let err = JitValue::Int(0xD800).cast_to(JitKind::Char).unwrap_err();
+        assert_eq!(err.to_string(), "Cannot cast Int to Char");
+        let err = JitValue::String("5".into())
+            .cast_to(JitKind::Int)
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Cannot cast String to Int");
+        assert!(JitValue::Char('a').cast_to(JitKind::Float).is_err());
+    }
+
+    #[test]
+    fn test_checked_arith() {
+        let err = JitValue::Int(i128::MAX)
+            .checked_add(JitValue::Int(1))
+            .unwrap_err();
+        assert_eq!(err.to_string(), "integer overflow");
+        assert!(JitValue::Int(i128::MIN)
+            .checked_sub(JitValue::Int(1))
+            .is_err());
+        assert!(JitValue::Int(i128::MAX)
+            .checked_mul(JitValue::Int(2))
+            .is_err());
+        assert_eq!(
+            JitValue::Int(i128::MAX - 1)
+                .checked_add(JitValue::Int(1))
+                .unwrap(),
+            JitValue::Int(i128::MAX)
+        );
+        assert_eq!(
+            JitValue::Int(i128::MIN)
+                .checked_mul(JitValue::Int(1))
+                .unwrap(),
+            JitValue::Int(i128::MIN)
+        );
+
+        // The narrower kinds overflow at their own bounds
+        assert!(JitValue::I32(i32::MAX)
+            .checked_add(JitValue::I32(1))
+            .is_err());
+        assert!(JitValue::I64(i64::MIN)
+            .checked_sub(JitValue::I64(1))
+            .is_err());
+        assert!(JitValue::I64(i64::MAX)
+            .checked_mul(JitValue::I64(i64::MAX))
+            .is_err());
+        assert_eq!(
+            JitValue::I32(i32::MAX)
+                .checked_sub(JitValue::I32(1))
+                .unwrap(),
+            JitValue::I32(i32::MAX - 1)
+        );
+
+        // Floats go to infinity instead
+        assert_eq!(
+            JitValue::Float(f64::MAX)
+                .checked_mul(JitValue::Float(2.0))
+                .unwrap(),
+            JitValue::Float(f64::INFINITY)
+        );
+        let err = JitValue::Int(1)
+            .checked_add(JitValue::Float(1.0))
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Unsupported: addition of Int and Float");
+    }
+
+    #[test]
+    fn test_bitwise_values() {
+        let x = JitValue::Int(0b1100).bitand(JitValue::Int(0b1010)).unwrap();
+        assert_eq!(x, JitValue::Int(8));
+        let x = JitValue::I64(0b1100).bitxor(JitValue::I64(0b1010)).unwrap();
+        assert_eq!(x, JitValue::I64(0b0110));
+        let x = JitValue::I32(-1).bitor(JitValue::I32(0)).unwrap();
+        assert_eq!(x, JitValue::I32(-1));
+        assert_eq!(
+            JitValue::Int(1).shl(JitValue::Int(4)).unwrap(),
+            JitValue::Int(16)
+        );
+        // Bits shifted past the top are dropped, as in the narrower kind
+        assert_eq!(
+            JitValue::I32(i32::MAX).shl(JitValue::I32(1)).unwrap(),
+            JitValue::I32(-2)
+        );
+        assert_eq!(
+            JitValue::I64(-8).shr(JitValue::I64(1)).unwrap(),
+            JitValue::I64(-4)
+        );
+
+        let err = JitValue::Int(1).shl(JitValue::Int(200)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Shift by 200 out of range for 128-bit integer"
+        );
+        assert!(JitValue::I64(1).shr(JitValue::I64(64)).is_err());
+        assert!(JitValue::I32(1).shl(JitValue::I32(-1)).is_err());
+        assert!(JitValue::Float(1.0).bitand(JitValue::Float(1.0)).is_err());
+        assert!(JitValue::Bool(true).bitor(JitValue::Bool(false)).is_err());
+        assert!(JitValue::Int(1).bitand(JitValue::I32(1)).is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "integer overflow")]
+    fn test_add_overflow_panics() {
+        let _ = JitValue::I32(i32::MIN) + JitValue::I32(-1);
+    }
+
+    #[test]
+    #[should_panic(expected = "integer overflow")]
+    fn test_div_overflow_panics() {
+        let _ = JitValue::Int(i128::MIN) / JitValue::Int(-1);
+    }
+
+    #[test]
+    #[should_panic(expected = "integer overflow")]
+    fn test_rem_by_zero_panics() {
+        let _ = JitValue::I64(7) % JitValue::I64(0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot assign to constant 'LIMIT'")]
+    fn test_assign_const_panics() {
+        let context = Context::create();
+        let mut jit_compiler = JitCompiler::new(&context, "jit_test");
+        jit_compiler.decl_const("LIMIT", JitValue::Int(3));
+        jit_compiler.assign_var("LIMIT", JitValue::Int(4));
+    }
+
+    #[test]
+    fn test_set_index() {
+        let mut arr = JitValue::Array {
+            elem: JitKind::Int,
+            items: vec![JitValue::Int(1), JitValue::Int(2)],
+        };
+
+        arr.set_index(1, JitValue::Int(5)).unwrap();
+        assert_eq!(arr.index(1), Some(&JitValue::Int(5)));
+        assert_eq!(arr.to_string(), "[1, 5]");
+
+        let err = arr.set_index(2, JitValue::Int(3)).unwrap_err();
+        assert_eq!(err.to_string(), "Index 2 out of bounds for length 2");
+
+        let err = arr.set_index(0, JitValue::Float(3.0)).unwrap_err();
+        assert_eq!(err.to_string(), "Type mismatch: expected Int, found Float");
+        assert_eq!(arr.index(0), Some(&JitValue::Int(1)));
+    }
+
+    #[test]
+    fn test_string_index() {
+        let s = JitValue::String("héllo".into());
+        assert_eq!(s.char_at(1).unwrap(), JitValue::Char('é'));
+        assert_eq!(s.char_at(2).unwrap(), JitValue::Char('l'));
+        assert_eq!(s.substring(1..3).unwrap(), JitValue::String("él".into()));
+        assert_eq!(s.substring(5..5).unwrap(), JitValue::String("".into()));
+
+        let err = s.char_at(5).unwrap_err();
+        assert_eq!(err.to_string(), "Index 5 out of bounds for length 5");
+        let err = s.substring(2..6).unwrap_err();
+        assert_eq!(err.to_string(), "Slice 2..6 out of bounds for length 5");
+        let err = s.substring(Range { start: 3, end: 1 }).unwrap_err();
+        assert_eq!(err.to_string(), "Slice 3..1 out of bounds for length 5");
+        assert!(JitValue::Int(1).char_at(0).is_err());
+    }
+
+    #[test]
+    fn test_content_hash() {
+        let point = |x| JitValue::Struct {
+            name: "Point".to_string(),
+            fields: vec![
+                ("x".to_string(), JitValue::Float(x)),
+                ("y".to_string(), JitValue::Int(2)),
+            ],
+        };
+        let arr = |v| JitValue::Array {
+            elem: JitKind::Struct,
+            items: vec![point(v)],
+        };
+
+        assert_eq!(arr(1.5).content_hash(), arr(1.5).content_hash());
+        assert_eq!(arr(1.5).content_hash(), arr(1.5).clone().content_hash());
+        assert_ne!(arr(1.5).content_hash(), arr(2.5).content_hash());
+        assert_eq!(point(0.0).content_hash(), point(-0.0).content_hash());
+        assert_eq!(
+            JitValue::Float(f64::NAN).content_hash(),
+            JitValue::Float(-f64::NAN).content_hash()
+        );
+        assert_ne!(
+            JitValue::Int(1).content_hash(),
+            JitValue::I32(1).content_hash()
+        );
+        assert_ne!(
+            JitValue::String("ab".into()).content_hash(),
+            JitValue::String("a".into()).content_hash()
+        );
+        // Pinned, so a change to the hashing shows up as a cache-breaking change
+        assert_eq!(JitValue::Int(0).content_hash(), 0x4dfa_4cff_d1f7_979f);
+    }
+
+    #[test]
+    fn test_reset() {
+        let context = Context::create();
+        let mut jit_compiler = JitCompiler::new(&context, "jit_test");
+
+        jit_compiler.decl_var("a", JitValue::Int(1));
+        jit_compiler.decl_var_mut("b", JitValue::Float(2.0));
+        jit_compiler.reset();
+
+        assert_eq!(jit_compiler.get("a").unwrap(), None);
+        assert_eq!(jit_compiler.get("b").unwrap(), None);
+
+        jit_compiler.decl_var("a", JitValue::Int(3));
+        assert_eq!(jit_compiler.get_auto("a").unwrap(), JitValue::Int(3));
+    }
+
+    #[test]
+    fn test_remove_var() {
+        let context = Context::create();
+        let mut jit_compiler = JitCompiler::new(&context, "jit_test");
+
+        jit_compiler.decl_var("a", JitValue::Int(1));
+        jit_compiler.decl_var_mut("b", JitValue::Float(2.0));
+        assert!(jit_compiler.contains_var("a"));
+        let mut names = jit_compiler.var_names().collect::<Vec<_>>();
+        names.sort_unstable();
+        assert_eq!(names, ["a", "b"]);
+
+        assert_eq!(jit_compiler.remove_var("a"), Some(JitValue::Int(1)));
+        assert!(!jit_compiler.contains_var("a"));
+        assert_eq!(jit_compiler.remove_var("a"), None);
+        assert_eq!(jit_compiler.var_names().collect::<Vec<_>>(), ["b"]);
+
+        // A removed name can be declared again, even with another mutability
+        jit_compiler.decl_var_mut("a", JitValue::Int(2));
+        jit_compiler.assign_var("a", JitValue::Int(3));
+        assert_eq!(jit_compiler.get_auto("a").unwrap(), JitValue::Int(3));
+    }
+
+    #[test]
+    fn test_run_i128() {
+        let context = Context::create();
+        let jit_compiler = JitCompiler::new(&context, "jit_test");
+        let i128_type = context.i128_type();
+
+        let inc_type = i128_type.fn_type(&[i128_type.into()], false);
+        let inc = jit_compiler.module.add_function("inc", inc_type, None);
+        jit_compiler
+            .builder
+            .position_at_end(context.append_basic_block(inc, "entry"));
+        let x = inc.get_nth_param(0).unwrap();
+        let one = i128_type.const_int(1, false).into();
+        let sum = jit_compiler
+            .emit_binop(rtl_parser::RtlBinOp::Add, x, one)
+            .unwrap();
+        jit_compiler.builder.build_return(Some(&sum)).unwrap();
+
+        let mul_type = i128_type.fn_type(&[i128_type.into(), i128_type.into()], false);
+        let mul = jit_compiler.module.add_function("mul", mul_type, None);
+        jit_compiler
+            .builder
+            .position_at_end(context.append_basic_block(mul, "entry"));
+        let (a, b) = (mul.get_nth_param(0).unwrap(), mul.get_nth_param(1).unwrap());
+        let product = jit_compiler
+            .emit_binop(rtl_parser::RtlBinOp::Mul, a, b)
+            .unwrap();
+        jit_compiler.builder.build_return(Some(&product)).unwrap();
+        jit_compiler.verify().unwrap();
+
+        assert_eq!(jit_compiler.run_i128_1("inc", 41).unwrap(), 42);
+        assert_eq!(jit_compiler.run_i128_2("mul", 6, -7).unwrap(), -42);
+        assert!(jit_compiler.run_i128_2("inc", 1, 2).is_err());
+        assert!(jit_compiler.run_i128_1("missing", 1).is_err());
+
+        // A function of another type can't be called as `fn(Int) Int`
+        let f64_type = context.f64_type();
+        let half_type = f64_type.fn_type(&[f64_type.into()], false);
+        let half = jit_compiler.module.add_function("half", half_type, None);
+        jit_compiler
+            .builder
+            .position_at_end(context.append_basic_block(half, "entry"));
+        let x = half.get_nth_param(0).unwrap();
+        jit_compiler.builder.build_return(Some(&x)).unwrap();
+        let err = jit_compiler.run_i128_1("half", 1).unwrap_err();
+        assert!(err.to_string().contains("only Int"), "{}", err);
+    }
 }